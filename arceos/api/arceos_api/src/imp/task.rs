@@ -26,6 +26,10 @@ pub fn ax_exit(_exit_code: i32) -> ! {
 cfg_task! {
     use core::time::Duration;
 
+    pub use axtask::AxCpuMask;
+    pub use axtask::DeadlineParams as AxDeadlineParams;
+    pub use axtask::TaskSnapshot as AxTaskSnapshot;
+
     /// A handle to a task.
     pub struct AxTaskHandle {
         inner: axtask::AxTaskRef,
@@ -39,6 +43,14 @@ cfg_task! {
         }
     }
 
+    /// A handle to a timer.
+    ///
+    /// Timers work only if the feature `irq` is enabled.
+    pub struct AxTimerHandle {
+        #[cfg(feature = "irq")]
+        inner: axtask::timers::Timer,
+    }
+
     /// A handle to a wait queue.
     ///
     /// A wait queue is used to store sleeping tasks waiting for a certain event
@@ -56,21 +68,42 @@ cfg_task! {
         axtask::current().id().as_u64()
     }
 
-    pub fn ax_spawn<F>(f: F, name: alloc::string::String, stack_size: usize) -> AxTaskHandle
+    pub fn ax_spawn<F>(
+        f: F,
+        name: alloc::string::String,
+        stack_size: usize,
+        cpumask: Option<AxCpuMask>,
+    ) -> AxTaskHandle
     where
         F: FnOnce() + Send + 'static,
     {
-        let inner = axtask::spawn_raw(f, name, stack_size);
+        let mut task = axtask::TaskInner::new(f, name, stack_size);
+        if let Some(cpumask) = cpumask {
+            task.set_cpumask(cpumask);
+        }
+        let inner = axtask::spawn_task(task);
         AxTaskHandle {
             id: inner.id().as_u64(),
             inner,
         }
     }
 
+    pub fn ax_get_task(id: u64) -> Option<AxTaskHandle> {
+        axtask::get_task(axtask::TaskId::from_u64(id)).map(|inner| AxTaskHandle { id, inner })
+    }
+
     pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32> {
         task.inner.join()
     }
 
+    pub fn ax_kill_task(task: &AxTaskHandle, exit_code: i32) -> crate::AxResult {
+        if axtask::kill_task(&task.inner, exit_code) {
+            Ok(())
+        } else {
+            axerrno::ax_err!(BadState, "ax_kill_task: the task cannot be killed")
+        }
+    }
+
     pub fn ax_set_current_priority(prio: isize) -> crate::AxResult {
         if axtask::set_priority(prio) {
             Ok(())
@@ -82,6 +115,59 @@ cfg_task! {
         }
     }
 
+    pub fn ax_set_current_affinity(cpumask: AxCpuMask) -> crate::AxResult {
+        if axtask::set_current_affinity(cpumask) {
+            Ok(())
+        } else {
+            axerrno::ax_err!(
+                InvalidInput,
+                "ax_set_current_affinity: empty CPU affinity mask"
+            )
+        }
+    }
+
+    pub fn ax_get_current_affinity() -> AxCpuMask {
+        axtask::current().cpumask()
+    }
+
+    pub fn ax_set_task_affinity(task: &AxTaskHandle, cpumask: AxCpuMask) -> crate::AxResult {
+        if axtask::set_affinity(&task.inner, cpumask) {
+            Ok(())
+        } else {
+            axerrno::ax_err!(InvalidInput, "ax_set_task_affinity: empty CPU affinity mask")
+        }
+    }
+
+    pub fn ax_get_task_affinity(task: &AxTaskHandle) -> AxCpuMask {
+        task.inner.cpumask()
+    }
+
+    pub fn ax_set_current_deadline(params: Option<AxDeadlineParams>) -> crate::AxResult {
+        use axtask::DeadlineError;
+        axtask::set_current_deadline(params).or_else(|e| match e {
+            DeadlineError::Unsupported => axerrno::ax_err!(
+                Unsupported,
+                "ax_set_current_deadline: EDF scheduler is not enabled"
+            ),
+            DeadlineError::InvalidParams => axerrno::ax_err!(
+                InvalidInput,
+                "ax_set_current_deadline: invalid deadline parameters"
+            ),
+            DeadlineError::AdmissionFailed => axerrno::ax_err!(
+                ResourceBusy,
+                "ax_set_current_deadline: not enough CPU bandwidth"
+            ),
+        })
+    }
+
+    pub fn ax_current_deadline_misses() -> u64 {
+        axtask::deadline_misses(axtask::current().as_task_ref())
+    }
+
+    pub fn ax_task_snapshot() -> alloc::vec::Vec<AxTaskSnapshot> {
+        axtask::task_snapshot()
+    }
+
     pub fn ax_wait_queue_wait(
         wq: &AxWaitQueueHandle,
         until_condition: impl Fn() -> bool,
@@ -108,4 +194,49 @@ cfg_task! {
             }
         }
     }
+
+    pub fn ax_timer_new(callback: impl Fn() + Send + Sync + 'static) -> AxTimerHandle {
+        #[cfg(feature = "irq")]
+        return AxTimerHandle {
+            inner: axtask::timers::Timer::new(callback),
+        };
+        #[cfg(not(feature = "irq"))]
+        {
+            drop(callback);
+            AxTimerHandle {}
+        }
+    }
+
+    pub fn ax_timer_arm(
+        timer: &AxTimerHandle,
+        deadline: crate::time::AxTimeValue,
+        period: Option<Duration>,
+    ) -> crate::AxResult {
+        #[cfg(feature = "irq")]
+        {
+            match period {
+                Some(period) if period.is_zero() => {
+                    return axerrno::ax_err!(InvalidInput, "ax_timer_arm: zero timer period")
+                }
+                Some(period) => timer.inner.arm_periodic(period),
+                None => timer.inner.arm_oneshot(deadline),
+            }
+            Ok(())
+        }
+        #[cfg(not(feature = "irq"))]
+        {
+            let _ = (timer, deadline, period);
+            axerrno::ax_err!(Unsupported, "ax_timer_arm: timers need the `irq` feature")
+        }
+    }
+
+    pub fn ax_timer_cancel(timer: &AxTimerHandle) -> bool {
+        #[cfg(feature = "irq")]
+        return timer.inner.cancel();
+        #[cfg(not(feature = "irq"))]
+        {
+            let _ = timer;
+            false
+        }
+    }
 }
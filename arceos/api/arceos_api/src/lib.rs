@@ -121,6 +121,10 @@ pub mod task {
         @cfg "multitask";
         pub type AxTaskHandle;
         pub type AxWaitQueueHandle;
+        pub type AxCpuMask;
+        pub type AxDeadlineParams;
+        pub type AxTaskSnapshot;
+        pub type AxTimerHandle;
     }
 
     define_api! {
@@ -145,16 +149,53 @@ pub mod task {
         /// Returns the current task's ID.
         pub fn ax_current_task_id() -> u64;
         /// Spawns a new task with the given entry point and other arguments.
+        ///
+        /// If `cpumask` is [`None`], the task is allowed to run on all CPUs.
         pub fn ax_spawn(
             f: impl FnOnce() + Send + 'static,
             name: alloc::string::String,
-            stack_size: usize
+            stack_size: usize,
+            cpumask: Option<AxCpuMask>,
         ) -> AxTaskHandle;
+        /// Returns a handle to the live task with the given ID, or [`None`] if
+        /// there is no such task.
+        pub fn ax_get_task(id: u64) -> Option<AxTaskHandle>;
         /// Waits for the given task to exit, and returns its exit code (the
         /// argument of [`ax_exit`]).
         pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32>;
+        /// Kills the given task, which exits with `exit_code` at its next
+        /// cancellation point (yielding or sleeping). It is woken up if it is
+        /// sleeping.
+        ///
+        /// Returns an error if the task is an idle, init or kernel-internal
+        /// task, has exited, or has already been killed.
+        pub fn ax_kill_task(task: &AxTaskHandle, exit_code: i32) -> crate::AxResult;
         /// Sets the priority of the current task.
         pub fn ax_set_current_priority(prio: isize) -> crate::AxResult;
+        /// Sets the CPU affinity of the current task, and migrates it to an
+        /// allowed CPU if necessary.
+        pub fn ax_set_current_affinity(cpumask: AxCpuMask) -> crate::AxResult;
+        /// Returns the CPU affinity of the current task.
+        pub fn ax_get_current_affinity() -> AxCpuMask;
+        /// Sets the CPU affinity of the given task.
+        ///
+        /// The task is migrated to an allowed CPU if necessary.
+        pub fn ax_set_task_affinity(task: &AxTaskHandle, cpumask: AxCpuMask) -> crate::AxResult;
+        /// Returns the CPU affinity of the given task.
+        pub fn ax_get_task_affinity(task: &AxTaskHandle) -> AxCpuMask;
+        /// Makes the current task a real-time task under the EDF scheduler,
+        /// which can run for at most `runtime` in each `period`, and each of
+        /// its jobs should complete within `deadline` after activation.
+        ///
+        /// Passing [`None`] turns it back into a normal task. Only available
+        /// with the `sched_edf` feature.
+        pub fn ax_set_current_deadline(params: Option<AxDeadlineParams>) -> crate::AxResult;
+        /// Returns the number of jobs of the current task that missed their
+        /// deadlines.
+        pub fn ax_current_deadline_misses() -> u64;
+        /// Returns the states and runtime statistics of all live tasks,
+        /// ordered by task IDs.
+        pub fn ax_task_snapshot() -> alloc::vec::Vec<AxTaskSnapshot>;
 
         /// Blocks the current task and put it into the wait queue, until the
         /// given condition becomes true, or the the given duration has elapsed
@@ -169,6 +210,24 @@ pub mod task {
         /// The maximum number of tasks to wake up is specified by `count`. If
         /// `count` is `u32::MAX`, it will wake up all tasks in the wait queue.
         pub fn ax_wait_queue_wake(wq: &AxWaitQueueHandle, count: u32);
+
+        /// Creates a disarmed timer that calls `callback` when it expires.
+        ///
+        /// The callback runs in softirq context, so it must not block. The
+        /// timer is cancelled when the handle is dropped.
+        pub fn ax_timer_new(callback: impl Fn() + Send + Sync + 'static) -> AxTimerHandle;
+        /// Arms the timer to expire once at the given deadline, or every
+        /// `period` starting one period from now if `period` is specified (the
+        /// `deadline` is ignored then).
+        ///
+        /// Returns an error if the feature `irq` is not enabled.
+        pub fn ax_timer_arm(
+            timer: &AxTimerHandle,
+            deadline: crate::time::AxTimeValue,
+            period: Option<core::time::Duration>,
+        ) -> crate::AxResult;
+        /// Cancels the timer, returns `false` if it was not armed.
+        pub fn ax_timer_cancel(timer: &AxTimerHandle) -> bool;
     }
 }
 
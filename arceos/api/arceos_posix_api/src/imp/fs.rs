@@ -116,6 +116,14 @@ pub fn sys_open(filename: *const c_char, flags: c_int, mode: ctypes::mode_t) ->
     })
 }
 
+/// Get the node of the file indicated by `fd`, to map the file to memory.
+///
+/// The file must be opened for reading, and also for writing if `write` is
+/// `true`.
+pub fn get_file_node(fd: c_int, write: bool) -> LinuxResult<axfs::fops::FileNodeRef> {
+    Ok(File::from_fd(fd)?.inner.lock().map_node(write)?)
+}
+
 /// Set the position of the file indicated by `fd`.
 ///
 /// Return its position after seek.
@@ -26,6 +26,8 @@ const CMD_TABLE: &[(&str, CmdHandler)] = &[
     ("mkdir", do_mkdir),
     ("pwd", do_pwd),
     ("rm", do_rm),
+    #[cfg(all(feature = "axstd", feature = "multitask"))]
+    ("top", do_top),
     ("uname", do_uname),
 ];
 
@@ -260,6 +262,65 @@ fn do_uname(_args: &str) {
     );
 }
 
+#[cfg(all(feature = "axstd", feature = "multitask"))]
+fn do_top(args: &str) {
+    use std::time::{Duration, Instant};
+
+    let secs = if args.is_empty() {
+        1
+    } else {
+        match args.parse::<u64>() {
+            Ok(secs) if secs > 0 => secs,
+            _ => {
+                print_err!("top", args, "invalid interval");
+                return;
+            }
+        }
+    };
+
+    // Sample twice to get the CPU usage during the interval.
+    let before = std::thread::snapshot();
+    let start = Instant::now();
+    std::thread::sleep(Duration::from_secs(secs));
+    let after = std::thread::snapshot();
+    let elapsed = start.elapsed().as_nanos().max(1);
+
+    let mut rows = after
+        .iter()
+        .map(|t| {
+            let prev_time = before
+                .iter()
+                .find(|p| p.id == t.id)
+                .map_or(Duration::ZERO, |p| p.stats.cpu_time);
+            (t, t.stats.cpu_time.saturating_sub(prev_time))
+        })
+        .collect::<Vec<_>>();
+    rows.sort_by(|a, b| b.1.cmp(&a.1));
+
+    println!(
+        "{:>5} {:<16} {:<8} {:>3} {:>4} {:>6} {:>12} {:>8} {:>8}",
+        "TID", "NAME", "STATE", "CPU", "PRI", "%CPU", "TIME", "VCSW", "ICSW"
+    );
+    for (t, delta) in rows {
+        let permille = delta.as_nanos() * 1000 / elapsed;
+        let time = t.stats.cpu_time;
+        println!(
+            "{:>5} {:<16} {:<8} {:>3} {:>4} {:>4}.{} {:>8}.{:03} {:>8} {:>8}",
+            t.id.as_u64(),
+            t.name,
+            std::format!("{:?}", t.state),
+            t.cpu_id,
+            t.priority,
+            permille / 10,
+            permille % 10,
+            time.as_secs(),
+            time.subsec_millis(),
+            t.stats.voluntary_switches,
+            t.stats.involuntary_switches,
+        );
+    }
+}
+
 fn do_help(_args: &str) {
     println!("Available commands:");
     for (name, _) in CMD_TABLE {
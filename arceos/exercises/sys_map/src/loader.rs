@@ -8,7 +8,7 @@ use axhal::paging::MappingFlags;
 use axhal::mem::{PAGE_SIZE_4K, VirtAddr, MemoryAddr};
 use axmm::AddrSpace;
 
-use elf::abi::{PT_INTERP, PT_LOAD};
+use elf::abi::{ET_DYN, PT_INTERP, PT_LOAD};
 use elf::endian::AnyEndian;
 use elf::parse::ParseAt;
 use elf::segment::ProgramHeader;
@@ -19,7 +19,14 @@ const ELF_HEAD_BUF_SIZE: usize = 256;
 
 pub fn load_user_app(fname: &str, uspace: &mut AddrSpace) -> io::Result<usize> {
     let mut file = File::open(fname)?;
-    let (phdrs, entry, _, _) = load_elf_phdrs(&mut file)?;
+    let (phdrs, entry, _, _, e_type) = load_elf_phdrs(&mut file)?;
+    // Position-independent executables are loaded at the random load bias
+    // with the `aslr` feature, and at their link addresses otherwise.
+    let bias = if cfg!(feature = "aslr") && e_type == ET_DYN {
+        uspace.layout().load_bias
+    } else {
+        0
+    };
 
     for phdr in &phdrs {
         ax_println!(
@@ -27,8 +34,8 @@ pub fn load_user_app(fname: &str, uspace: &mut AddrSpace) -> io::Result<usize> {
             phdr.p_offset, phdr.p_vaddr, phdr.p_filesz, phdr.p_memsz
         );
 
-        let vaddr = VirtAddr::from(phdr.p_vaddr as usize).align_down_4k();
-        let vaddr_end = VirtAddr::from((phdr.p_vaddr+phdr.p_memsz) as usize)
+        let vaddr = VirtAddr::from(phdr.p_vaddr as usize + bias).align_down_4k();
+        let vaddr_end = VirtAddr::from((phdr.p_vaddr+phdr.p_memsz) as usize + bias)
             .align_up_4k();
 
         ax_println!("{:#x} - {:#x}", vaddr, vaddr_end);
@@ -44,13 +51,13 @@ pub fn load_user_app(fname: &str, uspace: &mut AddrSpace) -> io::Result<usize> {
             index += n;
         }
         assert_eq!(index, filesz);
-        uspace.write(VirtAddr::from(phdr.p_vaddr as usize), &data)?;
+        uspace.write(VirtAddr::from(phdr.p_vaddr as usize + bias), &data)?;
     }
 
-    Ok(entry)
+    Ok(entry + bias)
 }
 
-fn load_elf_phdrs(file: &mut File) -> io::Result<(Vec<ProgramHeader>, usize, usize, usize)> {
+fn load_elf_phdrs(file: &mut File) -> io::Result<(Vec<ProgramHeader>, usize, usize, usize, u16)> {
     let mut buf: [u8; ELF_HEAD_BUF_SIZE] = [0; ELF_HEAD_BUF_SIZE];
     file.read(&mut buf)?;
 
@@ -72,5 +79,5 @@ fn load_elf_phdrs(file: &mut File) -> io::Result<(Vec<ProgramHeader>, usize, usi
         .iter()
         .filter(|phdr| phdr.p_type == PT_LOAD || phdr.p_type == PT_INTERP)
         .collect();
-    Ok((phdrs, ehdr.e_entry as usize, ehdr.e_phoff as usize, ehdr.e_phnum as usize))
+    Ok((phdrs, ehdr.e_entry as usize, ehdr.e_phoff as usize, ehdr.e_phnum as usize, ehdr.e_type))
 }
@@ -56,7 +56,7 @@ fn main() {
 }
 
 fn init_user_stack(uspace: &mut AddrSpace, populating: bool) -> io::Result<VirtAddr> {
-    let ustack_top = uspace.end();
+    let ustack_top = uspace.layout().stack_top;
     let ustack_vaddr = ustack_top - crate::USER_STACK_SIZE;
     ax_println!(
         "Mapping user stack: {:#x?} -> {:#x?}",
@@ -7,9 +7,9 @@ use axerrno::LinuxError;
 use axtask::current;
 use axtask::TaskExtRef;
 use axhal::paging::MappingFlags;
-use arceos_posix_api::{self as api, get_file_like};
+use arceos_posix_api::{self as api, get_file_node};
 use memory_addr::{MemoryAddr, VirtAddr, VirtAddrRange};
-use alloc::vec;
+use axmm::SharedMemory;
 
 const SYS_IOCTL: usize = 29;
 const SYS_OPENAT: usize = 56;
@@ -20,7 +20,9 @@ const SYS_WRITEV: usize = 66;
 const SYS_EXIT: usize = 93;
 const SYS_EXIT_GROUP: usize = 94;
 const SYS_SET_TID_ADDRESS: usize = 96;
+const SYS_MUNMAP: usize = 215;
 const SYS_MMAP: usize = 222;
+const SYS_MSYNC: usize = 227;
 
 const AT_FDCWD: i32 = -100;
 
@@ -125,6 +127,8 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg4() as _,
             tf.arg5() as _,
         ),
+        SYS_MUNMAP => sys_munmap(tf.arg0() as _, tf.arg1() as _),
+        SYS_MSYNC => sys_msync(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         _ => {
             ax_println!("Unimplemented syscall: {}", syscall_num);
             -LinuxError::ENOSYS.code() as _
@@ -140,28 +144,73 @@ fn sys_mmap(
     prot: i32,
     flags: i32,
     fd: i32,
-    _offset: isize,
+    offset: isize,
 ) -> isize {
-    debug!("sys_mmap: addr: {:#x}, length: {}, prot: {}, flags: {}, fd: {}, _offset: {}", addr as usize, length, prot, flags, fd, _offset);
-    let binding = current();
-    let mut uspace = binding.task_ext().aspace.lock();
-    let length = length.align_up_4k();
-    if let Some(vaddr) = uspace.find_free_area(
-        VirtAddr::from(addr as usize),
-        length,
-        VirtAddrRange::from_start_size(uspace.base(), uspace.size())) {
-            if let Ok(_) = uspace.map_alloc(vaddr, length, MappingFlags::from(MmapProt::from_bits(prot).unwrap()), true) {
-                if let Ok(file) = get_file_like(fd) {
-                    let mut buf = vec![0u8; length];
-                    if let Ok(_) = file.read(&mut buf) {
-                        if let Ok(_) = uspace.write(vaddr, &buf) {
-                            return vaddr.as_usize() as isize;
-                        }
-                    }
-                }
-            }
-    }
-    -1
+    debug!("sys_mmap: addr: {:#x}, length: {}, prot: {}, flags: {}, fd: {}, offset: {}", addr as usize, length, prot, flags, fd, offset);
+    syscall_body!(sys_mmap, {
+        let prot = MmapProt::from_bits(prot).ok_or(LinuxError::EINVAL)?;
+        let flags = MmapFlags::from_bits_truncate(flags);
+        if length == 0 || offset < 0 || !(offset as usize).is_aligned_4k() {
+            return Err(LinuxError::EINVAL);
+        }
+        let binding = current();
+        let mut uspace = binding.task_ext().aspace.lock();
+        let length = length.align_up_4k();
+        // Without a hint, search from the (random) base of memory mappings.
+        let hint = if addr.is_null() {
+            uspace.layout().mmap_base
+        } else {
+            VirtAddr::from(addr as usize)
+        };
+        let vaddr = uspace
+            .find_free_area(
+                hint,
+                length,
+                VirtAddrRange::from_start_size(uspace.base(), uspace.size()),
+            )
+            .ok_or(LinuxError::ENOMEM)?;
+        let map_flags = MappingFlags::from(prot);
+        if flags.contains(MmapFlags::MAP_ANONYMOUS | MmapFlags::MAP_SHARED) {
+            // Shared with the address spaces cloned from this one.
+            let shm = SharedMemory::new(length)?;
+            uspace.map_shared(vaddr, map_flags, shm)?;
+        } else if flags.contains(MmapFlags::MAP_ANONYMOUS) {
+            uspace.map_alloc(vaddr, length, map_flags, false)?;
+        } else {
+            // Pages of the file are read on demand, and written back on
+            // `munmap` or `msync` if the mapping is shared.
+            let shared = flags.contains(MmapFlags::MAP_SHARED);
+            let node = get_file_node(fd, shared && prot.contains(MmapProt::PROT_WRITE))?;
+            uspace.map_file(vaddr, length, map_flags, node, offset as u64, shared)?;
+        }
+        Ok(vaddr.as_usize())
+    })
+}
+
+fn sys_munmap(addr: *mut usize, length: usize) -> isize {
+    syscall_body!(sys_munmap, {
+        let vaddr = VirtAddr::from(addr as usize);
+        if !vaddr.is_aligned_4k() || length == 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let binding = current();
+        let mut uspace = binding.task_ext().aspace.lock();
+        uspace.unmap(vaddr, length.align_up_4k())?;
+        Ok(0)
+    })
+}
+
+fn sys_msync(addr: *mut usize, length: usize, _flags: i32) -> isize {
+    syscall_body!(sys_msync, {
+        let vaddr = VirtAddr::from(addr as usize);
+        if !vaddr.is_aligned_4k() {
+            return Err(LinuxError::EINVAL);
+        }
+        let binding = current();
+        let mut uspace = binding.task_ext().aspace.lock();
+        uspace.msync(vaddr, length.align_up_4k())?;
+        Ok(0)
+    })
 }
 
 fn sys_openat(dfd: c_int, fname: *const c_char, flags: c_int, mode: api::ctypes::mode_t) -> isize {
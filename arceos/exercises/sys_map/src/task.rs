@@ -64,8 +64,12 @@ pub fn spawn_user_task(aspace: Arc<Mutex<AddrSpace>>, uctx: UspaceContext) -> Ax
         "userboot".into(),
         crate::KERNEL_STACK_SIZE,
     );
-    task.ctx_mut()
-        .set_page_table_root(aspace.lock().page_table_root());
+    let (root, asid) = {
+        let aspace = aspace.lock();
+        (aspace.page_table_root(), aspace.asid().clone())
+    };
+    task.ctx_mut().set_page_table_root(root);
+    task.ctx_mut().set_asid(asid);
     task.init_task_ext(TaskExt::new(uctx, aspace));
     axtask::spawn_task(task)
 }
@@ -19,6 +19,8 @@ pub type DirEntry = axfs_vfs::VfsDirEntry;
 pub type FileAttr = axfs_vfs::VfsNodeAttr;
 /// Alias of [`axfs_vfs::VfsNodePerm`].
 pub type FilePerm = axfs_vfs::VfsNodePerm;
+/// Alias of [`axfs_vfs::VfsNodeRef`].
+pub type FileNodeRef = axfs_vfs::VfsNodeRef;
 
 /// An opened file object, with open permissions and a cursor.
 pub struct File {
@@ -246,6 +248,19 @@ impl File {
     pub fn get_attr(&self) -> AxResult<FileAttr> {
         self.access_node(Cap::empty())?.get_attr()
     }
+
+    /// Returns the underlying node of the file, to map the file to memory.
+    ///
+    /// The file must be opened for reading, and also for writing if `write`
+    /// is `true`.
+    pub fn map_node(&self, write: bool) -> AxResult<FileNodeRef> {
+        let cap = if write {
+            Cap::READ | Cap::WRITE
+        } else {
+            Cap::READ
+        };
+        Ok(self.access_node(cap)?.clone())
+    }
 }
 
 impl Directory {
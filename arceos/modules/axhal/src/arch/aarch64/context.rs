@@ -1,6 +1,14 @@
 use core::arch::asm;
 use memory_addr::VirtAddr;
 
+#[cfg(feature = "uspace")]
+use alloc::sync::Arc;
+#[cfg(feature = "uspace")]
+use memory_addr::PhysAddr;
+
+#[cfg(feature = "uspace")]
+use crate::paging::Asid;
+
 /// Saved registers when a trap (exception) occurs.
 #[repr(C)]
 #[derive(Debug, Default, Clone, Copy)]
@@ -65,6 +73,13 @@ pub struct TaskContext {
     pub lr: u64, // r30
     #[cfg(feature = "fp_simd")]
     pub fp_state: FpState,
+    /// The `TTBR0_EL1` register value, i.e., the page table root of the user
+    /// space. Zero means no user page table.
+    #[cfg(feature = "uspace")]
+    pub ttbr0_el1: PhysAddr,
+    /// The ASID of the page table, which is 0 if not set.
+    #[cfg(feature = "uspace")]
+    pub asid: Option<Arc<Asid>>,
 }
 
 impl TaskContext {
@@ -81,6 +96,25 @@ impl TaskContext {
         self.tpidr_el0 = tls_area.as_usize() as u64;
     }
 
+    /// Changes the user page table root (`TTBR0_EL1` register for aarch64).
+    ///
+    /// The kernel page table in `TTBR1_EL1` is shared by all tasks.
+    #[cfg(feature = "uspace")]
+    pub fn set_page_table_root(&mut self, ttbr0_el1: PhysAddr) {
+        self.ttbr0_el1 = ttbr0_el1;
+    }
+
+    /// Sets the ASID of the page table, so that the TLB is not flushed on
+    /// switching to this task.
+    ///
+    /// The ASID must be owned by the page table set by
+    /// [`set_page_table_root`](TaskContext::set_page_table_root), i.e., no
+    /// other page tables use it.
+    #[cfg(feature = "uspace")]
+    pub fn set_asid(&mut self, asid: Arc<Asid>) {
+        self.asid = Some(asid);
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
@@ -88,6 +122,12 @@ impl TaskContext {
     pub fn switch_to(&mut self, next_ctx: &Self) {
         #[cfg(feature = "fp_simd")]
         self.fp_state.switch_to(&next_ctx.fp_state);
+        #[cfg(feature = "uspace")]
+        unsafe {
+            if self.ttbr0_el1 != next_ctx.ttbr0_el1 {
+                crate::paging::switch_page_table(next_ctx.ttbr0_el1, next_ctx.asid.as_deref());
+            }
+        }
         unsafe { context_switch(self, next_ctx) }
     }
 }
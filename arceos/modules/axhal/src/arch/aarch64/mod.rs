@@ -35,6 +35,18 @@ pub fn wait_for_irqs() {
     aarch64_cpu::asm::wfi();
 }
 
+/// Enables interrupts and waits for the next one atomically.
+///
+/// It is called with interrupts disabled after checking that there is nothing
+/// to do, so that an interrupt arriving after the check still wakes it up.
+#[inline]
+pub fn enable_irqs_and_wait() {
+    // `wfi` wakes up on pending interrupts even if they are masked by
+    // `DAIF.I`, which are taken after enabling interrupts.
+    aarch64_cpu::asm::wfi();
+    enable_irqs();
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -53,7 +65,8 @@ pub fn read_page_table_root() -> PhysAddr {
 
 /// Reads the `TTBR0_EL1` register.
 pub fn read_page_table_root0() -> PhysAddr {
-    let root = TTBR0_EL1.get();
+    // The bits above 48 are the ASID.
+    let root = TTBR0_EL1.get() & ((1 << 48) - 1);
     pa!(root as usize)
 }
 
@@ -82,6 +95,26 @@ pub unsafe fn write_page_table_root0(root_paddr: PhysAddr) {
     flush_tlb(None);
 }
 
+/// Returns the number of ASID bits used, which is 8 as `TCR_EL1.AS` is not
+/// set.
+#[cfg(feature = "paging")]
+pub(crate) fn asid_bits() -> u32 {
+    8
+}
+
+/// Writes the page table root and the ASID to `TTBR0_EL1`, i.e., for the user
+/// space. The TLB entries of ASID 0 are flushed, as it is shared by the page
+/// tables without ASIDs.
+#[cfg(feature = "paging")]
+pub(crate) unsafe fn write_page_table_root_asid(root_paddr: PhysAddr, asid: usize) {
+    TTBR0_EL1.set((root_paddr.as_usize() | asid << 48) as _);
+    if asid == 0 {
+        asm!("tlbi aside1, xzr; dsb sy; isb");
+    } else {
+        asm!("isb");
+    }
+}
+
 /// Flushes the TLB.
 ///
 /// If `vaddr` is [`None`], flushes the entire TLB. Otherwise, flushes the TLB
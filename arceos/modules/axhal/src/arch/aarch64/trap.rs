@@ -37,8 +37,8 @@ fn invalid_exception(tf: &TrapFrame, kind: TrapKind, source: TrapSource) {
 }
 
 #[no_mangle]
-fn handle_irq_exception(_tf: &TrapFrame) {
-    handle_trap!(IRQ, 0);
+fn handle_irq_exception(tf: &TrapFrame) {
+    crate::trap::handle_irq_trap(tf, 0);
 }
 
 fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
@@ -48,10 +48,13 @@ fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     }
     let vaddr = va!(FAR_EL1.get() as usize);
 
-    // Only handle Translation fault and Permission fault
-    if !matches!(iss & 0b111100, 0b0100 | 0b1100) // IFSC or DFSC bits
+    // Only handle Translation fault, Access flag fault and Permission fault
+    if !matches!(iss & 0b111100, 0b0100 | 0b1000 | 0b1100) // IFSC or DFSC bits
         || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
     {
+        if !is_user {
+            crate::trap::check_stack_guard(vaddr);
+        }
         panic!(
             "Unhandled {} Instruction Abort @ {:#x}, fault_vaddr={:#x}, ISS={:#x} ({:?}):\n{:#x?}",
             if is_user { "EL0" } else { "EL1" },
@@ -77,10 +80,13 @@ fn handle_data_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     }
     let vaddr = va!(FAR_EL1.get() as usize);
 
-    // Only handle Translation fault and Permission fault
-    if !matches!(iss & 0b111100, 0b0100 | 0b1100) // IFSC or DFSC bits
+    // Only handle Translation fault, Access flag fault and Permission fault
+    if !matches!(iss & 0b111100, 0b0100 | 0b1000 | 0b1100) // IFSC or DFSC bits
         || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
     {
+        if !is_user {
+            crate::trap::check_stack_guard(vaddr);
+        }
         panic!(
             "Unhandled {} Data Abort @ {:#x}, fault_vaddr={:#x}, ISS=0b{:08b} ({:?}):\n{:#x?}",
             if is_user { "EL0" } else { "EL1" },
@@ -1,8 +1,13 @@
 use core::arch::asm;
 use memory_addr::VirtAddr;
 #[cfg(feature = "uspace")]
+use alloc::sync::Arc;
+#[cfg(feature = "uspace")]
 use memory_addr::PhysAddr;
 
+#[cfg(feature = "uspace")]
+use crate::paging::Asid;
+
 include_asm_marcos!();
 
 /// General registers of RISC-V.
@@ -123,6 +128,9 @@ pub struct TaskContext {
     /// The `satp` register value, i.e., the page table root.
     #[cfg(feature = "uspace")]
     pub satp: PhysAddr,
+    /// The ASID of the page table, which is 0 if not set.
+    #[cfg(feature = "uspace")]
+    pub asid: Option<Arc<Asid>>,
     // TODO: FP states
 }
 
@@ -161,6 +169,17 @@ impl TaskContext {
         self.satp = satp;
     }
 
+    /// Sets the ASID of the page table, so that the TLB is not flushed on
+    /// switching to this task.
+    ///
+    /// The ASID must be owned by the page table set by
+    /// [`set_page_table_root`](TaskContext::set_page_table_root), i.e., no
+    /// other page tables use it.
+    #[cfg(feature = "uspace")]
+    pub fn set_asid(&mut self, asid: Arc<Asid>) {
+        self.asid = Some(asid);
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
@@ -174,7 +193,7 @@ impl TaskContext {
         #[cfg(feature = "uspace")]
         unsafe {
             if self.satp != next_ctx.satp {
-                super::write_page_table_root(next_ctx.satp);
+                crate::paging::switch_page_table(next_ctx.satp, next_ctx.asid.as_deref());
             }
         }
         unsafe {
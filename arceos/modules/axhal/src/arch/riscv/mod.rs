@@ -38,6 +38,18 @@ pub fn wait_for_irqs() {
     riscv::asm::wfi()
 }
 
+/// Enables interrupts and waits for the next one atomically.
+///
+/// It is called with interrupts disabled after checking that there is nothing
+/// to do, so that an interrupt arriving after the check still wakes it up.
+#[inline]
+pub fn enable_irqs_and_wait() {
+    // `wfi` wakes up on pending interrupts even if they are masked by
+    // `sstatus.SIE`, which are taken after enabling interrupts.
+    riscv::asm::wfi();
+    enable_irqs();
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -67,6 +79,34 @@ pub unsafe fn write_page_table_root(root_paddr: PhysAddr) {
     }
 }
 
+/// Returns the number of ASID bits supported by the hardware, by writing ones
+/// to the ASID field of `satp` and reading it back.
+#[cfg(feature = "paging")]
+pub(crate) fn asid_bits() -> u32 {
+    const ASID_MASK: usize = 0xffff << 44;
+    let _guard = kernel_guard::IrqSave::new();
+    let old = satp::read().bits();
+    let asid = unsafe {
+        core::arch::asm!("csrw satp, {}", in(reg) old | ASID_MASK);
+        let asid = satp::read().bits() & ASID_MASK;
+        core::arch::asm!("csrw satp, {}", in(reg) old);
+        asm::sfence_vma_all();
+        asid
+    };
+    asid.count_ones()
+}
+
+/// Writes the page table root and the ASID to `satp`. The TLB entries of ASID
+/// 0 are flushed, as it is shared by the page tables without ASIDs.
+#[cfg(feature = "paging")]
+pub(crate) unsafe fn write_page_table_root_asid(root_paddr: PhysAddr, asid: usize) {
+    satp::set(satp::Mode::Sv39, asid, root_paddr.as_usize() >> 12);
+    if asid == 0 {
+        // `sfence.vma x0, rs2` flushes all entries of the ASID in `rs2`.
+        core::arch::asm!("sfence.vma zero, {}", in(reg) 0usize);
+    }
+}
+
 /// Flushes the TLB.
 ///
 /// If `vaddr` is [`None`], flushes the entire TLB. Otherwise, flushes the TLB
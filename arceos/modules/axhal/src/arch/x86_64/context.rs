@@ -1,6 +1,14 @@
 use core::{arch::asm, fmt};
 use memory_addr::VirtAddr;
 
+#[cfg(feature = "uspace")]
+use alloc::sync::Arc;
+#[cfg(feature = "uspace")]
+use memory_addr::PhysAddr;
+
+#[cfg(feature = "uspace")]
+use crate::paging::Asid;
+
 /// Saved registers when a trap (interrupt or exception) occurs.
 #[allow(missing_docs)]
 #[repr(C)]
@@ -141,6 +149,13 @@ pub struct TaskContext {
     /// Extended states, i.e., FP/SIMD states.
     #[cfg(feature = "fp_simd")]
     pub ext_state: ExtendedState,
+    /// The `CR3` register value, i.e., the page table root. Zero means the
+    /// kernel page table.
+    #[cfg(feature = "uspace")]
+    pub cr3: PhysAddr,
+    /// The PCID of the page table, which is 0 if not set.
+    #[cfg(feature = "uspace")]
+    pub asid: Option<Arc<Asid>>,
 }
 
 impl TaskContext {
@@ -152,6 +167,10 @@ impl TaskContext {
             fs_base: 0,
             #[cfg(feature = "fp_simd")]
             ext_state: ExtendedState::default(),
+            #[cfg(feature = "uspace")]
+            cr3: pa!(0),
+            #[cfg(feature = "uspace")]
+            asid: None,
         }
     }
 
@@ -177,6 +196,28 @@ impl TaskContext {
         self.fs_base = tls_area.as_usize();
     }
 
+    /// Changes the page table root (`CR3` register for x86_64).
+    ///
+    /// If not set, the kernel page table root is used (obtained by
+    /// [`axhal::paging::kernel_page_table_root`][1]).
+    ///
+    /// [1]: crate::paging::kernel_page_table_root
+    #[cfg(feature = "uspace")]
+    pub fn set_page_table_root(&mut self, cr3: PhysAddr) {
+        self.cr3 = cr3;
+    }
+
+    /// Sets the ASID (PCID on x86_64) of the page table, so that the TLB is
+    /// not flushed on switching to this task.
+    ///
+    /// The ASID must be owned by the page table set by
+    /// [`set_page_table_root`](TaskContext::set_page_table_root), i.e., no
+    /// other page tables use it.
+    #[cfg(feature = "uspace")]
+    pub fn set_asid(&mut self, asid: Arc<Asid>) {
+        self.asid = Some(asid);
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
@@ -192,6 +233,16 @@ impl TaskContext {
             self.fs_base = super::read_thread_pointer();
             unsafe { super::write_thread_pointer(next_ctx.fs_base) };
         }
+        #[cfg(feature = "uspace")]
+        unsafe {
+            if self.cr3 != next_ctx.cr3 {
+                let root = match next_ctx.cr3.as_usize() {
+                    0 => crate::paging::kernel_page_table_root(),
+                    _ => next_ctx.cr3,
+                };
+                crate::paging::switch_page_table(root, next_ctx.asid.as_deref());
+            }
+        }
         unsafe { context_switch(&mut self.rsp, &next_ctx.rsp) }
     }
 }
@@ -10,10 +10,11 @@ use core::arch::asm;
 use memory_addr::{MemoryAddr, PhysAddr, VirtAddr};
 use x86::{controlregs, msr, tlb};
 use x86_64::instructions::interrupts;
+use x86_64::registers::control::{Cr4, Cr4Flags};
 
 pub use self::context::{ExtendedState, FxsaveArea, TaskContext, TrapFrame};
 pub use self::gdt::GdtStruct;
-pub use self::idt::IdtStruct;
+pub use self::idt::{IdtStruct, DOUBLE_FAULT_IST_INDEX};
 pub use x86_64::structures::tss::TaskStateSegment;
 
 /// Allows the current CPU to respond to interrupts.
@@ -48,6 +49,20 @@ pub fn wait_for_irqs() {
     }
 }
 
+/// Enables interrupts and waits for the next one atomically.
+///
+/// It is called with interrupts disabled after checking that there is nothing
+/// to do, so that an interrupt arriving after the check still wakes it up.
+#[inline]
+pub fn enable_irqs_and_wait() {
+    if cfg!(target_os = "none") {
+        // Interrupts are not taken until the instruction after `sti`.
+        unsafe { asm!("sti; hlt") }
+    } else {
+        core::hint::spin_loop()
+    }
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -76,6 +91,36 @@ pub unsafe fn write_page_table_root(root_paddr: PhysAddr) {
     }
 }
 
+/// Enables PCIDs (process-context identifiers) on the current CPU if they are
+/// supported, which are used as the ASIDs of page tables.
+#[cfg(feature = "paging")]
+pub(crate) fn enable_pcid() {
+    let cpuid = raw_cpuid::CpuId::new();
+    if cpuid.get_feature_info().is_some_and(|f| f.has_pcid()) {
+        unsafe { Cr4::update(|f| f.insert(Cr4Flags::PCID)) };
+    }
+}
+
+/// Returns the number of ASID bits supported, i.e., 12 for PCIDs, or 0 if
+/// PCIDs are not enabled.
+#[cfg(feature = "paging")]
+pub(crate) fn asid_bits() -> u32 {
+    if Cr4::read().contains(Cr4Flags::PCID) {
+        12
+    } else {
+        0
+    }
+}
+
+/// Writes the page table root and the PCID to `CR3`. The TLB is not flushed
+/// unless the PCID is 0, as it is shared by the page tables without PCIDs.
+#[cfg(feature = "paging")]
+pub(crate) unsafe fn write_page_table_root_asid(root_paddr: PhysAddr, asid: usize) {
+    const CR3_NOFLUSH: usize = 1 << 63;
+    let noflush = if asid != 0 { CR3_NOFLUSH } else { 0 };
+    controlregs::cr3_write((root_paddr.as_usize() | asid | noflush) as _)
+}
+
 /// Flushes the TLB.
 ///
 /// If `vaddr` is [`None`], flushes the entire TLB. Otherwise, flushes the TLB
@@ -84,6 +129,14 @@ pub unsafe fn write_page_table_root(root_paddr: PhysAddr) {
 pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     if let Some(vaddr) = vaddr {
         unsafe { tlb::flush(vaddr.into()) }
+    } else if Cr4::read().contains(Cr4Flags::PCID) {
+        // Reloading `CR3` only flushes the current PCID, while toggling
+        // `CR4.PGE` flushes all PCIDs (and global pages).
+        let cr4 = Cr4::read();
+        unsafe {
+            Cr4::write(cr4 - Cr4Flags::PAGE_GLOBAL);
+            Cr4::write(cr4);
+        }
     } else {
         unsafe { tlb::flush_all() }
     }
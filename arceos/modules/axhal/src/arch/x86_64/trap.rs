@@ -14,6 +14,9 @@ fn handle_page_fault(tf: &TrapFrame) {
         .unwrap_or_else(|e| panic!("Invalid #PF error code: {:#x}", e));
     let vaddr = va!(unsafe { cr2() });
     if !handle_trap!(PAGE_FAULT, vaddr, access_flags, tf.is_user()) {
+        if !tf.is_user() {
+            crate::trap::check_stack_guard(vaddr);
+        }
         panic!(
             "Unhandled {} #PF @ {:#x}, fault_vaddr={:#x}, error_code={:#x} ({:?}):\n{:#x?}",
             if tf.is_user() { "user" } else { "kernel" },
@@ -31,6 +34,15 @@ fn x86_trap_handler(tf: &TrapFrame) {
     match tf.vector as u8 {
         PAGE_FAULT_VECTOR => handle_page_fault(tf),
         BREAKPOINT_VECTOR => debug!("#BP @ {:#x} ", tf.rip),
+        #[cfg(feature = "irq")]
+        NONMASKABLE_INTERRUPT_VECTOR => crate::trap::handle_nmi_trap(tf),
+        DOUBLE_FAULT_VECTOR => {
+            // Usually a page fault that cannot be delivered on the current
+            // stack, i.e., a kernel stack overflow. We are on a dedicated
+            // stack now, see `DOUBLE_FAULT_IST_INDEX`.
+            crate::trap::check_stack_guard(va!(unsafe { cr2() }));
+            panic!("#DF @ {:#x}:\n{:#x?}", tf.rip, tf);
+        }
         GENERAL_PROTECTION_FAULT_VECTOR => {
             panic!(
                 "#GP @ {:#x}, error_code={:#x}:\n{:#x?}",
@@ -38,7 +50,7 @@ fn x86_trap_handler(tf: &TrapFrame) {
             );
         }
         IRQ_VECTOR_START..=IRQ_VECTOR_END => {
-            handle_trap!(IRQ, tf.vector as _);
+            crate::trap::handle_irq_trap(tf, tf.vector as _);
         }
         _ => {
             panic!(
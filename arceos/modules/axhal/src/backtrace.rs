@@ -0,0 +1,105 @@
+//! Stack backtraces by walking frame pointers.
+//!
+//! Frame pointers are only maintained if the kernel is built with
+//! `-C force-frame-pointers=yes`, otherwise backtraces stop early or contain
+//! bogus addresses.
+
+use core::fmt;
+
+use crate::arch::TrapFrame;
+
+/// The maximum number of frames in a backtrace.
+const MAX_FRAMES: usize = 32;
+
+/// The maximum distance between the first and the last frame walked, which
+/// stops walking garbage frame pointers far away from the stack.
+const MAX_STACK_SPAN: usize = 0x10_0000; // 1 MiB
+
+/// A stack backtrace: the program counter followed by the return addresses of
+/// the callers.
+pub struct Backtrace {
+    pcs: [usize; MAX_FRAMES],
+    len: usize,
+}
+
+impl Backtrace {
+    /// Walks the stack of a context from its program counter and frame
+    /// pointer.
+    ///
+    /// # Safety
+    ///
+    /// The frame pointer chain must be readable, e.g., the context is
+    /// suspended on a kernel stack that is still alive.
+    pub unsafe fn from_fp(pc: usize, fp: usize) -> Self {
+        let mut bt = Self {
+            pcs: [0; MAX_FRAMES],
+            len: 1,
+        };
+        bt.pcs[0] = pc;
+
+        let kernel_aspace = axconfig::KERNEL_ASPACE_BASE
+            ..axconfig::KERNEL_ASPACE_BASE + axconfig::KERNEL_ASPACE_SIZE;
+        let mut fp = fp;
+        let start = fp;
+        while bt.len < MAX_FRAMES
+            && kernel_aspace.contains(&fp)
+            && fp % core::mem::align_of::<usize>() == 0
+            && fp - start < MAX_STACK_SPAN
+        {
+            let (next_fp, ra) = read_frame_record(fp);
+            if ra == 0 {
+                break;
+            }
+            bt.pcs[bt.len] = ra;
+            bt.len += 1;
+            if next_fp <= fp {
+                break;
+            }
+            fp = next_fp;
+        }
+        bt
+    }
+
+    /// Walks the stack of the kernel context saved in a trap frame.
+    ///
+    /// # Safety
+    ///
+    /// The trap frame must be taken from kernel mode, and the interrupted
+    /// stack must be still alive.
+    pub unsafe fn from_trap_frame(tf: &TrapFrame) -> Self {
+        #[cfg(target_arch = "x86_64")]
+        let (pc, fp) = (tf.rip as usize, tf.rbp as usize);
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        let (pc, fp) = (tf.sepc, tf.regs.s0);
+        #[cfg(target_arch = "aarch64")]
+        let (pc, fp) = (tf.elr as usize, tf.r[29] as usize);
+        Self::from_fp(pc, fp)
+    }
+
+    /// Returns the program counter and the return addresses.
+    pub fn frames(&self) -> &[usize] {
+        &self.pcs[..self.len]
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, pc) in self.frames().iter().enumerate() {
+            writeln!(f, "  #{:<2} {:#x}", i, pc)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the saved frame pointer and the return address of the frame at `fp`.
+unsafe fn read_frame_record(fp: usize) -> (usize, usize) {
+    let fp = fp as *const usize;
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+            // The frame record is just below the frame pointer.
+            (fp.sub(2).read(), fp.sub(1).read())
+        } else {
+            (fp.read(), fp.add(1).read())
+        }
+    }
+}
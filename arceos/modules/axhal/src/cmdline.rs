@@ -0,0 +1,118 @@
+//! The kernel command line.
+//!
+//! It is passed by the bootloader, e.g., by the `-append` option of QEMU, in
+//! the `/chosen/bootargs` property of the device tree, or in the multiboot
+//! information on x86. It is copied on boot, as the memory holding it may be
+//! reused later.
+//!
+//! The command line consists of whitespace-separated arguments of the form
+//! `key=value` or `key`.
+
+use core::ffi::CStr;
+
+use crate::mem::phys_to_virt;
+
+/// The maximum length of the command line. The rest is truncated.
+const MAX_CMDLINE_LEN: usize = 256;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+/// Set in the `flags` of the multiboot information if `cmdline` is valid.
+const MULTIBOOT_INFO_CMDLINE: u32 = 1 << 2;
+
+static mut CMDLINE: [u8; MAX_CMDLINE_LEN] = [0; MAX_CMDLINE_LEN];
+static mut CMDLINE_LEN: usize = 0;
+
+/// Returns the kernel command line, or an empty string if there is none.
+pub fn cmdline() -> &'static str {
+    // Safety: it is only written once on boot, before other CPUs start.
+    let bytes = unsafe { &*core::ptr::addr_of!(CMDLINE) };
+    core::str::from_utf8(&bytes[..unsafe { CMDLINE_LEN }]).unwrap_or_default()
+}
+
+/// Returns the value of the argument `key` in the command line, or an empty
+/// string if it has no value. If there are multiple ones, the last one is
+/// returned.
+pub fn get(key: &str) -> Option<&'static str> {
+    cmdline()
+        .split_ascii_whitespace()
+        .filter_map(|arg| match arg.split_once('=') {
+            Some((k, v)) => (k == key).then_some(v),
+            None => (arg == key).then_some(""),
+        })
+        .last()
+}
+
+/// Copies the command line on boot. It must be called after `.bss` is cleared.
+unsafe fn set(bytes: &[u8]) {
+    let len = bytes.len().min(MAX_CMDLINE_LEN);
+    let buf = &mut *core::ptr::addr_of_mut!(CMDLINE);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    CMDLINE_LEN = len;
+}
+
+/// Copies the command line from `/chosen/bootargs` of the device tree blob at
+/// the physical address `dtb`, if there is one.
+#[allow(dead_code)]
+pub(crate) unsafe fn init_from_dtb(dtb: usize) {
+    if dtb == 0 {
+        return;
+    }
+    let fdt = phys_to_virt(pa!(dtb)).as_usize();
+    let read_u32 = |offset: usize| u32::from_be(((fdt + offset) as *const u32).read_unaligned());
+    let read_str = |offset: usize| CStr::from_ptr((fdt + offset) as _).to_bytes();
+    if read_u32(0) != FDT_MAGIC {
+        return;
+    }
+    let strings = read_u32(12) as usize;
+    let mut offset = read_u32(8) as usize;
+    let mut depth = 0;
+    let mut in_chosen = false;
+    loop {
+        let token = read_u32(offset);
+        offset += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = read_str(offset);
+                offset += (name.len() + 4) & !3; // NUL-terminated and aligned
+                depth += 1;
+                if depth == 2 {
+                    in_chosen = name == b"chosen";
+                }
+            }
+            FDT_END_NODE => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            FDT_PROP => {
+                let len = read_u32(offset) as usize;
+                let name = read_str(strings + read_u32(offset + 4) as usize);
+                offset += 8;
+                if depth == 2 && in_chosen && name == b"bootargs" {
+                    set(read_str(offset));
+                    break;
+                }
+                offset += (len + 3) & !3;
+            }
+            FDT_NOP => {}
+            _ => break,
+        }
+    }
+}
+
+/// Copies the command line from the multiboot information at the physical
+/// address `mbi`, if there is one.
+#[allow(dead_code)]
+pub(crate) unsafe fn init_from_multiboot(mbi: usize) {
+    let info = phys_to_virt(pa!(mbi)).as_usize() as *const u32;
+    if info.read() & MULTIBOOT_INFO_CMDLINE != 0 {
+        let cmdline = phys_to_virt(pa!(info.add(4).read() as usize)).as_usize();
+        set(CStr::from_ptr(cmdline as _).to_bytes());
+    }
+}
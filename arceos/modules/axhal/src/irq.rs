@@ -1,6 +1,9 @@
 //! Interrupt management.
 
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
 use handler_table::HandlerTable;
+use kspin::SpinNoIrq;
 
 use crate::platform::irq::{dispatch_irq, MAX_IRQ_COUNT};
 use crate::trap::{register_trap_handler, IRQ};
@@ -12,6 +15,108 @@ pub type IrqHandler = handler_table::Handler;
 
 static IRQ_HANDLER_TABLE: HandlerTable<MAX_IRQ_COUNT> = HandlerTable::new();
 
+/// The maximum number of softirqs.
+pub const MAX_SOFTIRQ_COUNT: usize = 8;
+
+/// The softirq that runs expired timer callbacks.
+pub const TIMER_SOFTIRQ: usize = 0;
+
+/// The maximum number of rounds of pending softirqs handled at the end of an
+/// IRQ. Softirqs raised after that are left to the next IRQ.
+const MAX_SOFTIRQ_ROUNDS: usize = 10;
+
+static SOFTIRQ_HANDLER_TABLE: HandlerTable<MAX_SOFTIRQ_COUNT> = HandlerTable::new();
+
+/// The bitmap of pending softirqs of this CPU.
+#[percpu::def_percpu]
+static SOFTIRQ_PENDING: usize = 0;
+
+/// Whether this CPU is handling softirqs.
+#[percpu::def_percpu]
+static IN_SOFTIRQ: bool = false;
+
+/// The maximum number of kinds of inter-processor interrupts.
+pub const MAX_IPI_COUNT: usize = 8;
+
+/// The IPI that runs the function of [`smp_call_function`], whose handler is
+/// built in.
+pub const CALL_FUNCTION_IPI: usize = 0;
+
+/// The IPI that makes an idle CPU check its run queue, whose handler is
+/// registered by the scheduler.
+pub const RESCHED_IPI: usize = 1;
+
+/// The IPI that makes a CPU report its registers and backtrace, e.g., when it
+/// is stuck, whose handler is registered by the lockup watchdog. It is sent by
+/// [`send_nmi_ipi`].
+pub const BACKTRACE_IPI: usize = 2;
+
+static IPI_HANDLER_TABLE: HandlerTable<MAX_IPI_COUNT> = HandlerTable::new();
+
+/// The bitmasks of pending IPI kinds of each CPU.
+static IPI_PENDING: [AtomicUsize; axconfig::SMP] = [const { AtomicUsize::new(0) }; axconfig::SMP];
+
+/// The bitmasks of pending IPI kinds of each CPU sent by [`send_nmi_ipi`].
+static NMI_PENDING: [AtomicUsize; axconfig::SMP] = [const { AtomicUsize::new(0) }; axconfig::SMP];
+
+/// The bitmask of CPUs that are online, i.e., have enabled IRQs and handle
+/// IPIs.
+static ONLINE_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether a CPU is calling a function by [`smp_call_function`] on others.
+static CALL_LOCK: AtomicBool = AtomicBool::new(false);
+
+/// The function of the current [`smp_call_function`] call.
+static CALL_FUNC: SpinNoIrq<Option<CallFunc>> = SpinNoIrq::new(None);
+
+/// The bitmask of CPUs that have not run the function.
+static CALL_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// The function called by [`smp_call_function`], which is valid until all
+/// target CPUs have run it.
+#[derive(Clone, Copy)]
+struct CallFunc(*const (dyn Fn() + Sync));
+
+unsafe impl Send for CallFunc {}
+
+/// The target CPUs of an inter-processor interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpiTarget {
+    /// The CPU with the given ID.
+    Cpu(usize),
+    /// The CPUs whose IDs are set in the bitmask.
+    Mask(usize),
+    /// All CPUs except the current one.
+    Others,
+}
+
+impl IpiTarget {
+    /// Returns the bitmask of the target CPU IDs.
+    fn mask(self) -> usize {
+        let all = usize::MAX >> (usize::BITS as usize - axconfig::SMP);
+        match self {
+            Self::Cpu(cpu_id) => (1 << cpu_id) & all,
+            Self::Mask(mask) => mask & all,
+            Self::Others => all & !(1 << crate::cpu::this_cpu_id()),
+        }
+    }
+}
+
+/// Marks the current CPU online. It must be called right before the CPU
+/// enables IRQs for the first time.
+///
+/// Only online CPUs are interrupted to flush their TLBs when the kernel
+/// mappings are changed, so the entire TLB of the current CPU is flushed here.
+pub fn set_online() {
+    ONLINE_CPUS.fetch_or(1 << crate::cpu::this_cpu_id(), Ordering::SeqCst);
+    crate::arch::flush_tlb(None);
+}
+
+/// Returns the bitmask of online CPUs, see [`set_online`].
+pub fn online_cpus() -> usize {
+    ONLINE_CPUS.load(Ordering::SeqCst)
+}
+
 /// Platform-independent IRQ dispatching.
 #[allow(dead_code)]
 pub(crate) fn dispatch_irq_common(irq_num: usize) {
@@ -35,10 +140,204 @@ pub(crate) fn register_handler_common(irq_num: usize, handler: IrqHandler) -> bo
     false
 }
 
+/// Registers a handler for the IPI kind.
+///
+/// It returns `false` if the kind is invalid or built in, or a handler has been
+/// registered for it.
+pub fn register_ipi_handler(kind: usize, handler: IrqHandler) -> bool {
+    if kind < MAX_IPI_COUNT
+        && kind != CALL_FUNCTION_IPI
+        && IPI_HANDLER_TABLE.register_handler(kind, handler)
+    {
+        return true;
+    }
+    warn!("register handler for IPI {} failed", kind);
+    false
+}
+
+/// Sends an inter-processor interrupt of the kind to the target CPUs.
+///
+/// The handler of the kind runs in the IRQ context of each target CPU. IPIs of
+/// the same kind sent to a CPU before it handles them are merged into one.
+pub fn send_ipi(target: IpiTarget, kind: usize) {
+    assert!(kind < MAX_IPI_COUNT);
+    let mask = target.mask();
+    for (cpu_id, pending) in IPI_PENDING.iter().enumerate() {
+        if mask & (1 << cpu_id) != 0 {
+            pending.fetch_or(1 << kind, Ordering::Release);
+            crate::platform::irq::send_ipi(cpu_id);
+        }
+    }
+}
+
+/// Sends an inter-processor interrupt of the kind to the target CPUs as a
+/// non-maskable interrupt, which is handled even if they are stuck with IRQs
+/// disabled.
+///
+/// The handler may interrupt any code on the target CPUs, including the holders
+/// of spinlocks, so it should only read the state of the CPU. Platforms without
+/// NMIs (all but x86) send a normal IPI instead, which is not handled until the
+/// target CPUs enable IRQs.
+pub fn send_nmi_ipi(target: IpiTarget, kind: usize) {
+    assert!(kind < MAX_IPI_COUNT && kind != CALL_FUNCTION_IPI);
+    let mask = target.mask();
+    for (cpu_id, pending) in NMI_PENDING.iter().enumerate() {
+        if mask & (1 << cpu_id) != 0 {
+            pending.fetch_or(1 << kind, Ordering::Release);
+            crate::platform::irq::send_nmi(cpu_id);
+        }
+    }
+}
+
+/// Runs `func` on the target CPUs, and waits until all of them have returned.
+///
+/// The function runs in the IRQ context of the other CPUs, and with IRQs
+/// disabled on the current CPU if it is also a target, so it must not block.
+/// Calls from different CPUs run one after another.
+///
+/// The current CPU waits with IRQs disabled, and the other CPUs run the
+/// function only when they handle the IPI. So the caller must not hold any
+/// lock (e.g., a `SpinNoIrq`) that the target CPUs may spin on with IRQs
+/// disabled, unless they call [`handle_call_function`] while spinning.
+/// Otherwise, the CPUs wait for each other forever.
+pub fn smp_call_function(target: IpiTarget, func: &(dyn Fn() + Sync)) {
+    let _guard = kernel_guard::IrqSave::new();
+    let cpu_bit = 1 << crate::cpu::this_cpu_id();
+    let mask = target.mask();
+    let others = mask & !cpu_bit;
+    if others != 0 {
+        while CALL_LOCK
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // The calling CPU may be waiting for this one, which does not
+            // handle IPIs with IRQs disabled.
+            handle_call_function();
+            core::hint::spin_loop();
+        }
+        // Safety: the function outlives the call, as the call waits for all
+        // target CPUs to finish it before returning.
+        let func: &'static (dyn Fn() + Sync) = unsafe { core::mem::transmute(func) };
+        *CALL_FUNC.lock() = Some(CallFunc(func));
+        CALL_PENDING.store(others, Ordering::Release);
+        send_ipi(IpiTarget::Mask(others), CALL_FUNCTION_IPI);
+    }
+    if mask & cpu_bit != 0 {
+        func();
+    }
+    if others != 0 {
+        while CALL_PENDING.load(Ordering::Acquire) != 0 {
+            core::hint::spin_loop();
+        }
+        *CALL_FUNC.lock() = None;
+        CALL_LOCK.store(false, Ordering::Release);
+    }
+}
+
+/// Runs the function of the current [`smp_call_function`] call, if this CPU is
+/// a target and has not run it.
+///
+/// It is called on handling the IPI, and should be called by the CPUs spinning
+/// with IRQs disabled on what a caller of [`smp_call_function`] may hold.
+pub fn handle_call_function() {
+    let cpu_bit = 1 << crate::cpu::this_cpu_id();
+    if CALL_PENDING.load(Ordering::Acquire) & cpu_bit == 0 {
+        return;
+    }
+    let func = *CALL_FUNC.lock();
+    if let Some(CallFunc(func)) = func {
+        unsafe { (*func)() };
+    }
+    CALL_PENDING.fetch_and(!cpu_bit, Ordering::Release);
+}
+
+/// Handles an inter-processor interrupt, which is acknowledged by the
+/// platform-specific dispatcher.
+pub(crate) fn handle_ipi() {
+    let pending = IPI_PENDING[crate::cpu::this_cpu_id()].swap(0, Ordering::Acquire);
+    for kind in 0..MAX_IPI_COUNT {
+        if pending & (1 << kind) == 0 {
+            continue;
+        }
+        if kind == CALL_FUNCTION_IPI {
+            handle_call_function();
+        } else if !IPI_HANDLER_TABLE.handle(kind) {
+            warn!("Unhandled IPI {}", kind);
+        }
+    }
+    // Sent as normal IPIs on platforms without NMIs.
+    handle_nmi();
+}
+
+/// Handles the IPIs sent by [`send_nmi_ipi`] to this CPU.
+pub(crate) fn handle_nmi() {
+    let pending = NMI_PENDING[crate::cpu::this_cpu_id()].swap(0, Ordering::Acquire);
+    for kind in 0..MAX_IPI_COUNT {
+        if pending & (1 << kind) != 0 && !IPI_HANDLER_TABLE.handle(kind) {
+            warn!("Unhandled NMI IPI {}", kind);
+        }
+    }
+}
+
+/// Registers a softirq handler.
+///
+/// It returns `false` if the softirq number is invalid or a handler has been
+/// registered for it.
+pub fn register_softirq_handler(softirq_num: usize, handler: IrqHandler) -> bool {
+    if softirq_num < MAX_SOFTIRQ_COUNT
+        && SOFTIRQ_HANDLER_TABLE.register_handler(softirq_num, handler)
+    {
+        return true;
+    }
+    warn!("register handler for softirq {} failed", softirq_num);
+    false
+}
+
+/// Marks a softirq as pending on the current CPU.
+///
+/// Its handler runs at the end of the current (or the next) IRQ on this CPU,
+/// after the hardware has been acknowledged, with IRQs enabled and preemption
+/// disabled. Softirq handlers must not block.
+pub fn raise_softirq(softirq_num: usize) {
+    assert!(softirq_num < MAX_SOFTIRQ_COUNT);
+    let _guard = kernel_guard::IrqSave::new();
+    unsafe {
+        SOFTIRQ_PENDING.write_current_raw(SOFTIRQ_PENDING.read_current_raw() | 1 << softirq_num)
+    };
+}
+
+/// Runs the pending softirqs of this CPU. IRQs must be disabled.
+fn do_softirq() {
+    // An IRQ that interrupts softirq handling leaves its softirqs to the
+    // interrupted one.
+    if unsafe { IN_SOFTIRQ.read_current_raw() } {
+        return;
+    }
+    for _ in 0..MAX_SOFTIRQ_ROUNDS {
+        let pending = unsafe { SOFTIRQ_PENDING.read_current_raw() };
+        if pending == 0 {
+            break;
+        }
+        unsafe {
+            SOFTIRQ_PENDING.write_current_raw(0);
+            IN_SOFTIRQ.write_current_raw(true);
+        }
+        crate::arch::enable_irqs();
+        for softirq_num in 0..MAX_SOFTIRQ_COUNT {
+            if pending & (1 << softirq_num) != 0 {
+                SOFTIRQ_HANDLER_TABLE.handle(softirq_num);
+            }
+        }
+        crate::arch::disable_irqs();
+        unsafe { IN_SOFTIRQ.write_current_raw(false) };
+    }
+}
+
 #[register_trap_handler(IRQ)]
 fn handler_irq(irq_num: usize) -> bool {
     let guard = kernel_guard::NoPreempt::new();
     dispatch_irq(irq_num);
+    do_softirq();
     drop(guard); // rescheduling may occur when preemption is re-enabled.
     true
 }
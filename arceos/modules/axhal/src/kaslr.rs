@@ -0,0 +1,107 @@
+//! Kernel address space layout randomization (KASLR).
+//!
+//! The kernel is linked as a position-independent executable, and moved to a
+//! random virtual address on boot, together with the linear mapping of the
+//! physical memory. That is, [`phys_virt_offset`](crate::mem::phys_virt_offset)
+//! becomes [`PHYS_VIRT_OFFSET`] plus a random offset.
+//!
+//! The boot code calls [`relocate`] with the physical addresses, before it
+//! maps the high addresses, to adjust the absolute addresses in the image
+//! according to its dynamic relocations.
+
+use axconfig::{
+    KERNEL_ASPACE_BASE, KERNEL_ASPACE_SIZE, KERNEL_BASE_VADDR, MMIO_REGIONS, PHYS_MEMORY_END,
+    PHYS_VIRT_OFFSET,
+};
+
+#[cfg(not(any(target_arch = "riscv64", target_arch = "x86_64")))]
+compile_error!("KASLR is only supported on riscv64 and x86_64");
+
+/// The offset is a multiple of 1G, so that the boot page tables can still map
+/// the kernel with 1G pages.
+const KASLR_ALIGN: usize = 0x4000_0000;
+
+/// The number of possible offsets, such that the linear mapping of the highest
+/// physical address is still in the kernel address space.
+const NUM_SLOTS: usize =
+    (KERNEL_ASPACE_BASE + KERNEL_ASPACE_SIZE - PHYS_VIRT_OFFSET - max_paddr()) / KASLR_ALIGN + 1;
+
+#[cfg(target_arch = "riscv64")]
+const R_RELATIVE: usize = 3; // R_RISCV_RELATIVE
+#[cfg(target_arch = "x86_64")]
+const R_RELATIVE: usize = 8; // R_X86_64_RELATIVE
+
+/// An entry of `.rela.dyn`.
+#[repr(C)]
+struct Rela {
+    offset: usize,
+    info: usize,
+    addend: usize,
+}
+
+/// The random offset chosen on boot. It is placed in `.data` rather than
+/// `.bss`, as it is set before `.bss` is cleared.
+#[link_section = ".data"]
+static mut KASLR_OFFSET: usize = 0;
+
+/// Returns the highest physical address mapped by the linear mapping.
+const fn max_paddr() -> usize {
+    let mut max = PHYS_MEMORY_END;
+    let mut i = 0;
+    while i < MMIO_REGIONS.len() {
+        let end = MMIO_REGIONS[i].0 + MMIO_REGIONS[i].1;
+        if end > max {
+            max = end;
+        }
+        i += 1;
+    }
+    max
+}
+
+/// Returns the offset of the kernel from where it is linked.
+pub(crate) fn offset() -> usize {
+    unsafe { KASLR_OFFSET }
+}
+
+/// Chooses a random offset from `seed`, and relocates the kernel image for
+/// that offset. Returns the offset.
+///
+/// It runs at the physical address of the image, before the relocations are
+/// applied, so it must not access any absolute addresses (e.g., through
+/// function pointers or panics).
+#[allow(dead_code)] // not called on the dummy platform
+pub(crate) unsafe fn relocate(seed: usize) -> usize {
+    let offset = (seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32) % NUM_SLOTS * KASLR_ALIGN;
+
+    // Where the image is now (the physical address).
+    let image_start = _skernel as usize;
+    let image_end = KERNEL_BASE_VADDR + (_ekernel as usize - image_start);
+    let mut rela = _srela_dyn as usize as *const Rela;
+    while (rela as usize) < _erela_dyn as usize {
+        let Rela {
+            offset: r_offset,
+            info,
+            addend,
+        } = rela.read();
+        // Only the addresses in the image are moved, values relative to other
+        // bases (e.g., offsets of per-CPU data) are kept. The relocations in
+        // sections not linked in the image (i.e., `.percpu`) are skipped.
+        if info & 0xffff_ffff == R_RELATIVE
+            && (KERNEL_BASE_VADDR..image_end).contains(&r_offset)
+            && (KERNEL_BASE_VADDR..=image_end).contains(&addend)
+        {
+            let ptr = (r_offset - KERNEL_BASE_VADDR + image_start) as *mut usize;
+            ptr.write_unaligned(addend + offset);
+        }
+        rela = rela.add(1);
+    }
+    KASLR_OFFSET = offset;
+    offset
+}
+
+extern "C" {
+    fn _skernel();
+    fn _ekernel();
+    fn _srela_dyn();
+    fn _erela_dyn();
+}
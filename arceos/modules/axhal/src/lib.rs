@@ -20,6 +20,8 @@
 //! - `fp_simd`: Enable floating-point and SIMD support.
 //! - `paging`: Enable page table manipulation.
 //! - `irq`: Enable interrupt handling support.
+//! - `kaslr`: Move the kernel to a random address on boot (riscv64 and x86_64
+//!   only). The kernel must be linked as a position-independent executable.
 //!
 //! [ArceOS]: https://github.com/arceos-org/arceos
 //! [cargo test]: https://doc.rust-lang.org/cargo/guide/tests.html
@@ -39,12 +41,23 @@ extern crate log;
 #[macro_use]
 extern crate memory_addr;
 
+#[cfg(feature = "uspace")]
+extern crate alloc;
+
 mod platform;
 
+#[cfg(feature = "kaslr")]
+mod kaslr;
+
+#[cfg(all(feature = "smp", feature = "irq", feature = "paging"))]
+mod tlb;
+
 #[macro_use]
 pub mod trap;
 
 pub mod arch;
+pub mod backtrace;
+pub mod cmdline;
 pub mod cpu;
 pub mod mem;
 pub mod time;
@@ -44,32 +44,55 @@ pub struct MemRegion {
     pub name: &'static str,
 }
 
-/// Converts a virtual address to a physical address.
-///
-/// It assumes that there is a linear mapping with the offset
-/// [`PHYS_VIRT_OFFSET`], that maps all the physical memory to the virtual
-/// space at the address plus the offset. So we have
-/// `paddr = vaddr - PHYS_VIRT_OFFSET`.
-///
-/// [`PHYS_VIRT_OFFSET`]: axconfig::PHYS_VIRT_OFFSET
-#[inline]
-pub const fn virt_to_phys(vaddr: VirtAddr) -> PhysAddr {
-    pa!(vaddr.as_usize() - axconfig::PHYS_VIRT_OFFSET)
-}
+/// Defines the conversions of the linear mapping. They are `const` unless the
+/// `kaslr` feature is enabled, as the offset is only known on boot then.
+macro_rules! def_linear_mapping {
+    ($($qualifier:tt)*) => {
+        /// Returns the offset of the linear mapping of the physical memory, i.e.,
+        /// `vaddr - paddr`.
+        ///
+        /// It is [`PHYS_VIRT_OFFSET`], plus a random offset chosen on boot if the
+        /// `kaslr` feature is enabled.
+        ///
+        /// [`PHYS_VIRT_OFFSET`]: axconfig::PHYS_VIRT_OFFSET
+        #[inline]
+        pub $($qualifier)* fn phys_virt_offset() -> usize {
+            #[cfg(feature = "kaslr")]
+            let kaslr_offset = crate::kaslr::offset();
+            #[cfg(not(feature = "kaslr"))]
+            let kaslr_offset = 0;
+            axconfig::PHYS_VIRT_OFFSET + kaslr_offset
+        }
+
+        /// Converts a virtual address to a physical address.
+        ///
+        /// It assumes that there is a linear mapping with the offset
+        /// [`phys_virt_offset`], that maps all the physical memory to the virtual
+        /// space at the address plus the offset. So we have
+        /// `paddr = vaddr - phys_virt_offset()`.
+        #[inline]
+        pub $($qualifier)* fn virt_to_phys(vaddr: VirtAddr) -> PhysAddr {
+            pa!(vaddr.as_usize() - phys_virt_offset())
+        }
 
-/// Converts a physical address to a virtual address.
-///
-/// It assumes that there is a linear mapping with the offset
-/// [`PHYS_VIRT_OFFSET`], that maps all the physical memory to the virtual
-/// space at the address plus the offset. So we have
-/// `vaddr = paddr + PHYS_VIRT_OFFSET`.
-///
-/// [`PHYS_VIRT_OFFSET`]: axconfig::PHYS_VIRT_OFFSET
-#[inline]
-pub const fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
-    va!(paddr.as_usize() + axconfig::PHYS_VIRT_OFFSET)
+        /// Converts a physical address to a virtual address.
+        ///
+        /// It assumes that there is a linear mapping with the offset
+        /// [`phys_virt_offset`], that maps all the physical memory to the virtual
+        /// space at the address plus the offset. So we have
+        /// `vaddr = paddr + phys_virt_offset()`.
+        #[inline]
+        pub $($qualifier)* fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
+            va!(paddr.as_usize() + phys_virt_offset())
+        }
+    };
 }
 
+#[cfg(feature = "kaslr")]
+def_linear_mapping!();
+#[cfg(not(feature = "kaslr"))]
+def_linear_mapping!(const);
+
 /// Returns an iterator over all physical memory regions.
 pub fn memory_regions() -> impl Iterator<Item = MemRegion> {
     kernel_image_regions().chain(crate::platform::mem::platform_regions())
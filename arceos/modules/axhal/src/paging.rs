@@ -1,14 +1,22 @@
 //! Page table manipulation.
 
+use core::sync::atomic::{AtomicU64, Ordering};
+
 use axalloc::global_allocator;
+use kspin::SpinNoIrq;
 use lazyinit::LazyInit;
-use page_table_multiarch::PagingHandler;
+use memory_addr::VirtAddrRange;
+use page_table_entry::GenericPTE;
+use page_table_multiarch::{PageTable64, PagingHandler};
 
 use crate::mem::{phys_to_virt, virt_to_phys, MemRegionFlags, PhysAddr, VirtAddr, PAGE_SIZE_4K};
 
 #[doc(no_inline)]
 pub use page_table_multiarch::{MappingFlags, PageSize, PagingError, PagingResult};
 
+/// The number of entries in a page table page.
+const ENTRY_COUNT: usize = 512;
+
 impl From<MemRegionFlags> for MappingFlags {
     fn from(f: MemRegionFlags) -> Self {
         let mut ret = Self::empty();
@@ -55,15 +63,140 @@ impl PagingHandler for PagingHandlerImpl {
 
 cfg_if::cfg_if! {
     if #[cfg(target_arch = "x86_64")] {
-        /// The architecture-specific page table.
-        pub type PageTable = page_table_multiarch::x86_64::X64PageTable<PagingHandlerImpl>;
+        type PagingMetaDataImpl = page_table_multiarch::x86_64::X64PagingMetaData;
+        type PageTableEntry = page_table_entry::x86_64::X64PTE;
+        const PAGING_LEVELS: usize = 4;
+        const PTE_ACCESSED: usize = 1 << 5;
     } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
-        /// The architecture-specific page table.
-        pub type PageTable = page_table_multiarch::riscv::Sv39PageTable<PagingHandlerImpl>;
+        type PagingMetaDataImpl = page_table_multiarch::riscv::Sv39MetaData;
+        type PageTableEntry = page_table_entry::riscv::Rv64PTE;
+        const PAGING_LEVELS: usize = 3;
+        const PTE_ACCESSED: usize = 1 << 6;
     } else if #[cfg(target_arch = "aarch64")]{
+        type PagingMetaDataImpl = page_table_multiarch::aarch64::A64PagingMetaData;
+        type PageTableEntry = page_table_entry::aarch64::A64PTE;
+        const PAGING_LEVELS: usize = 4;
+        const PTE_ACCESSED: usize = 1 << 10;
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "none")] {
         /// The architecture-specific page table.
-        pub type PageTable = page_table_multiarch::aarch64::A64PageTable<PagingHandlerImpl>;
+        pub type PageTable = PageTable64<PagingMetaDataImpl, PageTableEntry, PagingHandlerImpl>;
+    } else {
+        /// The architecture-specific page table, whose TLB entries are not
+        /// flushed on the host, as it is only used by tests.
+        pub type PageTable = PageTable64<HostPagingMetaData, PageTableEntry, PagingHandlerImpl>;
+
+        use page_table_multiarch::PagingMetaData;
+
+        /// The paging metadata of the architecture, without flushing the TLB
+        /// which is not allowed in user mode.
+        pub struct HostPagingMetaData;
+
+        impl PagingMetaData for HostPagingMetaData {
+            const LEVELS: usize = PagingMetaDataImpl::LEVELS;
+            const PA_MAX_BITS: usize = PagingMetaDataImpl::PA_MAX_BITS;
+            const VA_MAX_BITS: usize = PagingMetaDataImpl::VA_MAX_BITS;
+
+            fn flush_tlb(_vaddr: Option<VirtAddr>) {}
+        }
+    }
+}
+
+/// Finds the page table entry that maps `vaddr`, and its level (0 for 4K
+/// pages, 1 for 2M pages, and so on).
+fn find_leaf_entry(
+    pt: &mut PageTable,
+    vaddr: VirtAddr,
+) -> PagingResult<(&mut PageTableEntry, usize)> {
+    let mut table_paddr = pt.root_paddr();
+    for level in (0..PAGING_LEVELS).rev() {
+        let index = (vaddr.as_usize() >> (12 + 9 * level)) % ENTRY_COUNT;
+        // Safety: page table pages are accessed through the linear mapping,
+        // and the page table is locked by the `&mut` borrow.
+        let entry = unsafe {
+            &mut *(phys_to_virt(table_paddr).as_mut_ptr() as *mut PageTableEntry).add(index)
+        };
+        if !entry.is_present() {
+            return Err(PagingError::NotMapped);
+        }
+        if level == 0 || entry.is_huge() {
+            return Ok((entry, level));
+        }
+        table_paddr = entry.paddr();
+    }
+    unreachable!()
+}
+
+/// Splits the huge page that maps `vaddr` into pages of the next smaller size,
+/// with the same flags. Returns the size of the new pages.
+///
+/// The new page table is filled before it replaces the huge page entry, so the
+/// memory is always mapped, even if the split huge page is being accessed,
+/// e.g., it contains the current stack.
+///
+/// Returns [`PagingError::NotMapped`] if `vaddr` is not mapped by a huge page.
+pub fn split_huge_page(pt: &mut PageTable, vaddr: VirtAddr) -> PagingResult<PageSize> {
+    let (entry, level) = find_leaf_entry(pt, vaddr)?;
+    if level == 0 {
+        return Err(PagingError::NotMapped);
+    }
+
+    let new_size = 1 << (12 + 9 * (level - 1));
+    let new_table = PagingHandlerImpl::alloc_frame().ok_or(PagingError::NoMemory)?;
+    let new_entries = phys_to_virt(new_table).as_mut_ptr() as *mut PageTableEntry;
+    let (paddr, flags) = (entry.paddr(), entry.flags());
+    for i in 0..ENTRY_COUNT {
+        let new_entry = PageTableEntry::new_page(paddr + i * new_size, flags, level > 1);
+        unsafe { new_entries.add(i).write(new_entry) };
+    }
+    core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+    *entry = PageTableEntry::new_table(new_table);
+    crate::arch::flush_tlb(None);
+    Ok(match new_size {
+        0x1000 => PageSize::Size4K,
+        0x20_0000 => PageSize::Size2M,
+        _ => PageSize::Size1G,
+    })
+}
+
+/// Tests and clears the accessed flag of the page that maps `vaddr`, which is
+/// set by the hardware when the page is accessed. It is used to find pages
+/// not accessed recently.
+///
+/// On AArch64 (and some RISC-V implementations), the hardware does not set the
+/// flag but raises a page fault (access flag fault) instead, which should be
+/// handled by [`set_accessed`].
+pub fn test_and_clear_accessed(pt: &mut PageTable, vaddr: VirtAddr) -> PagingResult<bool> {
+    let (entry, _) = find_leaf_entry(pt, vaddr)?;
+    let bits = entry.bits();
+    if bits & PTE_ACCESSED == 0 {
+        return Ok(false);
+    }
+    // Safety: the entry is a plain integer of the architecture.
+    unsafe { (entry as *mut PageTableEntry as *mut usize).write(bits & !PTE_ACCESSED) };
+    // Make the next access walk the page table and set the flag again.
+    crate::arch::flush_tlb(Some(vaddr));
+    Ok(true)
+}
+
+/// Sets the accessed flag of the page that maps `vaddr`, if it is cleared by
+/// [`test_and_clear_accessed`]. Returns `true` if the flag was cleared, i.e.,
+/// the page fault at `vaddr` is caused by the cleared flag and is handled.
+pub fn set_accessed(pt: &mut PageTable, vaddr: VirtAddr) -> bool {
+    let Ok((entry, _)) = find_leaf_entry(pt, vaddr) else {
+        return false;
+    };
+    let bits = entry.bits();
+    if bits & PTE_ACCESSED != 0 {
+        return false;
     }
+    // Safety: the entry is a plain integer of the architecture.
+    unsafe { (entry as *mut PageTableEntry as *mut usize).write(bits | PTE_ACCESSED) };
+    crate::arch::flush_tlb(Some(vaddr));
+    true
 }
 
 static KERNEL_PAGE_TABLE_ROOT: LazyInit<PhysAddr> = LazyInit::new();
@@ -85,3 +218,237 @@ pub fn kernel_page_table_root() -> PhysAddr {
         .get()
         .expect("kernel page table not initialized")
 }
+
+/// The number of bits of the ASID in [`Asid`] values, the higher bits are the
+/// generation.
+const ASID_SHIFT: u32 = 16;
+
+/// The current generation of ASIDs, started from 1 so that 0 means unassigned.
+static ASID_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// The number of ASIDs supported by the hardware, where ASID 0 is reserved for
+/// page tables without ASIDs (e.g., the kernel page table).
+static NUM_ASIDS: LazyInit<u64> = LazyInit::new();
+
+/// The next ASID to allocate in the current generation.
+static NEXT_ASID: SpinNoIrq<u64> = SpinNoIrq::new(1);
+
+/// The generation of ASIDs that the TLB of the current CPU is flushed for.
+#[percpu::def_percpu]
+static TLB_GENERATION: u64 = 0;
+
+/// The address of the [`Asid`] of the page table of the current CPU, or 0 if
+/// it has no [`Asid`].
+#[percpu::def_percpu]
+pub(crate) static ACTIVE_ASID: usize = 0;
+
+static_assertions::const_assert!(axconfig::SMP <= usize::BITS as usize);
+
+/// The CPUs that use an [`Asid`], as bitmasks of CPU IDs.
+#[derive(Debug, Default)]
+struct AsidCpus {
+    /// The CPUs running on the page table.
+    active: usize,
+    /// The CPUs that have run on the page table with the current ASID, which
+    /// may have TLB entries of it.
+    cached: usize,
+}
+
+/// An address space identifier (ASID) of a page table, i.e., the RISC-V `satp`
+/// ASID, the x86 PCID or the AArch64 ASID, which tags the TLB entries so that
+/// the TLB need not be flushed on switching page tables.
+///
+/// The ASID is allocated lazily on switching to the page table. When all ASIDs
+/// are used, a new generation is started: ASIDs of older generations become
+/// invalid and are reallocated on next switch, and each CPU flushes its entire
+/// TLB before it uses an ASID of the new generation.
+///
+/// It also tracks the CPUs running on the page table, whose TLBs are flushed
+/// by [`shootdown_tlb`](Asid::shootdown_tlb) after the page table is changed.
+#[derive(Debug)]
+pub struct Asid {
+    /// The generation in the high bits, and the ASID in the low bits, or 0 if
+    /// unassigned.
+    value: AtomicU64,
+    cpus: SpinNoIrq<AsidCpus>,
+}
+
+impl Default for Asid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Asid {
+    /// Creates an unassigned ASID.
+    pub const fn new() -> Self {
+        Self {
+            value: AtomicU64::new(0),
+            cpus: SpinNoIrq::new(AsidCpus {
+                active: 0,
+                cached: 0,
+            }),
+        }
+    }
+
+    /// Returns the ASID of the current generation, allocating a new one if
+    /// it is unassigned or of an older generation.
+    ///
+    /// Returns 0 if the hardware does not support ASIDs.
+    pub fn get(&self) -> usize {
+        self.get_with_generation().1
+    }
+
+    /// Returns the ASID and its generation.
+    fn get_with_generation(&self) -> (u64, usize) {
+        let num_asids = *NUM_ASIDS.call_once(|| 1 << crate::arch::asid_bits());
+        if num_asids <= 1 {
+            return (0, 0);
+        }
+        let split = |val: u64| (val >> ASID_SHIFT, (val & ((1 << ASID_SHIFT) - 1)) as usize);
+        let val = self.value.load(Ordering::Acquire);
+        if val >> ASID_SHIFT == ASID_GENERATION.load(Ordering::Acquire) {
+            return split(val);
+        }
+
+        let mut next = NEXT_ASID.lock();
+        // Check again, as another CPU may have allocated it.
+        let mut generation = ASID_GENERATION.load(Ordering::Acquire);
+        let val = self.value.load(Ordering::Acquire);
+        if val >> ASID_SHIFT == generation {
+            return split(val);
+        }
+        if *next >= num_asids {
+            *next = 1;
+            generation += 1;
+            ASID_GENERATION.store(generation, Ordering::Release);
+        }
+        let asid = *next;
+        *next += 1;
+        self.value
+            .store(generation << ASID_SHIFT | asid, Ordering::Release);
+        (generation, asid as usize)
+    }
+
+    /// Drops the allocated ASID, so that a new one is allocated on next
+    /// switch.
+    ///
+    /// It is needed if the page table is modified when it is not active,
+    /// where the TLB entries cannot be flushed by the virtual address. As the
+    /// ASIDs are not reused in a generation, the new ASID has no stale TLB
+    /// entries.
+    pub fn invalidate(&self) {
+        self.value.store(0, Ordering::Release);
+    }
+
+    /// Flushes the TLB entries of the page table in `range` (or all entries if
+    /// `None`) on other CPUs, after the page table is changed and the TLB of
+    /// the current CPU is flushed.
+    ///
+    /// The other CPUs running on the page table are interrupted to flush their
+    /// TLBs, which requires the `smp` and `irq` features. The ASID is
+    /// reallocated instead if any CPUs not running on it may have stale TLB
+    /// entries of it.
+    pub fn shootdown_tlb(&self, range: Option<VirtAddrRange>) {
+        let _guard = kernel_guard::IrqSave::new();
+        let cpu_bit = 1 << crate::cpu::this_cpu_id();
+        let targets = {
+            let mut cpus = self.cpus.lock();
+            let mut stale = cpus.cached & !cpus.active;
+            if cfg!(not(target_arch = "x86_64")) {
+                // The flushes by addresses on the current CPU apply to all
+                // ASIDs, except for PCIDs on x86.
+                stale &= !cpu_bit;
+            }
+            if stale != 0 {
+                self.invalidate();
+                cpus.cached = cpus.active;
+            }
+            cpus.active & !cpu_bit
+        };
+        #[cfg(all(feature = "smp", feature = "irq"))]
+        if targets != 0 {
+            crate::tlb::shootdown(self as *const _ as usize, targets, range);
+        }
+        #[cfg(not(all(feature = "smp", feature = "irq")))]
+        let _ = (targets, range);
+    }
+}
+
+/// Flushes the TLB entries of the kernel address space in `range` (or all
+/// entries if `None`) on other CPUs, after the kernel page table is changed
+/// and the TLB of the current CPU is flushed by addresses.
+///
+/// All other online CPUs are interrupted to flush their TLBs, as the kernel
+/// mappings are shared by all page tables, which requires the `smp` and `irq`
+/// features. CPUs not online yet flush their TLBs when they become online.
+/// The caller must not hold any lock that the other CPUs may spin on with IRQs
+/// disabled, unless they call [`handle_pending_shootdown`] while spinning.
+pub fn shootdown_kernel_tlb(range: Option<VirtAddrRange>) {
+    // The flushes by addresses only apply to the current PCID on x86, while
+    // the kernel mappings may be cached with other PCIDs.
+    #[cfg(target_arch = "x86_64")]
+    crate::arch::flush_tlb(None);
+    #[cfg(all(feature = "smp", feature = "irq"))]
+    {
+        let targets = crate::irq::online_cpus() & !(1 << crate::cpu::this_cpu_id());
+        if targets != 0 {
+            crate::tlb::shootdown(0, targets, range);
+        }
+    }
+    #[cfg(not(all(feature = "smp", feature = "irq")))]
+    let _ = range;
+}
+
+/// Handles the TLB shootdown requested to the current CPU, if any.
+///
+/// CPUs spinning with IRQs disabled should call it, if the CPU they wait for
+/// may be waiting for their TLB flushes, e.g., on a lock of an address space.
+/// It is a no-op unless the `smp` and `irq` features are enabled.
+pub fn handle_pending_shootdown() {
+    #[cfg(all(feature = "smp", feature = "irq"))]
+    crate::irq::handle_call_function();
+}
+
+/// Switches to the page table whose root is `root_paddr`, with the ASID if
+/// given.
+///
+/// The TLB is not flushed if the ASID is assigned, otherwise the TLB entries
+/// without ASIDs are flushed.
+///
+/// # Safety
+///
+/// This function is unsafe as it changes the virtual memory address space.
+pub unsafe fn switch_page_table(root_paddr: PhysAddr, asid: Option<&Asid>) {
+    let _guard = kernel_guard::IrqSave::new();
+    let cpu_bit = 1 << crate::cpu::this_cpu_id();
+    if let Some(prev) = (ACTIVE_ASID.read_current_raw() as *const Asid).as_ref() {
+        prev.cpus.lock().active &= !cpu_bit;
+    }
+    ACTIVE_ASID.write_current_raw(asid.map_or(0, |asid| asid as *const _ as usize));
+
+    // Hold the lock until the page table is switched to, so that the ASID is
+    // not reallocated by `shootdown_tlb` in between.
+    let cpus = asid.map(|asid| {
+        let mut cpus = asid.cpus.lock();
+        cpus.active |= cpu_bit;
+        cpus.cached |= cpu_bit;
+        cpus
+    });
+    let (generation, tag) = asid.map_or((0, 0), Asid::get_with_generation);
+    if tag != 0 {
+        // The TLB may have stale entries of the ASID from older generations.
+        if TLB_GENERATION.read_current_raw() < generation {
+            crate::arch::flush_tlb(None);
+            TLB_GENERATION.write_current_raw(generation);
+        }
+    }
+    trace!(
+        "switch page table: {:#x} => {:#x} (ASID {})",
+        crate::arch::read_page_table_root(),
+        root_paddr,
+        tag
+    );
+    crate::arch::write_page_table_root_asid(root_paddr, tag);
+    drop(cpus);
+}
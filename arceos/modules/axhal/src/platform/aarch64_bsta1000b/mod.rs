@@ -29,6 +29,7 @@ extern "C" {
 pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::mem::clear_bss();
     crate::arch::set_exception_vector_base(exception_vector_base as usize);
+    crate::cmdline::init_from_dtb(dtb);
     crate::cpu::init_primary(cpu_id);
     dw_apb_uart::init_early();
     super::aarch64_common::generic_timer::init_early();
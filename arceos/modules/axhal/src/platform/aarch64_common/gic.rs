@@ -9,6 +9,9 @@ pub const MAX_IRQ_COUNT: usize = 1024;
 /// The timer IRQ number.
 pub const TIMER_IRQ_NUM: usize = translate_irq(14, InterruptType::PPI).unwrap();
 
+/// The inter-processor interrupt number (SGI 1).
+pub(crate) const IPI_IRQ_NUM: usize = translate_irq(1, InterruptType::SGI).unwrap();
+
 /// The UART IRQ number.
 pub const UART_IRQ_NUM: usize = translate_irq(axconfig::UART_IRQ, InterruptType::SPI).unwrap();
 
@@ -42,7 +45,24 @@ pub fn register_handler(irq_num: usize, handler: IrqHandler) -> bool {
 /// up in the IRQ handler table and calls the corresponding handler. If
 /// necessary, it also acknowledges the interrupt controller after handling.
 pub fn dispatch_irq(_unused: usize) {
-    GICC.handle_irq(|irq_num| crate::irq::dispatch_irq_common(irq_num as _));
+    GICC.handle_irq(|irq_num| {
+        if irq_num as usize == IPI_IRQ_NUM {
+            crate::irq::handle_ipi();
+        } else {
+            crate::irq::dispatch_irq_common(irq_num as _);
+        }
+    });
+}
+
+/// Sends an inter-processor interrupt to the given CPU.
+pub(crate) fn send_ipi(cpu_id: usize) {
+    GICD.lock().send_sgi(cpu_id, IPI_IRQ_NUM);
+}
+
+/// Sends a normal inter-processor interrupt to the given CPU, as NMIs are not
+/// supported.
+pub(crate) fn send_nmi(cpu_id: usize) {
+    send_ipi(cpu_id);
 }
 
 /// Initializes GICD, GICC on the primary CPU.
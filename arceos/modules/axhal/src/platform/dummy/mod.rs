@@ -82,6 +82,24 @@ pub mod irq {
     /// up in the IRQ handler table and calls the corresponding handler. If
     /// necessary, it also acknowledges the interrupt controller after handling.
     pub fn dispatch_irq(irq_num: usize) {}
+
+    /// Sends an inter-processor interrupt to the given CPU.
+    ///
+    /// The IPI is handled immediately if it is sent to the current CPU, while
+    /// other CPUs do not exist.
+    pub(crate) fn send_ipi(cpu_id: usize) {
+        if cpu_id == crate::cpu::this_cpu_id() {
+            crate::irq::handle_ipi();
+        }
+    }
+
+    /// Sends a non-maskable interrupt to the given CPU, in the same way as
+    /// [`send_ipi`].
+    pub(crate) fn send_nmi(cpu_id: usize) {
+        if cpu_id == crate::cpu::this_cpu_id() {
+            crate::irq::handle_nmi();
+        }
+    }
 }
 
 /// Initializes the platform devices for the primary CPU.
@@ -8,11 +8,18 @@ static mut BOOT_STACK: [u8; TASK_STACK_SIZE] = [0; TASK_STACK_SIZE];
 #[link_section = ".data.boot_page_table"]
 static mut BOOT_PT_SV39: [u64; 512] = [0; 512];
 
-unsafe fn init_boot_page_table() {
+/// Sets up the boot page table, and returns the offset of the high address
+/// mapping (relocating the kernel to a random offset with KASLR).
+unsafe fn init_boot_page_table() -> usize {
+    #[cfg(feature = "kaslr")]
+    let offset = PHYS_VIRT_OFFSET + crate::kaslr::relocate(riscv::register::time::read());
+    #[cfg(not(feature = "kaslr"))]
+    let offset = PHYS_VIRT_OFFSET;
     // 0x8000_0000..0xc000_0000, VRWX_GAD, 1G block
     BOOT_PT_SV39[2] = (0x80000 << 10) | 0xef;
-    // 0xffff_ffc0_8000_0000..0xffff_ffc0_c000_0000, VRWX_GAD, 1G block
-    BOOT_PT_SV39[0x102] = (0x80000 << 10) | 0xef;
+    // offset + (0x8000_0000..0xc000_0000), VRWX_GAD, 1G block
+    BOOT_PT_SV39[((offset + 0x8000_0000) >> 30) & 0x1ff] = (0x80000 << 10) | 0xef;
+    offset
 }
 
 unsafe fn init_mmu() {
@@ -32,23 +39,22 @@ unsafe extern "C" fn _start() -> ! {
     core::arch::asm!("
         mv      s0, a0                  // save hartid
         mv      s1, a1                  // save DTB pointer
-        la      sp, {boot_stack}
+        lla     sp, {boot_stack}
         li      t0, {boot_stack_size}
         add     sp, sp, t0              // setup boot stack
 
         call    {init_boot_page_table}
+        mv      s2, a0                  // save the offset of the high address
         call    {init_mmu}              // setup boot page table and enabel MMU
 
-        li      s2, {phys_virt_offset}  // fix up virtual high address
-        add     sp, sp, s2
+        add     sp, sp, s2              // fix up virtual high address
 
         mv      a0, s0
         mv      a1, s1
-        la      a2, {entry}
+        lla     a2, {entry}
         add     a2, a2, s2
         jalr    a2                      // call rust_entry(hartid, dtb)
         j       .",
-        phys_virt_offset = const PHYS_VIRT_OFFSET,
         boot_stack_size = const TASK_STACK_SIZE,
         boot_stack = sym BOOT_STACK,
         init_boot_page_table = sym init_boot_page_table,
@@ -72,16 +78,16 @@ unsafe extern "C" fn _start_secondary() -> ! {
 
         call    {init_mmu}              // setup boot page table and enabel MMU
 
-        li      s1, {phys_virt_offset}  // fix up virtual high address
-        add     a1, a1, s1
+        call    {phys_virt_offset}      // fix up virtual high address
+        mv      s1, a0
         add     sp, sp, s1
 
         mv      a0, s0
-        la      a1, {entry}
+        lla     a1, {entry}
         add     a1, a1, s1
         jalr    a1                      // call rust_entry_secondary(hartid)
         j       .",
-        phys_virt_offset = const PHYS_VIRT_OFFSET,
+        phys_virt_offset = sym crate::mem::phys_virt_offset,
         init_mmu = sym init_mmu,
         entry = sym super::rust_entry_secondary,
         options(noreturn),
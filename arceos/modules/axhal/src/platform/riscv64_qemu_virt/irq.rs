@@ -2,13 +2,12 @@
 
 use crate::irq::IrqHandler;
 use lazyinit::LazyInit;
-use riscv::register::sie;
+use riscv::register::{sie, sip};
 
 /// `Interrupt` bit in `scause`
 pub(super) const INTC_IRQ_BASE: usize = 1 << (usize::BITS - 1);
 
 /// Supervisor software interrupt in `scause`
-#[allow(unused)]
 pub(super) const S_SOFT: usize = INTC_IRQ_BASE + 1;
 
 /// Supervisor timer interrupt in `scause`
@@ -26,9 +25,15 @@ pub const MAX_IRQ_COUNT: usize = 1024;
 pub const TIMER_IRQ_NUM: usize = S_TIMER;
 
 macro_rules! with_cause {
-    ($cause: expr, @TIMER => $timer_op: expr, @EXT => $ext_op: expr $(,)?) => {
+    (
+        $cause: expr,
+        @TIMER => $timer_op: expr,
+        @EXT => $ext_op: expr,
+        @SOFT => $soft_op: expr $(,)?
+    ) => {
         match $cause {
             S_TIMER => $timer_op,
+            S_SOFT => $soft_op,
             S_EXT => $ext_op,
             _ => panic!("invalid trap cause: {:#x}", $cause),
         }
@@ -56,6 +61,7 @@ pub fn register_handler(scause: usize, handler: IrqHandler) -> bool {
             false
         },
         @EXT => crate::irq::register_handler_common(scause & !INTC_IRQ_BASE, handler),
+        @SOFT => false, // handled by `crate::irq::handle_ipi`
     )
 }
 
@@ -72,9 +78,25 @@ pub fn dispatch_irq(scause: usize) {
             TIMER_HANDLER();
         },
         @EXT => crate::irq::dispatch_irq_common(0), // TODO: get IRQ number from PLIC
+        @SOFT => {
+            trace!("IRQ: IPI");
+            unsafe { sip::clear_ssoft() };
+            crate::irq::handle_ipi();
+        },
     );
 }
 
+/// Sends an inter-processor interrupt to the given CPU.
+pub(crate) fn send_ipi(cpu_id: usize) {
+    sbi_rt::send_ipi(sbi_rt::HartMask::from_mask_base(1 << cpu_id, 0));
+}
+
+/// Sends a normal inter-processor interrupt to the given CPU, as NMIs are not
+/// supported.
+pub(crate) fn send_nmi(cpu_id: usize) {
+    send_ipi(cpu_id);
+}
+
 pub(super) fn init_percpu() {
     // enable soft interrupts, timer interrupts, and external interrupts
     unsafe {
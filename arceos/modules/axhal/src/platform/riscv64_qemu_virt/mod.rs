@@ -20,6 +20,7 @@ extern "C" {
 
 unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::mem::clear_bss();
+    crate::cmdline::init_from_dtb(dtb);
     crate::cpu::init_primary(cpu_id);
     crate::arch::set_trap_vector_base(trap_vector_base as usize);
     self::time::init_early();
@@ -14,6 +14,7 @@ pub(super) mod vectors {
     pub const APIC_TIMER_VECTOR: u8 = 0xf0;
     pub const APIC_SPURIOUS_VECTOR: u8 = 0xf1;
     pub const APIC_ERROR_VECTOR: u8 = 0xf2;
+    pub const APIC_IPI_VECTOR: u8 = 0xf3;
 }
 
 /// The maximum number of IRQs.
@@ -22,6 +23,9 @@ pub const MAX_IRQ_COUNT: usize = 256;
 /// The timer IRQ number.
 pub const TIMER_IRQ_NUM: usize = APIC_TIMER_VECTOR as usize;
 
+/// The inter-processor interrupt number.
+pub(crate) const IPI_IRQ_NUM: usize = APIC_IPI_VECTOR as usize;
+
 const IO_APIC_BASE: PhysAddr = pa!(0xFEC0_0000);
 
 static mut LOCAL_APIC: Option<LocalApic> = None;
@@ -59,10 +63,26 @@ pub fn register_handler(vector: usize, handler: crate::irq::IrqHandler) -> bool
 /// necessary, it also acknowledges the interrupt controller after handling.
 #[cfg(feature = "irq")]
 pub fn dispatch_irq(vector: usize) {
-    crate::irq::dispatch_irq_common(vector);
+    if vector == IPI_IRQ_NUM {
+        crate::irq::handle_ipi();
+    } else {
+        crate::irq::dispatch_irq_common(vector);
+    }
     unsafe { local_apic().end_of_interrupt() };
 }
 
+/// Sends an inter-processor interrupt to the given CPU.
+#[cfg(feature = "irq")]
+pub(crate) fn send_ipi(cpu_id: usize) {
+    unsafe { local_apic().send_ipi(APIC_IPI_VECTOR, raw_apic_id(cpu_id as u8)) };
+}
+
+/// Sends a non-maskable interrupt to the given CPU.
+#[cfg(feature = "irq")]
+pub(crate) fn send_nmi(cpu_id: usize) {
+    unsafe { local_apic().send_nmi(raw_apic_id(cpu_id as u8)) };
+}
+
 pub(super) fn local_apic<'a>() -> &'a mut LocalApic {
     // It's safe as LAPIC is per-cpu.
     unsafe { LOCAL_APIC.as_mut().unwrap() }
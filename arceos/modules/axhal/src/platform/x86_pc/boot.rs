@@ -3,7 +3,7 @@ use core::arch::global_asm;
 use x86_64::registers::control::{Cr0Flags, Cr4Flags};
 use x86_64::registers::model_specific::EferFlags;
 
-use axconfig::{PHYS_VIRT_OFFSET, TASK_STACK_SIZE};
+use axconfig::{KERNEL_BASE_PADDR, PHYS_VIRT_OFFSET, TASK_STACK_SIZE};
 
 /// Flags set in the ’flags’ member of the multiboot header.
 ///
@@ -33,6 +33,29 @@ const EFER: u64 = EferFlags::LONG_MODE_ENABLE.bits() | EferFlags::NO_EXECUTE_ENA
 #[link_section = ".bss.stack"]
 static mut BOOT_STACK: [u8; TASK_STACK_SIZE] = [0; TASK_STACK_SIZE];
 
+/// Maps the high address in the temporary page table, and returns its offset
+/// (relocating the kernel to a random offset with KASLR).
+///
+/// It runs at the physical address, with the lower 4G identity-mapped.
+unsafe fn init_boot_page_table() -> usize {
+    #[cfg(feature = "kaslr")]
+    {
+        extern "C" {
+            static mut tmp_pdpt_high: [u64; 512];
+        }
+        let offset = crate::kaslr::relocate(core::arch::x86_64::_rdtsc() as usize);
+        // Map the lower 4G with 1G pages, moved by the offset.
+        let pdpt = core::ptr::addr_of_mut!(tmp_pdpt_high) as *mut u64;
+        for i in 0..4 {
+            pdpt.add((offset >> 30) + i)
+                .write(((i as u64) << 30) | 0x83); // PRESENT | WRITABLE | HUGE_PAGE
+        }
+        PHYS_VIRT_OFFSET + offset
+    }
+    #[cfg(not(feature = "kaslr"))]
+    PHYS_VIRT_OFFSET
+}
+
 global_asm!(
     include_str!("multiboot.S"),
     mb_magic = const MULTIBOOT_BOOTLOADER_MAGIC,
@@ -40,8 +63,11 @@ global_asm!(
     mb_hdr_flags = const MULTIBOOT_HEADER_FLAGS,
     entry = sym super::rust_entry,
     entry_secondary = sym super::rust_entry_secondary,
+    init_boot_page_table = sym init_boot_page_table,
+    phys_virt_offset = sym crate::mem::phys_virt_offset,
 
     offset = const PHYS_VIRT_OFFSET,
+    paddr = const KERNEL_BASE_PADDR,
     boot_stack_size = const TASK_STACK_SIZE,
     boot_stack = sym BOOT_STACK,
 
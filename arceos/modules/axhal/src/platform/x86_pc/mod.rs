@@ -32,13 +32,16 @@ fn current_cpu_id() -> usize {
     }
 }
 
-unsafe extern "C" fn rust_entry(magic: usize, _mbi: usize) {
-    // TODO: handle multiboot info
+unsafe extern "C" fn rust_entry(magic: usize, mbi: usize) {
+    // TODO: get memory regions from multiboot info
     if magic == self::boot::MULTIBOOT_BOOTLOADER_MAGIC {
         crate::mem::clear_bss();
+        crate::cmdline::init_from_multiboot(mbi);
         crate::cpu::init_primary(current_cpu_id());
         self::uart16550::init();
         self::dtables::init_primary();
+        #[cfg(feature = "paging")]
+        crate::arch::enable_pcid();
         self::time::init_early();
         rust_main(current_cpu_id(), 0);
     }
@@ -50,6 +53,8 @@ unsafe extern "C" fn rust_entry_secondary(magic: usize) {
     if magic == self::boot::MULTIBOOT_BOOTLOADER_MAGIC {
         crate::cpu::init_secondary(current_cpu_id());
         self::dtables::init_secondary();
+        #[cfg(feature = "paging")]
+        crate::arch::enable_pcid();
         rust_main_secondary(current_cpu_id());
     }
 }
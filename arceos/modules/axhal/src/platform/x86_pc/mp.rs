@@ -1,4 +1,4 @@
-use crate::mem::{phys_to_virt, PhysAddr, PAGE_SIZE_4K};
+use crate::mem::{phys_to_virt, virt_to_phys, PhysAddr, PAGE_SIZE_4K};
 use crate::time::{busy_wait, Duration};
 
 const START_PAGE_IDX: u8 = 6;
@@ -25,7 +25,8 @@ unsafe fn setup_startup_page(stack_top: PhysAddr) {
         (ap_end as usize - ap_start as usize) / 8,
     );
     start_page[U64_PER_PAGE - 2] = stack_top.as_usize() as u64; // stack_top
-    start_page[U64_PER_PAGE - 1] = ap_entry32 as usize as _; // entry
+    let entry = virt_to_phys(va!(ap_entry32 as usize));
+    start_page[U64_PER_PAGE - 1] = entry.as_usize() as _; // entry
 }
 
 /// Starts the given secondary CPU with its boot stack.
@@ -0,0 +1,36 @@
+//! TLB shootdown by inter-processor interrupts.
+//!
+//! A CPU that changes the page table of an address space flushes its own TLB,
+//! and asks the other CPUs running the address space to flush theirs by
+//! [`shootdown`], which waits until all target CPUs have flushed.
+
+use memory_addr::{VirtAddr, VirtAddrRange, PAGE_SIZE_4K};
+
+use crate::irq::{smp_call_function, IpiTarget};
+
+/// Flush the entire TLB if more pages than this are to be flushed.
+const MAX_FLUSH_PAGES: usize = 32;
+
+/// Asks the CPUs in the bitmask `targets` to flush the TLB entries of the
+/// address space identified by `asid` (0 for the kernel address space) in
+/// `range` (or all entries if `None`), and waits until they have done it.
+pub(crate) fn shootdown(asid: usize, targets: usize, range: Option<VirtAddrRange>) {
+    smp_call_function(IpiTarget::Mask(targets), &|| flush(asid, range));
+}
+
+/// Flushes the TLB entries of the address space on the current CPU.
+fn flush(asid: usize, range: Option<VirtAddrRange>) {
+    // On x86, flushing by addresses only applies to the current PCID, which
+    // may have changed since the request was sent, or is not the only one
+    // caching the kernel mappings.
+    let active = cfg!(not(target_arch = "x86_64"))
+        || (asid != 0 && unsafe { crate::paging::ACTIVE_ASID.read_current_raw() } == asid);
+    match range {
+        Some(range) if active && range.size() / PAGE_SIZE_4K <= MAX_FLUSH_PAGES => {
+            for vaddr in (range.start.as_usize()..range.end.as_usize()).step_by(PAGE_SIZE_4K) {
+                crate::arch::flush_tlb(Some(VirtAddr::from(vaddr)));
+            }
+        }
+        _ => crate::arch::flush_tlb(None),
+    }
+}
@@ -4,7 +4,6 @@ use linkme::distributed_slice as def_trap_handler;
 use memory_addr::VirtAddr;
 use page_table_entry::MappingFlags;
 
-#[cfg(feature = "uspace")]
 use crate::arch::TrapFrame;
 
 pub use linkme::distributed_slice as register_trap_handler;
@@ -17,6 +16,14 @@ pub static IRQ: [fn(usize) -> bool];
 #[def_trap_handler]
 pub static PAGE_FAULT: [fn(VirtAddr, MappingFlags, bool) -> bool];
 
+/// A slice of kernel stack guard checkers.
+///
+/// They are called with the faulting address before panicking on a kernel
+/// page fault that cannot be handled, and should panic with a clear message
+/// if the address is in a stack guard page, i.e., the stack has overflowed.
+#[def_trap_handler]
+pub static STACK_GUARD: [fn(VirtAddr)];
+
 /// A slice of syscall handler functions.
 #[cfg(feature = "uspace")]
 #[def_trap_handler]
@@ -38,6 +45,49 @@ macro_rules! handle_trap {
     }}
 }
 
+/// The address of the trap frame of the IRQ (or NMI) being handled on this CPU,
+/// or 0 if it is not handling one.
+#[percpu::def_percpu]
+static IRQ_TRAP_FRAME: usize = 0;
+
+/// Calls the IRQ handler, with the trap frame of the IRQ available to
+/// [`irq_trap_frame`].
+#[allow(dead_code)]
+pub(crate) fn handle_irq_trap(tf: &TrapFrame, irq_num: usize) {
+    let prev = unsafe { IRQ_TRAP_FRAME.read_current_raw() };
+    unsafe { IRQ_TRAP_FRAME.write_current_raw(tf as *const _ as usize) };
+    handle_trap!(IRQ, irq_num);
+    unsafe { IRQ_TRAP_FRAME.write_current_raw(prev) };
+}
+
+/// Handles a non-maskable interrupt, with its trap frame available to
+/// [`irq_trap_frame`].
+#[cfg(feature = "irq")]
+#[allow(dead_code)]
+pub(crate) fn handle_nmi_trap(tf: &TrapFrame) {
+    let prev = unsafe { IRQ_TRAP_FRAME.read_current_raw() };
+    unsafe { IRQ_TRAP_FRAME.write_current_raw(tf as *const _ as usize) };
+    crate::irq::handle_nmi();
+    unsafe { IRQ_TRAP_FRAME.write_current_raw(prev) };
+}
+
+/// Returns the registers of the context interrupted by the IRQ (or NMI) being
+/// handled on this CPU, or [`None`] if it is not handling one.
+pub fn irq_trap_frame() -> Option<TrapFrame> {
+    let _guard = kernel_guard::IrqSave::new();
+    let ptr = unsafe { IRQ_TRAP_FRAME.read_current_raw() } as *const TrapFrame;
+    // Safety: the trap frame is on the stack until the IRQ handler returns.
+    unsafe { ptr.as_ref() }.cloned()
+}
+
+/// Reports a kernel stack overflow if `vaddr` is in a stack guard page.
+#[allow(dead_code)]
+pub(crate) fn check_stack_guard(vaddr: VirtAddr) {
+    for check in STACK_GUARD.iter() {
+        check(vaddr);
+    }
+}
+
 /// Call the external syscall handler.
 #[cfg(feature = "uspace")]
 pub(crate) fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
@@ -1,17 +1,22 @@
 use core::fmt;
 
+use axalloc::global_allocator;
 use axerrno::{ax_err, AxError, AxResult};
 use axhal::{
-    mem::phys_to_virt,
-    paging::{MappingFlags, PageTable},
+    mem::{phys_to_virt, virt_to_phys},
+    paging::{Asid, MappingFlags, PageSize, PageTable},
 };
 use memory_addr::{
-    is_aligned_4k, pa, MemoryAddr, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange, PAGE_SIZE_4K,
+    is_aligned, is_aligned_4k, pa, MemoryAddr, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange,
+    PAGE_SIZE_4K,
 };
 use memory_set::{MemoryArea, MemorySet};
-use crate::backend::Backend;
+use crate::backend::{Backend, SharedMemory};
+use crate::layout::UserLayout;
+use crate::maps::{AreaInfo, AreaKind};
 use crate::paging_err_to_ax_err;
 use crate::mapping_err_to_ax_err;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 /// The virtual memory address space.
@@ -19,6 +24,11 @@ pub struct AddrSpace {
     va_range: VirtAddrRange,
     areas: MemorySet<Backend>,
     pt: PageTable,
+    asid: Arc<Asid>,
+    layout: UserLayout,
+    /// Where [`swap_out`](Self::swap_out) continues scanning pages.
+    #[cfg(feature = "swap")]
+    swap_hand: VirtAddr,
 }
 
 impl AddrSpace {
@@ -47,18 +57,94 @@ impl AddrSpace {
         self.pt.root_paddr()
     }
 
+    /// Returns the ASID of the page table, which should be passed along with
+    /// the root on switching to it.
+    pub fn asid(&self) -> &Arc<Asid> {
+        &self.asid
+    }
+
+    /// Returns the layout of the user stack, memory mappings and executable.
+    pub const fn layout(&self) -> &UserLayout {
+        &self.layout
+    }
+
     /// Checks if the address space contains the given address range.
     pub fn contains_range(&self, start: VirtAddr, size: usize) -> bool {
         self.va_range
             .contains_range(VirtAddrRange::from_start_size(start, size))
     }
 
+    /// Returns an iterator over the memory areas, in ascending order of their
+    /// addresses, with their backends and the numbers of resident pages.
+    pub fn areas(&self) -> impl Iterator<Item = AreaInfo> + '_ {
+        self.areas.iter().map(|area| {
+            let (kind, page_size) = match area.backend() {
+                Backend::Linear { .. } => (AreaKind::Linear, PageSize::Size4K),
+                Backend::Alloc {
+                    populate,
+                    page_size,
+                } => (
+                    AreaKind::Alloc {
+                        populate: *populate,
+                    },
+                    *page_size,
+                ),
+                #[cfg(feature = "fs")]
+                Backend::File {
+                    start,
+                    offset,
+                    shared,
+                    ..
+                } => (
+                    AreaKind::File {
+                        offset: offset + (area.start().as_usize() - start.as_usize()) as u64,
+                        shared: *shared,
+                    },
+                    PageSize::Size4K,
+                ),
+                Backend::Shared { .. } => (AreaKind::Shared, PageSize::Size4K),
+            };
+            let mut info = AreaInfo {
+                start: area.start(),
+                end: area.end(),
+                flags: area.flags(),
+                kind,
+                page_size,
+                resident_pages: 0,
+                swapped_pages: 0,
+            };
+            let mut vaddr = area.start();
+            while vaddr < area.end() {
+                let next = match self.pt.query(vaddr) {
+                    // Linear areas may be mapped with huge pages as well.
+                    Ok((_, flags, size)) if !flags.is_empty() => {
+                        let next = (vaddr.align_down(size) + size.into()).min(area.end());
+                        info.resident_pages += (next.as_usize() - vaddr.as_usize()) / PAGE_SIZE_4K;
+                        next
+                    }
+                    #[cfg(feature = "swap")]
+                    Ok((paddr, _, _)) if crate::swap::paddr_to_slot(paddr).is_some() => {
+                        info.swapped_pages += 1;
+                        vaddr + PAGE_SIZE_4K
+                    }
+                    _ => vaddr + PAGE_SIZE_4K,
+                };
+                vaddr = next;
+            }
+            info
+        })
+    }
+
     /// Creates a new empty address space.
     pub fn new_empty(base: VirtAddr, size: usize) -> AxResult<Self> {
         Ok(Self {
             va_range: VirtAddrRange::from_start_size(base, size),
             areas: MemorySet::new(),
             pt: PageTable::try_new().map_err(|_| AxError::NoMemory)?,
+            asid: Arc::new(Asid::new()),
+            layout: UserLayout::new(base, size),
+            #[cfg(feature = "swap")]
+            swap_hand: base,
         })
     }
 
@@ -77,9 +163,172 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Creates a copy of the address space, whose allocation mappings share the
+    /// physical frames with this one copy-on-write.
+    ///
+    /// Shared memory mappings are mapped to the same objects, and the layout
+    /// is kept.
+    ///
+    /// The mapped pages of allocation areas and private file areas are made
+    /// read-only in both address spaces, and each page is copied when either
+    /// side writes it, except that huge pages are copied immediately. Pages not
+    /// mapped yet are allocated separately on demand. The kernel mappings are
+    /// copied as in [`new_user_aspace`](crate::new_user_aspace).
+    ///
+    /// It is usually used to implement `fork`.
+    pub fn clone_cow(&mut self) -> AxResult<Self> {
+        let mut new = Self::new_empty(self.base(), self.size())?;
+        new.layout = self.layout;
+        let kernel_aspace = crate::kernel_aspace().lock();
+        if !self.va_range.overlaps(kernel_aspace.va_range) {
+            new.copy_mappings_from(&kernel_aspace)?;
+        }
+        drop(kernel_aspace);
+
+        for area in self.areas.iter() {
+            let (start, size, flags) = (area.start(), area.size(), area.flags());
+            let backend = match area.backend() {
+                Backend::Linear { .. } | Backend::Shared { .. } => area.backend().clone(),
+                // Map the new area lazily, and share the mapped frames below.
+                Backend::Alloc { page_size, .. } => Backend::Alloc {
+                    populate: false,
+                    page_size: *page_size,
+                },
+                #[cfg(feature = "fs")]
+                Backend::File { .. } => area.backend().clone(),
+            };
+            new.areas
+                .map(
+                    MemoryArea::new(start, size, flags, backend),
+                    &mut new.pt,
+                    false,
+                )
+                .map_err(mapping_err_to_ax_err)?;
+            match area.backend() {
+                Backend::Linear { .. } | Backend::Shared { .. } => continue,
+                Backend::Alloc { page_size, .. } if page_size.is_huge() => {
+                    self.copy_pages_to(&mut new, start, size)?;
+                    continue;
+                }
+                // Pages of shared file mappings are not shared copy-on-write,
+                // but mapped by the new area from the page cache on demand.
+                #[cfg(feature = "fs")]
+                Backend::File { shared: true, .. } => continue,
+                _ => {}
+            }
+
+            let cow_flags = flags - MappingFlags::WRITE;
+            for vaddr in PageIter4K::new(start, area.end()).unwrap() {
+                let frame = match self.pt.query(vaddr) {
+                    Ok((frame, pte_flags, _)) if !pte_flags.is_empty() => frame,
+                    #[cfg(feature = "swap")]
+                    Ok((paddr, _, _)) if crate::swap::paddr_to_slot(paddr).is_some() => {
+                        // Swapped out, read it back to share it.
+                        if !area.backend().handle_page_fault(
+                            vaddr,
+                            MappingFlags::empty(),
+                            flags,
+                            &mut self.pt,
+                        ) {
+                            return ax_err!(NoMemory, "failed to swap in");
+                        }
+                        self.pt.query(vaddr).map_err(paging_err_to_ax_err)?.0
+                    }
+                    _ => continue, // not mapped yet
+                };
+                crate::backend::share_frame(frame);
+                if flags.contains(MappingFlags::WRITE) {
+                    self.pt
+                        .protect(vaddr, cow_flags)
+                        .map_err(paging_err_to_ax_err)?
+                        .1
+                        .flush();
+                }
+                new.pt
+                    .remap(vaddr, frame, cow_flags)
+                    .map_err(paging_err_to_ax_err)?
+                    .1
+                    .ignore();
+            }
+        }
+        self.flush_tlb(None);
+        Ok(new)
+    }
+
+    /// Copies the mapped pages of a huge page area to the same addresses of
+    /// another address space, whose area is not populated.
+    fn copy_pages_to(&self, other: &mut Self, start: VirtAddr, size: usize) -> AxResult {
+        let end = start + size;
+        let mut vaddr = start;
+        while vaddr < end {
+            let (paddr, flags, page_size) = match self.pt.query(vaddr) {
+                Ok((paddr, flags, page_size)) if !flags.is_empty() => (paddr, flags, page_size),
+                _ => {
+                    vaddr += PAGE_SIZE_4K; // not mapped yet
+                    continue;
+                }
+            };
+            let size = page_size.into();
+            let new_frame = global_allocator()
+                .alloc_pages(size / PAGE_SIZE_4K, size)
+                .map_err(|_| AxError::NoMemory)?;
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    phys_to_virt(paddr).as_ptr(),
+                    new_frame as *mut u8,
+                    size,
+                )
+            };
+            other
+                .pt
+                .map(vaddr, virt_to_phys(new_frame.into()), page_size, flags)
+                .map_err(paging_err_to_ax_err)?
+                .ignore();
+            vaddr += size;
+        }
+        Ok(())
+    }
+
+    /// Removes all mappings of the areas in the address space, and deallocates
+    /// (or releases the shares of) their physical frames.
+    ///
+    /// The modified pages of shared file mappings are written back first. All
+    /// mappings are removed even if it fails, and the error is returned.
+    pub fn clear(&mut self) -> AxResult {
+        #[cfg(feature = "fs")]
+        let sync_res = self.sync_files(self.base(), self.size());
+        #[cfg(not(feature = "fs"))]
+        let sync_res = Ok(());
+        let res = self
+            .areas
+            .clear(&mut self.pt)
+            .map_err(mapping_err_to_ax_err);
+        self.flush_tlb(None);
+        sync_res.and(res)
+    }
+
+    /// Flushes the TLB entries in `range` (or all entries if `None`) on other
+    /// CPUs, after the page table is changed and the TLB of the current CPU is
+    /// flushed by addresses.
+    ///
+    /// The kernel mappings are flushed on all other CPUs, as they are shared by
+    /// all page tables. The frames released by the backends are freed
+    /// afterwards, as other CPUs may access them until they flush.
+    fn flush_tlb(&self, range: Option<VirtAddrRange>) {
+        if self.base().as_usize() == axconfig::KERNEL_ASPACE_BASE
+            && self.size() == axconfig::KERNEL_ASPACE_SIZE
+        {
+            axhal::paging::shootdown_kernel_tlb(range);
+        } else {
+            self.asid.shootdown_tlb(range);
+        }
+        crate::backend::free_released(self.page_table_root());
+    }
+
     /// Finds a free area that can accommodate the given size.
     ///
-    /// The search starts from the given hint address, and the area should be within the given limit range.
+    /// The search starts from the given hint address, and the area should be
+    /// within the given limit range.
     ///
     /// Returns the start address of the free area. Returns None if no such area is found.
     pub fn find_free_area(
@@ -94,7 +343,8 @@ impl AddrSpace {
     /// Add a new linear mapping.
     ///
     /// The mapping is linear, i.e., `start_vaddr` is mapped to `start_paddr`,
-    /// and `start_vaddr + size` is mapped to `start_paddr + size`.
+    /// and `start_vaddr + size` is mapped to `start_paddr + size`. Huge pages
+    /// are used where both addresses are aligned to the huge page size.
     ///
     /// The `flags` parameter indicates the mapping permissions and attributes.
     ///
@@ -121,7 +371,7 @@ impl AddrSpace {
                 |va| pa!(va.as_usize() - offset),
                 size,
                 flags,
-                false, // allow_huge
+                true,  // allow_huge
                 false, // flush_tlb_by_page
             )
             .map_err(paging_err_to_ax_err)?
@@ -158,10 +408,194 @@ impl AddrSpace {
         Ok(())
     }
 
-    /// Removes mappings within the specified virtual address range.
+    /// Add a new allocation mapping with huge pages of `page_size`.
+    ///
+    /// Each page is mapped to contiguous physical frames, which are allocated
+    /// when the mapping is created if `populate` is `true`, or on the first
+    /// access otherwise.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned to `page_size`.
+    pub fn map_alloc_huge(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        populate: bool,
+        page_size: PageSize,
+    ) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned(page_size) || !is_aligned(size, page_size.into()) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let area = MemoryArea::new(
+            start,
+            size,
+            flags,
+            Backend::new_alloc_huge(populate, page_size),
+        );
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
+    /// Add a new file mapping.
+    ///
+    /// The file is mapped from `offset` to `start`, and its pages are read on
+    /// demand. If `shared` is `true`, the modified pages are written back to
+    /// the file when they are unmapped or synced by [`msync`](Self::msync).
+    /// See [`Backend`] for more details about the mapping backends.
+    ///
+    /// Returns an error if the address range is out of the address space or
+    /// the range or `offset` is not aligned.
+    #[cfg(feature = "fs")]
+    pub fn map_file(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        node: axfs_vfs::VfsNodeRef,
+        offset: u64,
+        shared: bool,
+    ) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() || !is_aligned_4k(size) || !is_aligned_4k(offset as usize) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let area = MemoryArea::new(
+            start,
+            size,
+            flags,
+            Backend::new_file(node, start, offset, shared),
+        );
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
+    /// Writes the modified pages of shared file mappings within the specified
+    /// virtual address range back to the files.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned, or failed to write the files.
+    #[cfg(feature = "fs")]
+    pub fn msync(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() || !is_aligned_4k(size) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        self.sync_files(start, size)
+    }
+
+    /// Writes the modified pages of shared file mappings within the range back
+    /// to the files.
+    #[cfg(feature = "fs")]
+    fn sync_files(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        let end = start + size;
+        for area in self.areas.iter() {
+            if !matches!(area.backend(), Backend::File { shared: true, .. })
+                || area.end() <= start
+                || area.start() >= end
+            {
+                continue;
+            }
+            let sync_start = area.start().max(start);
+            let sync_size = area.end().min(end).as_usize() - sync_start.as_usize();
+            if !area
+                .backend()
+                .sync_file(sync_start, sync_size, area.flags(), &mut self.pt)
+            {
+                return ax_err!(Io, "failed to write back the mapped file");
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a new mapping of a shared memory object.
+    ///
+    /// The whole object is mapped to `start`. The object is kept alive until
+    /// the mapping is removed, so it can be freed after mapping.
     ///
     /// Returns an error if the address range is out of the address space or not
     /// aligned.
+    pub fn map_shared(
+        &mut self,
+        start: VirtAddr,
+        flags: MappingFlags,
+        shm: Arc<SharedMemory>,
+    ) -> AxResult {
+        let size = shm.size();
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let area = MemoryArea::new(start, size, flags, Backend::new_shared(shm, start));
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
+    /// Checks that the range is aligned to the page size of the huge page
+    /// allocation areas it overlaps, which may be split at the boundaries.
+    fn check_huge_page_areas(&self, start: VirtAddr, size: usize) -> AxResult {
+        let end = start + size;
+        for area in self.areas.iter() {
+            if let Backend::Alloc { page_size, .. } = *area.backend() {
+                if page_size.is_huge()
+                    && area.start() < end
+                    && area.end() > start
+                    && !(start.is_aligned(page_size) && is_aligned(size, page_size.into()))
+                {
+                    // Otherwise, lazily mapped huge pages may exceed the area.
+                    return ax_err!(InvalidInput, "address not aligned to huge pages");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits the huge pages across the boundaries of the range, so that the
+    /// pages in the range can be unmapped or protected.
+    fn split_huge_pages(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        for vaddr in [start, start + size] {
+            while let Ok((_, _, page_size)) = self.pt.query(vaddr) {
+                if !page_size.is_huge() || vaddr.is_aligned(page_size) {
+                    break;
+                }
+                axhal::paging::split_huge_page(&mut self.pt, vaddr)
+                    .map_err(paging_err_to_ax_err)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes mappings within the specified virtual address range.
+    ///
+    /// If the range overlaps with areas, the areas are unmapped by their
+    /// backends, e.g., the allocated frames are deallocated and shared file
+    /// mappings are written back. Otherwise, the page table entries (e.g.,
+    /// added by [`map_linear`](Self::map_linear)) are removed.
+    ///
+    /// Huge pages across the range boundaries are split, but the range must be
+    /// aligned to the page size of huge page allocation areas it overlaps.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned, or failed to write back the files, where nothing is unmapped.
     pub fn unmap(&mut self, start: VirtAddr, size: usize) -> AxResult {
         if !self.contains_range(start, size) {
             return ax_err!(InvalidInput, "address out of range");
@@ -169,18 +603,56 @@ impl AddrSpace {
         if !start.is_aligned_4k() || !is_aligned_4k(size) {
             return ax_err!(InvalidInput, "address not aligned");
         }
+        self.check_huge_page_areas(start, size)?;
+        #[cfg(feature = "fs")]
+        self.sync_files(start, size)?;
 
-        self.pt
-            .unmap_region(start, size, true)
-            .map_err(paging_err_to_ax_err)?
-            .ignore();
-        Ok(())
+        self.split_huge_pages(start, size)?;
+        let range = VirtAddrRange::from_start_size(start, size);
+        let res = if self.areas.overlaps(range) {
+            self.areas
+                .unmap(start, size, &mut self.pt)
+                .map_err(mapping_err_to_ax_err)
+        } else {
+            self.pt
+                .unmap_region(start, size, true)
+                .map(|tlb| tlb.ignore())
+                .map_err(paging_err_to_ax_err)
+        };
+        self.flush_tlb(Some(range));
+        res
+    }
+
+    /// Queries the mapping of the page at `vaddr`, and faults it in through
+    /// the backend first if it is not present, e.g., not allocated yet or
+    /// swapped out.
+    fn query_or_fault_in(&mut self, vaddr: VirtAddr) -> Option<(PhysAddr, MappingFlags, PageSize)> {
+        match self.pt.query(vaddr) {
+            Ok((paddr, flags, page_size)) if !flags.is_empty() => Some((paddr, flags, page_size)),
+            _ => {
+                if !self.handle_page_fault(vaddr, MappingFlags::empty()) {
+                    return None;
+                }
+                self.pt
+                    .query(vaddr)
+                    .ok()
+                    .filter(|(_, flags, _)| !flags.is_empty())
+            }
+        }
     }
 
     /// To process data in this area with the given function.
     ///
     /// Now it supports reading and writing data in the given interval.
-    fn process_area_data<F>(&self, start: VirtAddr, size: usize, mut f: F) -> AxResult
+    /// `access_flags` indicates the access type, e.g., pages shared
+    /// copy-on-write are copied before they are written.
+    fn process_area_data<F>(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        access_flags: MappingFlags,
+        mut f: F,
+    ) -> AxResult
     where
         F: FnMut(VirtAddr, usize, usize),
     {
@@ -193,7 +665,25 @@ impl AddrSpace {
         for vaddr in PageIter4K::new(start.align_down_4k(), end_align_up)
             .expect("Failed to create page iterator")
         {
-            let (mut paddr, _, _) = self.pt.query(vaddr).map_err(|_| AxError::BadAddress)?;
+            let (mut paddr, flags, _) = self.query_or_fault_in(vaddr).ok_or(AxError::BadAddress)?;
+            if access_flags.contains(MappingFlags::WRITE) && !flags.contains(MappingFlags::WRITE) {
+                // Copy the page if it is shared copy-on-write, so that other
+                // address spaces are not affected, as a write fault does.
+                let area = self.areas.find(vaddr).ok_or(AxError::BadAddress)?;
+                let handled = if area.flags().contains(MappingFlags::WRITE) {
+                    self.handle_page_fault(vaddr, MappingFlags::WRITE)
+                } else {
+                    // The kernel may write read-only areas, e.g., to load code.
+                    let handled = area.backend().unshare_page(vaddr, &mut self.pt);
+                    let page = vaddr.align_down_4k();
+                    self.flush_tlb(Some(VirtAddrRange::from_start_size(page, PAGE_SIZE_4K)));
+                    handled
+                };
+                if !handled {
+                    return Err(AxError::BadAddress);
+                }
+                paddr = self.pt.query(vaddr).map_err(|_| AxError::BadAddress)?.0;
+            }
 
             let mut copy_size = (size - cnt).min(PAGE_SIZE_4K);
 
@@ -217,26 +707,56 @@ impl AddrSpace {
     ///
     /// * `start` - The start virtual address to read.
     /// * `buf` - The buffer to store the data.
-    pub fn read(&self, start: VirtAddr, buf: &mut [u8]) -> AxResult {
-        self.process_area_data(start, buf.len(), |src, offset, read_size| unsafe {
-            core::ptr::copy_nonoverlapping(src.as_ptr(), buf.as_mut_ptr().add(offset), read_size);
-        })
+    pub fn read(&mut self, start: VirtAddr, buf: &mut [u8]) -> AxResult {
+        self.process_area_data(
+            start,
+            buf.len(),
+            MappingFlags::READ,
+            |src, offset, read_size| unsafe {
+                core::ptr::copy_nonoverlapping(
+                    src.as_ptr(),
+                    buf.as_mut_ptr().add(offset),
+                    read_size,
+                );
+            },
+        )
     }
 
     /// To write data to the address space.
     ///
+    /// Pages shared copy-on-write (e.g., by [`clone_cow`](Self::clone_cow))
+    /// are copied first, so that other address spaces are not affected.
+    ///
     /// # Arguments
     ///
     /// * `start_vaddr` - The start virtual address to write.
     /// * `buf` - The buffer to write to the address space.
-    pub fn write(&self, start: VirtAddr, buf: &[u8]) -> AxResult {
-        self.process_area_data(start, buf.len(), |dst, offset, write_size| unsafe {
-            core::ptr::copy_nonoverlapping(buf.as_ptr().add(offset), dst.as_mut_ptr(), write_size);
-        })
+    pub fn write(&mut self, start: VirtAddr, buf: &[u8]) -> AxResult {
+        self.process_area_data(
+            start,
+            buf.len(),
+            MappingFlags::WRITE,
+            |dst, offset, write_size| unsafe {
+                core::ptr::copy_nonoverlapping(
+                    buf.as_ptr().add(offset),
+                    dst.as_mut_ptr(),
+                    write_size,
+                );
+            },
+        )
     }
 
     /// Updates mapping within the specified virtual address range.
     ///
+    /// If the range overlaps with areas, the flags of the areas are updated,
+    /// and their mapped pages are protected by their backends, e.g., pages
+    /// shared copy-on-write stay read-only until they are written. Otherwise,
+    /// the page table entries (e.g., added by [`map_linear`](Self::map_linear))
+    /// are updated.
+    ///
+    /// Huge pages across the range boundaries are split, but the range must be
+    /// aligned to the page size of huge page allocation areas it overlaps.
+    ///
     /// Returns an error if the address range is out of the address space or not
     /// aligned.
     pub fn protect(&mut self, start: VirtAddr, size: usize, flags: MappingFlags) -> AxResult {
@@ -246,12 +766,22 @@ impl AddrSpace {
         if !start.is_aligned_4k() || !is_aligned_4k(size) {
             return ax_err!(InvalidInput, "address not aligned");
         }
+        self.check_huge_page_areas(start, size)?;
 
-        self.pt
-            .protect_region(start, size, flags, true)
-            .map_err(paging_err_to_ax_err)?
-            .ignore();
-        Ok(())
+        self.split_huge_pages(start, size)?;
+        let range = VirtAddrRange::from_start_size(start, size);
+        let res = if self.areas.overlaps(range) {
+            self.areas
+                .protect(start, size, |_| Some(flags), &mut self.pt)
+                .map_err(mapping_err_to_ax_err)
+        } else {
+            self.pt
+                .protect_region(start, size, flags, true)
+                .map(|tlb| tlb.ignore())
+                .map_err(paging_err_to_ax_err)
+        };
+        self.flush_tlb(Some(range));
+        res
     }
 
     /// Handles a page fault at the given address.
@@ -264,19 +794,109 @@ impl AddrSpace {
         if !self.va_range.contains(vaddr) {
             return false;
         }
+        #[cfg(feature = "swap")]
+        if crate::swap::memory_low() {
+            self.swap_out(crate::swap::SWAP_OUT_BATCH);
+        }
         if let Some(area) = self.areas.find(vaddr) {
             let orig_flags = area.flags();
             if orig_flags.contains(access_flags) {
-                return area
-                    .backend()
-                    .handle_page_fault(vaddr, orig_flags, &mut self.pt);
+                // The accessed flag may be cleared by swapping.
+                #[cfg(feature = "swap")]
+                if axhal::paging::set_accessed(&mut self.pt, vaddr) {
+                    return true;
+                }
+                // A present page is remapped, e.g., copied on write, so other
+                // CPUs may have stale entries of it.
+                let present =
+                    matches!(self.pt.query(vaddr), Ok((_, flags, _)) if !flags.is_empty());
+                let handled =
+                    area.backend()
+                        .handle_page_fault(vaddr, access_flags, orig_flags, &mut self.pt);
+                if present {
+                    let page = vaddr.align_down_4k();
+                    self.flush_tlb(Some(VirtAddrRange::from_start_size(page, PAGE_SIZE_4K)));
+                }
+                return handled;
             }
         }
         false
     }
 
+    /// Swaps out at most `nr_pages` pages of the allocation areas that are not
+    /// accessed recently, and returns the number of pages swapped out.
+    ///
+    /// Pages are scanned circularly from where the last call stopped, and the
+    /// accessed pages are skipped, with their accessed flags cleared (the clock
+    /// algorithm). Huge pages and populated areas are not swapped out.
+    #[cfg(feature = "swap")]
+    pub fn swap_out(&mut self, nr_pages: usize) -> usize {
+        let ranges: Vec<_> = self
+            .areas
+            .iter()
+            .filter(|area| {
+                matches!(
+                    area.backend(),
+                    Backend::Alloc {
+                        populate: false,
+                        page_size: PageSize::Size4K,
+                    }
+                )
+            })
+            .map(|area| (area.start(), area.end(), area.flags()))
+            .collect();
+        let total_pages: usize = ranges
+            .iter()
+            .map(|(start, end, _)| (end.as_usize() - start.as_usize()) / PAGE_SIZE_4K)
+            .sum();
+        if total_pages == 0 {
+            return 0;
+        }
+
+        let (mut idx, mut vaddr) = match ranges.iter().position(|(_, end, _)| *end > self.swap_hand)
+        {
+            Some(idx) => (idx, self.swap_hand.max(ranges[idx].0)),
+            None => (0, ranges[0].0),
+        };
+        let mut unmapped = Vec::new();
+        // Two rounds at most, as the first one may only clear accessed flags.
+        for _ in 0..2 * total_pages {
+            if unmapped.len() == nr_pages {
+                break;
+            }
+            if vaddr >= ranges[idx].1 {
+                idx = (idx + 1) % ranges.len();
+                vaddr = ranges[idx].0;
+            }
+            let flags = ranges[idx].2;
+            if let Some((frame, slot)) = Backend::unmap_for_swap(vaddr, flags, &mut self.pt) {
+                unmapped.push((vaddr, frame, slot, flags));
+            }
+            vaddr += PAGE_SIZE_4K;
+        }
+        self.swap_hand = vaddr;
+        if unmapped.is_empty() {
+            return 0;
+        }
+        // Other CPUs must not modify the pages while they are being written.
+        self.flush_tlb(None);
+        let swapped = unmapped
+            .into_iter()
+            .filter(|&(vaddr, frame, slot, flags)| {
+                Backend::write_swapped_page(vaddr, frame, slot, flags, &mut self.pt)
+            })
+            .count();
+        debug!("swap_out: {} pages swapped out", swapped);
+        swapped
+    }
+
+    /// Returns the buffers of the physical memory mapped by the given virtual
+    /// address range, or `None` if it is not mapped.
+    ///
+    /// Pages not present, e.g., not allocated yet or swapped out, are faulted
+    /// in first.
     pub fn translated_byte_buffer(
-        &self,
+        &mut self,
         vaddr: VirtAddr,
         len: usize,
     ) -> Option<Vec<&'static mut [u8]>> {
@@ -305,7 +925,7 @@ impl AddrSpace {
 
             let mut v = Vec::new();
             while start < end {
-                let (start_paddr, _, page_size) = self.page_table().query(start).unwrap();
+                let (start_paddr, _, page_size) = self.query_or_fault_in(start)?;
                 let mut end_va = start.align_down(page_size) + page_size.into();
                 end_va = end_va.min(end);
 
@@ -1,11 +1,19 @@
+use alloc::collections::BTreeMap;
+
 use axalloc::global_allocator;
 use axhal::mem::{phys_to_virt, virt_to_phys};
 use axhal::paging::{MappingFlags, PageSize, PageTable};
-use memory_addr::{PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+use kspin::SpinNoIrq;
+use memory_addr::{MemoryAddr, PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use super::{release, Backend, Released};
 
-use super::Backend;
+/// Reference counts of the frames shared by copy-on-write mappings.
+///
+/// Frames not in the map are referenced by only one mapping.
+static SHARED_FRAMES: SpinNoIrq<BTreeMap<PhysAddr, usize>> = SpinNoIrq::new(BTreeMap::new());
 
-fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
+pub(super) fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
     let vaddr = VirtAddr::from(global_allocator().alloc_pages(1, PAGE_SIZE_4K).ok()?);
     if zeroed {
         unsafe { core::ptr::write_bytes(vaddr.as_mut_ptr(), 0, PAGE_SIZE_4K) };
@@ -14,15 +22,69 @@ fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
     Some(paddr)
 }
 
-fn dealloc_frame(frame: PhysAddr) {
+pub(super) fn dealloc_frame(frame: PhysAddr) {
     let vaddr = phys_to_virt(frame);
     global_allocator().dealloc_pages(vaddr.as_usize(), 1);
 }
 
+/// Allocates contiguous frames for a huge page, aligned to the page size.
+fn alloc_huge_frame(page_size: PageSize, zeroed: bool) -> Option<PhysAddr> {
+    let size: usize = page_size.into();
+    let vaddr = VirtAddr::from(
+        global_allocator()
+            .alloc_pages(size / PAGE_SIZE_4K, size)
+            .ok()?,
+    );
+    if zeroed {
+        unsafe { core::ptr::write_bytes(vaddr.as_mut_ptr(), 0, size) };
+    }
+    Some(virt_to_phys(vaddr))
+}
+
+pub(super) fn dealloc_huge_frame(frame: PhysAddr, page_size: PageSize) {
+    let vaddr = phys_to_virt(frame);
+    global_allocator().dealloc_pages(vaddr.as_usize(), usize::from(page_size) / PAGE_SIZE_4K);
+}
+
+/// Adds a reference to the frame, for a new copy-on-write mapping.
+pub(crate) fn share_frame(frame: PhysAddr) {
+    *SHARED_FRAMES.lock().entry(frame).or_insert(1) += 1;
+}
+
+/// Drops a reference to the frame, and deallocates it if it was the last one.
+pub(super) fn put_frame(frame: PhysAddr) {
+    let mut shared = SHARED_FRAMES.lock();
+    if let Some(count) = shared.get_mut(&frame) {
+        *count -= 1;
+        if *count == 1 {
+            shared.remove(&frame);
+        }
+    } else {
+        drop(shared);
+        dealloc_frame(frame);
+    }
+}
+
+fn is_frame_shared(frame: PhysAddr) -> bool {
+    SHARED_FRAMES.lock().contains_key(&frame)
+}
+
 impl Backend {
     /// Creates a new allocation mapping backend.
     pub const fn new_alloc(populate: bool) -> Self {
-        Self::Alloc { populate }
+        Self::Alloc {
+            populate,
+            page_size: PageSize::Size4K,
+        }
+    }
+
+    /// Creates a new allocation mapping backend with huge pages of
+    /// `page_size`.
+    pub const fn new_alloc_huge(populate: bool, page_size: PageSize) -> Self {
+        Self::Alloc {
+            populate,
+            page_size,
+        }
     }
 
     pub(crate) fn map_alloc(
@@ -32,15 +94,37 @@ impl Backend {
         flags: MappingFlags,
         pt: &mut PageTable,
         populate: bool,
+        page_size: PageSize,
     ) -> bool {
         debug!(
-            "map_alloc: [{:#x}, {:#x}) {:?} (populate={})",
+            "map_alloc: [{:#x}, {:#x}) {:?} (populate={}, page_size={:?})",
             start,
             start + size,
             flags,
-            populate
+            populate,
+            page_size
         );
-        if populate {
+        if page_size.is_huge() {
+            if !populate {
+                // Huge pages are mapped on demand, no empty entries are needed.
+                return true;
+            }
+            let mut addr = start;
+            while addr < start + size {
+                let Some(frame) = alloc_huge_frame(page_size, true) else {
+                    return false;
+                };
+                match pt.map(addr, frame, page_size, flags) {
+                    Ok(tlb) => tlb.ignore(),
+                    Err(_) => {
+                        dealloc_huge_frame(frame, page_size);
+                        return false;
+                    }
+                }
+                addr += usize::from(page_size);
+            }
+            true
+        } else if populate {
             // allocate all possible physical frames for populated mapping.
             for addr in PageIter4K::new(start, start + size).unwrap() {
                 if let Some(frame) = alloc_frame(true) {
@@ -69,31 +153,115 @@ impl Backend {
         _populate: bool,
     ) -> bool {
         debug!("unmap_alloc: [{:#x}, {:#x})", start, start + size);
-        for addr in PageIter4K::new(start, start + size).unwrap() {
+        let end = start + size;
+        let mut addr = start;
+        while addr < end {
+            #[cfg(feature = "swap")]
+            Self::discard_swapped(addr, pt);
             if let Ok((frame, page_size, tlb)) = pt.unmap(addr) {
                 // Deallocate the physical frame if there is a mapping in the
-                // page table.
+                // page table, after other CPUs flush it. Huge pages across the
+                // range boundaries have been split by the address space.
+                tlb.flush();
                 if page_size.is_huge() {
-                    return false;
+                    release(pt, Released::HugeFrame(frame, page_size));
+                } else {
+                    release(pt, Released::Frame(frame));
                 }
-                tlb.flush();
-                dealloc_frame(frame);
+                addr += usize::from(page_size);
             } else {
                 // Deallocation is needn't if the page is not mapped.
+                addr += PAGE_SIZE_4K;
             }
         }
         true
     }
 
+    /// Changes the flags of the mapped pages of an allocation or file mapping.
+    ///
+    /// Pages not mapped yet or swapped out are skipped, as they are mapped with
+    /// the flags of the area later. Pages shared copy-on-write are kept
+    /// read-only, and so are the clean pages of shared file mappings, so that
+    /// writes to them still trigger page faults.
+    pub(super) fn protect_pages(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        #[cfg(feature = "fs")]
+        let track_dirty = matches!(self, Self::File { shared: true, .. });
+        #[cfg(not(feature = "fs"))]
+        let track_dirty = false;
+
+        let end = start + size;
+        let mut addr = start;
+        while addr < end {
+            let (frame, flags, page_size) = match pt.query(addr) {
+                Ok((frame, flags, page_size)) if !flags.is_empty() => (frame, flags, page_size),
+                _ => {
+                    addr += PAGE_SIZE_4K;
+                    continue;
+                }
+            };
+            let read_only =
+                !flags.contains(MappingFlags::WRITE) && (track_dirty || is_frame_shared(frame));
+            let flags = if read_only {
+                new_flags - MappingFlags::WRITE
+            } else {
+                new_flags
+            };
+            match pt.protect(addr, flags) {
+                Ok((_, tlb)) => tlb.flush(),
+                Err(_) => return false,
+            }
+            addr += usize::from(page_size);
+        }
+        true
+    }
+
     pub(crate) fn handle_page_fault_alloc(
         &self,
         vaddr: VirtAddr,
         orig_flags: MappingFlags,
         pt: &mut PageTable,
         populate: bool,
+        page_size: PageSize,
     ) -> bool {
+        if let Ok((frame, flags, mapped_size)) = pt.query(vaddr.align_down_4k()) {
+            if mapped_size.is_huge() {
+                return false; // Huge pages are not shared copy-on-write.
+            }
+            if !flags.is_empty() {
+                // The page is mapped, only writes to copy-on-write pages
+                // should trigger page faults.
+                return orig_flags.contains(MappingFlags::WRITE)
+                    && !flags.contains(MappingFlags::WRITE)
+                    && Self::break_cow(vaddr, frame, orig_flags, pt);
+            }
+            #[cfg(feature = "swap")]
+            if let Some(slot) = crate::swap::paddr_to_slot(frame) {
+                return Self::swap_in(vaddr, slot, orig_flags, pt);
+            }
+        }
         if populate {
             false // Populated mappings should not trigger page faults.
+        } else if page_size.is_huge() {
+            // Allocate contiguous frames lazily and map the huge page.
+            let Some(frame) = alloc_huge_frame(page_size, true) else {
+                return false;
+            };
+            match pt.map(vaddr.align_down(page_size), frame, page_size, orig_flags) {
+                Ok(tlb) => {
+                    tlb.flush();
+                    true
+                }
+                Err(_) => {
+                    dealloc_huge_frame(frame, page_size);
+                    false
+                }
+            }
         } else if let Some(frame) = alloc_frame(true) {
             // Allocate a physical frame lazily and map it to the fault address.
             // `vaddr` does not need to be aligned. It will be automatically
@@ -105,4 +273,134 @@ impl Backend {
             false
         }
     }
+
+    /// Makes a copy-on-write page writable, by copying the shared frame, or
+    /// reusing it if it is not shared anymore.
+    pub(super) fn break_cow(
+        vaddr: VirtAddr,
+        frame: PhysAddr,
+        flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let new_frame = if is_frame_shared(frame) {
+            let Some(new_frame) = alloc_frame(false) else {
+                return false;
+            };
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    phys_to_virt(frame).as_ptr(),
+                    phys_to_virt(new_frame).as_mut_ptr(),
+                    PAGE_SIZE_4K,
+                )
+            };
+            // Other CPUs may still read the old frame before they flush.
+            release(pt, Released::Frame(frame));
+            new_frame
+        } else {
+            frame
+        };
+        pt.remap(vaddr, new_frame, flags)
+            .map(|(_, tlb)| tlb.flush())
+            .is_ok()
+    }
+
+    /// Unmaps a page not accessed recently to swap it out, and returns its
+    /// frame and the allocated swap slot. Returns `None` if the page is not
+    /// swapped out.
+    ///
+    /// Pages shared copy-on-write, or with flags different from the area
+    /// (`orig_flags`) are skipped, as they can not be restored from the flags.
+    ///
+    /// The frame must be written by [`write_swapped_page`] after other CPUs
+    /// flush the page, so that it is not modified during the write.
+    ///
+    /// [`write_swapped_page`]: Self::write_swapped_page
+    #[cfg(feature = "swap")]
+    pub(crate) fn unmap_for_swap(
+        vaddr: VirtAddr,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> Option<(PhysAddr, usize)> {
+        let frame = match pt.query(vaddr) {
+            Ok((frame, flags, page_size))
+                if !page_size.is_huge() && flags == orig_flags && !is_frame_shared(frame) =>
+            {
+                frame
+            }
+            _ => return None,
+        };
+        // Give recently accessed pages a second chance.
+        if axhal::paging::test_and_clear_accessed(pt, vaddr).unwrap_or(true) {
+            return None;
+        }
+        let slot = crate::swap::alloc_slot()?;
+        match pt.remap(
+            vaddr,
+            crate::swap::slot_to_paddr(slot),
+            MappingFlags::empty(),
+        ) {
+            Ok((_, tlb)) => tlb.flush(),
+            Err(_) => {
+                crate::swap::free_slot(slot);
+                return None;
+            }
+        }
+        Some((frame, slot))
+    }
+
+    /// Writes the frame of a page unmapped by [`unmap_for_swap`] to the swap
+    /// slot, and frees the frame. The page is mapped back if the write fails.
+    ///
+    /// [`unmap_for_swap`]: Self::unmap_for_swap
+    #[cfg(feature = "swap")]
+    pub(crate) fn write_swapped_page(
+        vaddr: VirtAddr,
+        frame: PhysAddr,
+        slot: usize,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        if !crate::swap::write_slot(slot, frame) {
+            warn!("failed to swap out page {:#x}", vaddr);
+            crate::swap::free_slot(slot);
+            if let Ok((_, tlb)) = pt.remap(vaddr, frame, orig_flags) {
+                tlb.flush();
+            }
+            return false;
+        }
+        dealloc_frame(frame);
+        true
+    }
+
+    /// Reads a swapped-out page back to a new frame and maps it.
+    #[cfg(feature = "swap")]
+    fn swap_in(vaddr: VirtAddr, slot: usize, flags: MappingFlags, pt: &mut PageTable) -> bool {
+        let Some(frame) = alloc_frame(false) else {
+            return false;
+        };
+        if !crate::swap::read_and_free_slot(slot, frame) {
+            warn!("failed to swap in page {:#x}", vaddr);
+            dealloc_frame(frame);
+            return false;
+        }
+        pt.remap(vaddr, frame, flags)
+            .map(|(_, tlb)| tlb.flush())
+            .is_ok()
+    }
+
+    /// Frees the swap slot of the page if it is swapped out, and resets its
+    /// entry to an empty one.
+    #[cfg(feature = "swap")]
+    fn discard_swapped(vaddr: VirtAddr, pt: &mut PageTable) {
+        if let Ok((paddr, flags, _)) = pt.query(vaddr) {
+            if flags.is_empty() {
+                if let Some(slot) = crate::swap::paddr_to_slot(paddr) {
+                    crate::swap::free_slot(slot);
+                    if let Ok((_, tlb)) = pt.remap(vaddr, 0.into(), MappingFlags::empty()) {
+                        tlb.ignore();
+                    }
+                }
+            }
+        }
+    }
 }
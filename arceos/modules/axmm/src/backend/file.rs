@@ -0,0 +1,288 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+
+use axfs_vfs::VfsNodeRef;
+use axhal::mem::phys_to_virt;
+use axhal::paging::{MappingFlags, PageTable};
+use kspin::SpinNoIrq;
+use memory_addr::{MemoryAddr, PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use super::alloc::{alloc_frame, dealloc_frame, put_frame};
+use super::{release, Backend, Released};
+
+/// A file page mapped by shared mappings.
+struct CachedPage {
+    frame: PhysAddr,
+    /// The number of shared mappings that map the page.
+    mappings: usize,
+}
+
+/// The pages of files mapped by shared mappings, indexed by the file node (its
+/// address) and the offset of the page, so that all shared mappings of a page
+/// map the same frame and see the writes of each other.
+///
+/// A page is removed after the last mapping of it is unmapped, when it has
+/// been written back to the file.
+static PAGE_CACHE: SpinNoIrq<BTreeMap<(usize, u64), CachedPage>> = SpinNoIrq::new(BTreeMap::new());
+
+fn cache_key(node: &VfsNodeRef, offset: u64) -> (usize, u64) {
+    (Arc::as_ptr(node) as *const () as usize, offset)
+}
+
+/// Returns the frame of the cached file page for a new mapping of it, or reads
+/// the page to a new frame and caches it if it is not cached.
+fn get_cached_page(node: &VfsNodeRef, offset: u64) -> Option<PhysAddr> {
+    let key = cache_key(node, offset);
+    if let Some(page) = PAGE_CACHE.lock().get_mut(&key) {
+        page.mappings += 1;
+        return Some(page.frame);
+    }
+    // Read the file without holding the lock.
+    let frame = alloc_frame(true)?;
+    if !read_page(node, offset, frame) {
+        dealloc_frame(frame);
+        return None;
+    }
+    let mut cache = PAGE_CACHE.lock();
+    let page = cache
+        .entry(key)
+        .or_insert(CachedPage { frame, mappings: 0 });
+    page.mappings += 1;
+    let cached = page.frame;
+    drop(cache);
+    if cached != frame {
+        // Another mapping has read the page in the meantime.
+        dealloc_frame(frame);
+    }
+    Some(cached)
+}
+
+/// Drops a mapping of the cached file page. Returns `true` if it was the last
+/// one, and the frame should be deallocated.
+fn put_cached_page(node: &VfsNodeRef, offset: u64) -> bool {
+    let key = cache_key(node, offset);
+    let mut cache = PAGE_CACHE.lock();
+    let page = cache.get_mut(&key).expect("file page not cached");
+    page.mappings -= 1;
+    if page.mappings == 0 {
+        cache.remove(&key);
+        true
+    } else {
+        false
+    }
+}
+
+/// Reads a page of the file at `offset` to the frame. The part beyond the end
+/// of the file is left unchanged.
+fn read_page(node: &VfsNodeRef, offset: u64, frame: PhysAddr) -> bool {
+    let buf =
+        unsafe { core::slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), PAGE_SIZE_4K) };
+    let mut read_len = 0;
+    while read_len < PAGE_SIZE_4K {
+        match node.read_at(offset + read_len as u64, &mut buf[read_len..]) {
+            Ok(0) => break, // end of file
+            Ok(n) => read_len += n,
+            Err(e) => {
+                warn!("failed to read the mapped file at {:#x}: {:?}", offset, e);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Writes the frame back to a page of the file at `offset`. The part beyond
+/// the end of the file is discarded, the file size is not changed.
+fn write_page(node: &VfsNodeRef, offset: u64, frame: PhysAddr) -> bool {
+    let file_size = match node.get_attr() {
+        Ok(attr) => attr.size(),
+        Err(_) => return false,
+    };
+    if offset >= file_size {
+        return true;
+    }
+    let len = (file_size - offset).min(PAGE_SIZE_4K as u64) as usize;
+    let buf = unsafe { core::slice::from_raw_parts(phys_to_virt(frame).as_ptr(), len) };
+    let mut write_len = 0;
+    while write_len < len {
+        match node.write_at(offset + write_len as u64, &buf[write_len..]) {
+            Ok(0) => return false,
+            Ok(n) => write_len += n,
+            Err(e) => {
+                warn!(
+                    "failed to write back the mapped file at {:#x}: {:?}",
+                    offset, e
+                );
+                return false;
+            }
+        }
+    }
+    true
+}
+
+impl Backend {
+    /// Creates a new file mapping backend, which maps the file from `offset`
+    /// to the virtual address `start`.
+    pub fn new_file(node: VfsNodeRef, start: VirtAddr, offset: u64, shared: bool) -> Self {
+        Self::File {
+            node,
+            start,
+            offset,
+            shared,
+        }
+    }
+
+    /// Returns the file, the offset in it of the page at `vaddr`, and whether
+    /// the mapping is shared.
+    fn file_page(&self, vaddr: VirtAddr) -> (&VfsNodeRef, u64, bool) {
+        match self {
+            Self::File {
+                node,
+                start,
+                offset,
+                shared,
+            } => (
+                node,
+                offset + (vaddr.as_usize() - start.as_usize()) as u64,
+                *shared,
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    pub(crate) fn map_file(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let (_, offset, shared) = self.file_page(start);
+        debug!(
+            "map_file: [{:#x}, {:#x}) {:?} (offset={:#x}, shared={})",
+            start,
+            start + size,
+            flags,
+            offset,
+            shared
+        );
+        // Map to a empty entry for on-demand mapping.
+        let flags = MappingFlags::empty();
+        pt.map_region(start, |_| 0.into(), size, flags, false, false)
+            .map(|tlb| tlb.ignore())
+            .is_ok()
+    }
+
+    pub(crate) fn unmap_file(&self, start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
+        debug!("unmap_file: [{:#x}, {:#x})", start, start + size);
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            let (node, offset, shared) = self.file_page(addr);
+            if let Ok((frame, flags, _)) = pt.query(addr) {
+                // Only the pages written are writable in shared mappings. They
+                // have been written back by the address space, unless they are
+                // written again in the meantime.
+                if shared && flags.contains(MappingFlags::WRITE) && !write_page(node, offset, frame)
+                {
+                    warn!("failed to write back the unmapped page {:#x}", addr);
+                }
+            }
+            if let Ok((frame, page_size, tlb)) = pt.unmap(addr) {
+                if page_size.is_huge() {
+                    return false;
+                }
+                tlb.flush();
+                if !shared || put_cached_page(node, offset) {
+                    release(pt, Released::Frame(frame));
+                }
+            }
+        }
+        true
+    }
+
+    /// Writes the modified pages of a shared file mapping back to the file,
+    /// and makes them read-only to track further writes.
+    pub(crate) fn sync_file(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            let (node, offset, shared) = self.file_page(addr);
+            if !shared {
+                return true;
+            }
+            let frame = match pt.query(addr) {
+                Ok((frame, flags, _)) if flags.contains(MappingFlags::WRITE) => frame,
+                _ => continue, // not mapped or not modified
+            };
+            if !write_page(node, offset, frame) {
+                return false;
+            }
+            match pt.protect(addr, orig_flags - MappingFlags::WRITE) {
+                Ok((_, tlb)) => tlb.flush(),
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+
+    pub(crate) fn handle_page_fault_file(
+        &self,
+        vaddr: VirtAddr,
+        access_flags: MappingFlags,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let vaddr = vaddr.align_down_4k();
+        let (node, offset, shared) = self.file_page(vaddr);
+        let is_write = access_flags.contains(MappingFlags::WRITE);
+        if let Ok((frame, flags, _)) = pt.query(vaddr) {
+            if !flags.is_empty() {
+                // The page is mapped, only writes to read-only pages of
+                // writable mappings should trigger page faults.
+                if !is_write
+                    || flags.contains(MappingFlags::WRITE)
+                    || !orig_flags.contains(MappingFlags::WRITE)
+                {
+                    return false;
+                }
+                return if shared {
+                    // The first write to a clean page, which becomes dirty.
+                    pt.protect(vaddr, orig_flags)
+                        .map(|(_, tlb)| tlb.flush())
+                        .is_ok()
+                } else {
+                    Self::break_cow(vaddr, frame, orig_flags, pt)
+                };
+            }
+        }
+
+        let frame = if shared {
+            match get_cached_page(node, offset) {
+                Some(frame) => frame,
+                None => return false,
+            }
+        } else {
+            let Some(frame) = alloc_frame(true) else {
+                return false;
+            };
+            if !read_page(node, offset, frame) {
+                put_frame(frame);
+                return false;
+            }
+            frame
+        };
+        // Pages of shared mappings are made writable on the first write, so
+        // that only modified pages are written back.
+        let flags = if shared && !is_write {
+            orig_flags - MappingFlags::WRITE
+        } else {
+            orig_flags
+        };
+        pt.remap(vaddr, frame, flags)
+            .map(|(_, tlb)| tlb.flush())
+            .is_ok()
+    }
+}
@@ -26,7 +26,7 @@ impl Backend {
             va_to_pa(start + size),
             flags
         );
-        pt.map_region(start, va_to_pa, size, flags, false, false)
+        pt.map_region(start, va_to_pa, size, flags, true, false)
             .map(|tlb| tlb.ignore()) // TLB flush on map is unnecessary, as there are no outdated mappings.
             .is_ok()
     }
@@ -1,21 +1,83 @@
 //! Memory mapping backends.
 #![allow(dead_code)]
 
-use axhal::paging::{MappingFlags, PageTable};
-use memory_addr::VirtAddr;
+use ::alloc::sync::Arc;
+use ::alloc::vec::Vec;
+
+use axhal::paging::{MappingFlags, PageSize, PageTable};
+use kspin::SpinNoIrq;
+use memory_addr::{PhysAddr, VirtAddr};
 use memory_set::MappingBackend;
 
 mod alloc;
+#[cfg(feature = "fs")]
+mod file;
 mod linear;
+mod shared;
+
+pub(crate) use self::alloc::share_frame;
+pub use self::shared::SharedMemory;
+
+/// Frames released by unmapping or remapping pages, which can be freed only
+/// after the TLB entries of the pages are flushed on all CPUs.
+enum Released {
+    /// A frame (or a share of it) of an allocation or file mapping.
+    Frame(PhysAddr),
+    /// Contiguous frames of a huge page.
+    HugeFrame(PhysAddr, PageSize),
+    /// A mapping of a shared memory object, which keeps its frames alive.
+    Shared(Arc<SharedMemory>),
+}
+
+/// Released frames waiting for [`free_released`], with the roots of the page
+/// tables they were mapped in.
+static RELEASED: SpinNoIrq<Vec<(PhysAddr, Released)>> = SpinNoIrq::new(Vec::new());
+
+/// Defers freeing the frames unmapped from `pt` until [`free_released`].
+fn release(pt: &PageTable, released: Released) {
+    RELEASED.lock().push((pt.root_paddr(), released));
+}
+
+/// Frees the frames released from the page table whose root is `root`. It must
+/// be called after the TLB entries of the pages are flushed on all CPUs.
+pub(crate) fn free_released(root: PhysAddr) {
+    let released: Vec<_> = {
+        let mut list = RELEASED.lock();
+        if list.is_empty() {
+            return;
+        }
+        let (released, others) = core::mem::take(&mut *list)
+            .into_iter()
+            .partition(|(pt_root, _)| *pt_root == root);
+        *list = others;
+        released
+    };
+    for (_, released) in released {
+        match released {
+            Released::Frame(frame) => self::alloc::put_frame(frame),
+            Released::HugeFrame(frame, page_size) => {
+                self::alloc::dealloc_huge_frame(frame, page_size)
+            }
+            Released::Shared(shm) => drop(shm),
+        }
+    }
+}
 
 /// A unified enum type for different memory mapping backends.
 ///
-/// Currently, two backends are implemented:
+/// Currently, the following backends are implemented:
 ///
 /// - **Linear**: used for linear mappings. The target physical frames are
 ///   contiguous and their addresses should be known when creating the mapping.
 /// - **Allocation**: used in general, or for lazy mappings. The target physical
-///   frames are obtained from the global allocator.
+///   frames are obtained from the global allocator. The frames can be shared
+///   copy-on-write by [`AddrSpace::clone_cow`](crate::AddrSpace::clone_cow),
+///   unless they are huge pages.
+/// - **File** (with the `fs` feature): used for file mappings. The pages are
+///   read from the file on demand, and written back if the mapping is shared.
+/// - **Shared**: used for shared memory. The target physical frames belong to
+///   a [`SharedMemory`] object, which may be mapped into several address
+///   spaces.
 #[derive(Clone)]
 pub enum Backend {
     /// Linear mapping backend.
@@ -33,9 +95,45 @@ pub enum Backend {
     /// mapping is created, and no page faults are triggered during the memory
     /// access. Otherwise, the physical frames are allocated on demand (by
     /// handling page faults).
+    ///
+    /// If `page_size` is a huge page size, each page is mapped to contiguous
+    /// physical frames of that size.
     Alloc {
         /// Whether to populate the physical frames when creating the mapping.
         populate: bool,
+        /// The size of the pages to map.
+        page_size: PageSize,
+    },
+    /// File mapping backend.
+    ///
+    /// Pages are allocated and read from the file on the first access (by
+    /// handling page faults), and the part beyond the end of the file is
+    /// zero-filled. If `shared` is `true`, the pages are shared with other
+    /// shared mappings of the file node through a page cache, and the
+    /// modified pages are written back to the file when they are unmapped or
+    /// synced by [`AddrSpace::msync`](crate::AddrSpace::msync). Otherwise, the
+    /// changes are private to the mapping.
+    #[cfg(feature = "fs")]
+    File {
+        /// The mapped file.
+        node: axfs_vfs::VfsNodeRef,
+        /// The virtual address where the mapping starts.
+        start: VirtAddr,
+        /// The offset in the file mapped at `start`.
+        offset: u64,
+        /// Whether the changes are written back to the file.
+        shared: bool,
+    },
+    /// Shared memory mapping backend.
+    ///
+    /// All pages are mapped to the frames of the shared memory object when the
+    /// mapping is created. The frames are kept alive by the mappings, and
+    /// deallocated with the object after the last mapping is removed.
+    Shared {
+        /// The mapped shared memory object.
+        shm: Arc<SharedMemory>,
+        /// The virtual address where the start of the object is mapped.
+        start: VirtAddr,
     },
 }
 
@@ -46,14 +144,26 @@ impl MappingBackend for Backend {
     fn map(&self, start: VirtAddr, size: usize, flags: MappingFlags, pt: &mut PageTable) -> bool {
         match *self {
             Self::Linear { pa_va_offset } => self.map_linear(start, size, flags, pt, pa_va_offset),
-            Self::Alloc { populate } => self.map_alloc(start, size, flags, pt, populate),
+            Self::Alloc {
+                populate,
+                page_size,
+            } => self.map_alloc(start, size, flags, pt, populate, page_size),
+            #[cfg(feature = "fs")]
+            Self::File { .. } => self.map_file(start, size, flags, pt),
+            Self::Shared {
+                ref shm,
+                start: shm_start,
+            } => self.map_shared(start, size, flags, pt, shm, shm_start),
         }
     }
 
     fn unmap(&self, start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
         match *self {
             Self::Linear { pa_va_offset } => self.unmap_linear(start, size, pt, pa_va_offset),
-            Self::Alloc { populate } => self.unmap_alloc(start, size, pt, populate),
+            Self::Alloc { populate, .. } => self.unmap_alloc(start, size, pt, populate),
+            #[cfg(feature = "fs")]
+            Self::File { .. } => self.unmap_file(start, size, pt),
+            Self::Shared { .. } => self.unmap_shared(start, size, pt),
         }
     }
 
@@ -64,25 +174,61 @@ impl MappingBackend for Backend {
         new_flags: Self::Flags,
         page_table: &mut Self::PageTable,
     ) -> bool {
-        page_table
-            .protect_region(start, size, new_flags, true)
-            .map(|tlb| tlb.ignore())
-            .is_ok()
+        match *self {
+            Self::Linear { .. } | Self::Shared { .. } => page_table
+                .protect_region(start, size, new_flags, true)
+                // Flushed by page, and on other CPUs by the address space.
+                .map(|tlb| tlb.ignore())
+                .is_ok(),
+            _ => self.protect_pages(start, size, new_flags, page_table),
+        }
     }
 }
 
 impl Backend {
+    #[cfg_attr(not(feature = "fs"), allow(unused_variables))]
     pub(crate) fn handle_page_fault(
         &self,
         vaddr: VirtAddr,
+        access_flags: MappingFlags,
         orig_flags: MappingFlags,
         page_table: &mut PageTable,
     ) -> bool {
         match *self {
-            Self::Linear { .. } => false, // Linear mappings should not trigger page faults.
-            Self::Alloc { populate } => {
-                self.handle_page_fault_alloc(vaddr, orig_flags, page_table, populate)
+            // Linear and shared mappings should not trigger page faults.
+            Self::Linear { .. } | Self::Shared { .. } => false,
+            Self::Alloc {
+                populate,
+                page_size,
+            } => self.handle_page_fault_alloc(vaddr, orig_flags, page_table, populate, page_size),
+            #[cfg(feature = "fs")]
+            Self::File { .. } => {
+                self.handle_page_fault_file(vaddr, access_flags, orig_flags, page_table)
+            }
+        }
+    }
+
+    /// Copies the present page at `vaddr` if it is shared copy-on-write, so
+    /// that the kernel can write it without affecting other address spaces.
+    /// The page flags are kept, e.g., the page of a read-only area.
+    pub(crate) fn unshare_page(&self, vaddr: VirtAddr, page_table: &mut PageTable) -> bool {
+        match *self {
+            Self::Alloc { page_size, .. } if page_size.is_huge() => true,
+            #[cfg(feature = "fs")]
+            Self::File { shared: true, .. } => true,
+            #[cfg(feature = "fs")]
+            Self::File { .. } => Self::unshare_frame(vaddr, page_table),
+            Self::Alloc { .. } => Self::unshare_frame(vaddr, page_table),
+            Self::Linear { .. } | Self::Shared { .. } => true,
+        }
+    }
+
+    fn unshare_frame(vaddr: VirtAddr, page_table: &mut PageTable) -> bool {
+        match page_table.query(vaddr.align_down_4k()) {
+            Ok((frame, flags, _)) if !flags.is_empty() => {
+                Self::break_cow(vaddr, frame, flags, page_table)
             }
+            _ => false,
         }
     }
 }
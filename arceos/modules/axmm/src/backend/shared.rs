@@ -0,0 +1,93 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use axerrno::{AxError, AxResult};
+use axhal::paging::{MappingFlags, PageSize, PageTable};
+use memory_addr::{MemoryAddr, PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use super::alloc::{alloc_frame, dealloc_frame};
+use super::{release, Backend, Released};
+
+/// A region of physical memory that can be mapped into multiple address
+/// spaces, e.g., for `shmget` or `MAP_SHARED | MAP_ANONYMOUS`.
+///
+/// The frames are allocated and zeroed on creation, and deallocated when the
+/// object is dropped, i.e., after the last mapping of it is removed and the
+/// last handle is dropped.
+pub struct SharedMemory {
+    frames: Vec<PhysAddr>,
+}
+
+impl SharedMemory {
+    /// Creates a shared memory region of `size` bytes, rounded up to pages.
+    pub fn new(size: usize) -> AxResult<Arc<Self>> {
+        let num_pages = size.align_up_4k() / PAGE_SIZE_4K;
+        let mut shm = Self {
+            frames: Vec::with_capacity(num_pages),
+        };
+        for _ in 0..num_pages {
+            // The allocated frames are deallocated on drop if it fails.
+            shm.frames.push(alloc_frame(true).ok_or(AxError::NoMemory)?);
+        }
+        Ok(Arc::new(shm))
+    }
+
+    /// Returns the size of the region in bytes.
+    pub fn size(&self) -> usize {
+        self.frames.len() * PAGE_SIZE_4K
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        for &frame in &self.frames {
+            dealloc_frame(frame);
+        }
+    }
+}
+
+impl Backend {
+    /// Creates a new shared memory mapping backend, which maps the shared
+    /// memory region to the virtual address `start`.
+    pub fn new_shared(shm: Arc<SharedMemory>, start: VirtAddr) -> Self {
+        Self::Shared { shm, start }
+    }
+
+    pub(crate) fn map_shared(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        pt: &mut PageTable,
+        shm: &SharedMemory,
+        shm_start: VirtAddr,
+    ) -> bool {
+        debug!(
+            "map_shared: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            flags
+        );
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            let frame = shm.frames[(addr.as_usize() - shm_start.as_usize()) / PAGE_SIZE_4K];
+            if let Ok(tlb) = pt.map(addr, frame, PageSize::Size4K, flags) {
+                tlb.ignore(); // TLB flush on map is unnecessary, as there are no outdated mappings.
+            } else {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub(crate) fn unmap_shared(&self, start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
+        debug!("unmap_shared: [{:#x}, {:#x})", start, start + size);
+        // The frames are deallocated when the shared memory object is dropped,
+        // so keep it alive until other CPUs flush the pages.
+        if let Self::Shared { shm, .. } = self {
+            release(pt, Released::Shared(shm.clone()));
+        }
+        pt.unmap_region(start, size, true)
+            .map(|tlb| tlb.ignore()) // flush each page on unmap, do not flush the entire TLB.
+            .is_ok()
+    }
+}
@@ -0,0 +1,56 @@
+//! Layout of user address spaces.
+
+use memory_addr::{MemoryAddr, VirtAddr};
+
+/// The alignment of the load bias, which is enough for the segment alignment
+/// of most executables.
+const LOAD_BIAS_ALIGN: usize = 0x20_0000;
+
+/// Where to put the user stack, the memory mappings and the executable in a
+/// user address space.
+///
+/// With the `aslr` feature, the positions are moved by random offsets (seeded
+/// from [`axhal::misc::random`]) when the address space is created.
+#[derive(Debug, Clone, Copy)]
+pub struct UserLayout {
+    /// The top of the user stack.
+    pub stack_top: VirtAddr,
+    /// Where to search for free areas for memory mappings without address
+    /// hints.
+    pub mmap_base: VirtAddr,
+    /// The offset to load position-independent executables (`ET_DYN`) at.
+    pub load_bias: usize,
+}
+
+impl UserLayout {
+    /// Creates the layout of the address space `[base, base + size)`.
+    ///
+    /// By default, the stack is at the end, the memory mappings are from the
+    /// base, and position-independent executables are loaded at 1/8 of the
+    /// size.
+    pub fn new(base: VirtAddr, size: usize) -> Self {
+        let layout = Self {
+            stack_top: base + size,
+            mmap_base: base,
+            load_bias: (base.as_usize() + size / 8).align_down(LOAD_BIAS_ALIGN),
+        };
+        #[cfg(feature = "aslr")]
+        let layout = Self {
+            stack_top: layout.stack_top - random_offset(size / 64, memory_addr::PAGE_SIZE_4K),
+            mmap_base: layout.mmap_base
+                + size / 4
+                + random_offset(size / 4, memory_addr::PAGE_SIZE_4K),
+            load_bias: layout.load_bias + random_offset(size / 8, LOAD_BIAS_ALIGN),
+        };
+        layout
+    }
+}
+
+/// Returns a random multiple of `align` less than `range`.
+#[cfg(feature = "aslr")]
+fn random_offset(range: usize, align: usize) -> usize {
+    match range / align {
+        0 => 0,
+        n => (axhal::misc::random() as usize % n) * align,
+    }
+}
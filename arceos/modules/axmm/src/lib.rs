@@ -1,20 +1,30 @@
 //! [ArceOS](https://github.com/arceos-org/arceos) memory management module.
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 #[macro_use]
 extern crate log;
 extern crate alloc;
 
+#[cfg(test)]
+mod tests;
+
 mod aspace;
 mod backend;
+mod layout;
+mod maps;
+#[cfg(feature = "swap")]
+pub mod swap;
 
 pub use self::aspace::AddrSpace;
+pub use self::backend::SharedMemory;
+pub use self::layout::UserLayout;
+pub use self::maps::{AreaInfo, AreaKind};
 
 use axerrno::{AxError, AxResult};
 use axhal::mem::phys_to_virt;
 use axhal::paging::PagingError;
-use kspin::SpinNoIrq;
+use kspin::{SpinNoIrq, SpinNoIrqGuard};
 use lazyinit::LazyInit;
 use memory_addr::{va, PhysAddr, VirtAddr};
 use memory_set::MappingError;
@@ -22,7 +32,27 @@ use memory_set::MappingError;
 const USER_ASPACE_BASE: usize = 0x0000;
 const USER_ASPACE_SIZE: usize = 0x40_0000_0000;
 
-static KERNEL_ASPACE: LazyInit<SpinNoIrq<AddrSpace>> = LazyInit::new();
+static KERNEL_ASPACE: LazyInit<KernelAspace> = LazyInit::new();
+
+/// The kernel address space protected by a spin lock.
+///
+/// Changing the kernel mappings waits for all other CPUs to flush their TLBs
+/// with the lock held, so the CPUs waiting for the lock handle the flush
+/// requests while spinning, even with IRQs disabled.
+pub struct KernelAspace(SpinNoIrq<AddrSpace>);
+
+impl KernelAspace {
+    /// Locks the kernel address space, with IRQs and preemption disabled.
+    pub fn lock(&self) -> SpinNoIrqGuard<'_, AddrSpace> {
+        loop {
+            if let Some(guard) = self.0.try_lock() {
+                return guard;
+            }
+            axhal::paging::handle_pending_shootdown();
+            core::hint::spin_loop();
+        }
+    }
+}
 
 fn mapping_err_to_ax_err(err: MappingError) -> AxError {
     warn!("Mapping error: {:?}", err);
@@ -64,7 +94,7 @@ pub fn new_kernel_aspace() -> AxResult<AddrSpace> {
 }
 
 /// Returns the globally unique kernel address space.
-pub fn kernel_aspace() -> &'static SpinNoIrq<AddrSpace> {
+pub fn kernel_aspace() -> &'static KernelAspace {
     &KERNEL_ASPACE
 }
 
@@ -82,7 +112,7 @@ pub fn init_memory_management() {
 
     let kernel_aspace = new_kernel_aspace().expect("failed to initialize kernel address space");
     debug!("kernel address space init OK: {:#x?}", kernel_aspace);
-    KERNEL_ASPACE.init_once(SpinNoIrq::new(kernel_aspace));
+    KERNEL_ASPACE.init_once(KernelAspace(SpinNoIrq::new(kernel_aspace)));
     axhal::paging::set_kernel_page_table_root(kernel_page_table_root());
 }
 
@@ -0,0 +1,157 @@
+//! Information of memory areas, and the generator of Linux-format
+//! `/proc/<pid>/maps` and `/proc/<pid>/smaps`.
+
+use core::fmt::{self, Write};
+
+use axhal::paging::{MappingFlags, PageSize};
+use memory_addr::{VirtAddr, PAGE_SIZE_4K};
+
+use crate::AddrSpace;
+
+/// The kind of the backend of a memory area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AreaKind {
+    /// Linear mapping to contiguous physical memory.
+    Linear,
+    /// Anonymous memory allocated from the global allocator.
+    Alloc {
+        /// Whether the frames are allocated when the area is mapped.
+        populate: bool,
+    },
+    /// File mapping.
+    #[cfg(feature = "fs")]
+    File {
+        /// The offset in the file mapped at the start of the area.
+        offset: u64,
+        /// Whether the changes are written back to the file.
+        shared: bool,
+    },
+    /// Mapping of a [`SharedMemory`](crate::SharedMemory) object.
+    Shared,
+}
+
+/// Information of a memory area, returned by [`AddrSpace::areas`].
+#[derive(Debug, Clone)]
+pub struct AreaInfo {
+    /// The start address of the area.
+    pub start: VirtAddr,
+    /// The end address of the area (exclusive).
+    pub end: VirtAddr,
+    /// The mapping flags of the area.
+    pub flags: MappingFlags,
+    /// The kind of the backend.
+    pub kind: AreaKind,
+    /// The size of the pages the backend maps.
+    pub page_size: PageSize,
+    /// The number of 4K pages mapped to physical memory.
+    pub resident_pages: usize,
+    /// The number of 4K pages swapped out (always 0 without the `swap`
+    /// feature).
+    pub swapped_pages: usize,
+}
+
+impl AreaInfo {
+    /// Returns the size of the area in bytes.
+    pub fn size(&self) -> usize {
+        self.end.as_usize() - self.start.as_usize()
+    }
+
+    /// Returns the number of 4K pages reserved by the area, resident or not.
+    pub fn reserved_pages(&self) -> usize {
+        self.size() / PAGE_SIZE_4K
+    }
+
+    /// Whether the changes to the area are visible to other mappings.
+    pub fn is_shared(&self) -> bool {
+        match self.kind {
+            #[cfg(feature = "fs")]
+            AreaKind::File { shared, .. } => shared,
+            AreaKind::Shared => true,
+            _ => false,
+        }
+    }
+
+    /// Writes the line of the area in `/proc/<pid>/maps`.
+    pub fn write_maps_line<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let offset = match self.kind {
+            #[cfg(feature = "fs")]
+            AreaKind::File { offset, .. } => offset,
+            _ => 0,
+        };
+        // No device, inode or path name is recorded for the areas.
+        writeln!(
+            w,
+            "{:08x}-{:08x} {}{}{}{} {:08x} 00:00 0",
+            self.start.as_usize(),
+            self.end.as_usize(),
+            flag_char(self.flags, MappingFlags::READ, 'r'),
+            flag_char(self.flags, MappingFlags::WRITE, 'w'),
+            flag_char(self.flags, MappingFlags::EXECUTE, 'x'),
+            if self.is_shared() { 's' } else { 'p' },
+            offset
+        )
+    }
+
+    /// Writes the entry of the area in `/proc/<pid>/smaps`, which is the line
+    /// in `maps` followed by the memory usage.
+    pub fn write_smaps_entry<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let page_kb = usize::from(self.page_size) / 1024;
+        let rss_kb = self.resident_pages * PAGE_SIZE_4K / 1024;
+        let anon_kb = match self.kind {
+            AreaKind::Alloc { .. } => rss_kb,
+            _ => 0,
+        };
+        let anon_huge_kb = if self.page_size.is_huge() { anon_kb } else { 0 };
+
+        self.write_maps_line(w)?;
+        write_kb(w, "Size:", self.size() / 1024)?;
+        write_kb(w, "KernelPageSize:", page_kb)?;
+        write_kb(w, "MMUPageSize:", page_kb)?;
+        write_kb(w, "Rss:", rss_kb)?;
+        write_kb(w, "Anonymous:", anon_kb)?;
+        write_kb(w, "AnonHugePages:", anon_huge_kb)?;
+        write_kb(w, "Swap:", self.swapped_pages * PAGE_SIZE_4K / 1024)?;
+        w.write_str("VmFlags:")?;
+        for (flag, name) in [
+            (MappingFlags::READ, "rd"),
+            (MappingFlags::WRITE, "wr"),
+            (MappingFlags::EXECUTE, "ex"),
+        ] {
+            if self.flags.contains(flag) {
+                write!(w, " {}", name)?;
+            }
+        }
+        if self.is_shared() {
+            w.write_str(" sh")?;
+        }
+        if self.page_size.is_huge() {
+            w.write_str(" ht")?;
+        }
+        w.write_char('\n')
+    }
+}
+
+fn flag_char(flags: MappingFlags, flag: MappingFlags, c: char) -> char {
+    if flags.contains(flag) {
+        c
+    } else {
+        '-'
+    }
+}
+
+fn write_kb<W: Write>(w: &mut W, name: &str, kb: usize) -> fmt::Result {
+    writeln!(w, "{:<16}{:>8} kB", name, kb)
+}
+
+impl AddrSpace {
+    /// Writes the memory areas in the format of Linux `/proc/<pid>/maps`.
+    pub fn write_maps<W: Write>(&self, w: &mut W) -> fmt::Result {
+        self.areas().try_for_each(|area| area.write_maps_line(w))
+    }
+
+    /// Writes the memory areas and their memory usage in the format of Linux
+    /// `/proc/<pid>/smaps`.
+    pub fn write_smaps<W: Write>(&self, w: &mut W) -> fmt::Result {
+        self.areas().try_for_each(|area| area.write_smaps_entry(w))
+    }
+}
@@ -0,0 +1,183 @@
+//! Swapping anonymous pages out to a block device.
+//!
+//! The pages of allocation mappings that are not accessed recently can be
+//! written to slots of the swap area by [`AddrSpace::swap_out`], which follows
+//! the clock algorithm with the accessed flags of the page table entries. The
+//! entry of a swapped-out page is not present, and holds the slot number in
+//! place of the physical address. The page is read back when it is accessed
+//! again, by [`AddrSpace::handle_page_fault`].
+//!
+//! Address spaces swap their own pages out before handling page faults if the
+//! free memory is low. Other address spaces may be reclaimed by calling
+//! [`AddrSpace::swap_out`] explicitly.
+//!
+//! [`AddrSpace::swap_out`]: crate::AddrSpace::swap_out
+//! [`AddrSpace::handle_page_fault`]: crate::AddrSpace::handle_page_fault
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use axalloc::global_allocator;
+use axdriver::prelude::*;
+use axhal::mem::phys_to_virt;
+use kspin::{SpinNoIrq, SpinNoPreempt};
+use lazyinit::LazyInit;
+use memory_addr::{PhysAddr, PAGE_SIZE_4K};
+
+/// Swap pages out when the number of free pages is below it.
+const LOW_FREE_PAGES: usize = 1024;
+
+/// The number of pages to swap out when the free memory is low.
+pub(crate) const SWAP_OUT_BATCH: usize = 32;
+
+struct SwapArea {
+    /// The device is locked only for I/O, with IRQs enabled.
+    dev: SpinNoPreempt<AxBlockDevice>,
+    blocks_per_slot: u64,
+    slots: SpinNoIrq<SwapSlots>,
+}
+
+struct SwapSlots {
+    num_slots: usize,
+    /// One bit for each slot, set if the slot is used.
+    used: Vec<u64>,
+    /// Where to start searching for a free slot.
+    next: usize,
+    free_slots: usize,
+}
+
+static SWAP_AREA: LazyInit<SwapArea> = LazyInit::new();
+
+impl SwapSlots {
+    fn alloc_slot(&mut self) -> Option<usize> {
+        if self.free_slots == 0 {
+            return None;
+        }
+        for i in 0..self.num_slots {
+            let slot = (self.next + i) % self.num_slots;
+            let (word, bit) = (slot / 64, slot % 64);
+            if self.used[word] & (1 << bit) == 0 {
+                self.used[word] |= 1 << bit;
+                self.next = slot + 1;
+                self.free_slots -= 1;
+                return Some(slot);
+            }
+        }
+        None
+    }
+
+    fn free_slot(&mut self, slot: usize) {
+        let (word, bit) = (slot / 64, slot % 64);
+        debug_assert!(self.used[word] & (1 << bit) != 0);
+        self.used[word] &= !(1 << bit);
+        self.free_slots += 1;
+    }
+}
+
+impl SwapArea {
+    fn page_buf(frame: PhysAddr) -> &'static mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), PAGE_SIZE_4K) }
+    }
+
+    fn write_slot(&self, slot: usize, frame: PhysAddr) -> bool {
+        let mut dev = self.dev.lock();
+        let block_size = dev.block_size();
+        let first_block = slot as u64 * self.blocks_per_slot;
+        let buf = Self::page_buf(frame);
+        (0..self.blocks_per_slot).all(|i| {
+            let block = &buf[i as usize * block_size..(i as usize + 1) * block_size];
+            dev.write_block(first_block + i, block).is_ok()
+        })
+    }
+
+    fn read_slot(&self, slot: usize, frame: PhysAddr) -> bool {
+        let mut dev = self.dev.lock();
+        let block_size = dev.block_size();
+        let first_block = slot as u64 * self.blocks_per_slot;
+        let buf = Self::page_buf(frame);
+        (0..self.blocks_per_slot).all(|i| {
+            let block = &mut buf[i as usize * block_size..(i as usize + 1) * block_size];
+            dev.read_block(first_block + i, block).is_ok()
+        })
+    }
+}
+
+/// Uses the whole block device as the swap area.
+pub fn init_swap(dev: AxBlockDevice) {
+    let block_size = dev.block_size();
+    assert!(
+        block_size <= PAGE_SIZE_4K && PAGE_SIZE_4K % block_size == 0,
+        "unsupported block size {} for swap",
+        block_size
+    );
+    let blocks_per_slot = (PAGE_SIZE_4K / block_size) as u64;
+    let num_slots = (dev.num_blocks() / blocks_per_slot) as usize;
+    info!(
+        "Initialize swap area: {} ({} pages)",
+        dev.device_name(),
+        num_slots
+    );
+    SWAP_AREA.init_once(SwapArea {
+        dev: SpinNoPreempt::new(dev),
+        blocks_per_slot,
+        slots: SpinNoIrq::new(SwapSlots {
+            num_slots,
+            used: vec![0; num_slots.div_ceil(64)],
+            next: 0,
+            free_slots: num_slots,
+        }),
+    });
+}
+
+/// Returns the number of free and total pages in the swap area.
+pub fn swap_usage() -> (usize, usize) {
+    match SWAP_AREA.get() {
+        Some(area) => {
+            let slots = area.slots.lock();
+            (slots.free_slots, slots.num_slots)
+        }
+        None => (0, 0),
+    }
+}
+
+/// Whether pages should be swapped out to free memory.
+pub(crate) fn memory_low() -> bool {
+    SWAP_AREA.is_inited() && global_allocator().available_pages() < LOW_FREE_PAGES
+}
+
+/// Returns the value in place of the physical address in the page table entry
+/// of a page swapped out to the slot.
+pub(crate) fn slot_to_paddr(slot: usize) -> PhysAddr {
+    PhysAddr::from((slot + 1) * PAGE_SIZE_4K)
+}
+
+/// Returns the slot of a swapped-out page from the "physical address" of its
+/// page table entry (which is not present), or `None` if the page is not
+/// swapped out, i.e., it is not mapped yet.
+pub(crate) fn paddr_to_slot(paddr: PhysAddr) -> Option<usize> {
+    (paddr.as_usize() / PAGE_SIZE_4K).checked_sub(1)
+}
+
+/// Allocates a free slot in the swap area.
+pub(crate) fn alloc_slot() -> Option<usize> {
+    SWAP_AREA.get()?.slots.lock().alloc_slot()
+}
+
+/// Frees a slot whose page is not needed anymore.
+pub(crate) fn free_slot(slot: usize) {
+    SWAP_AREA.slots.lock().free_slot(slot);
+}
+
+/// Writes the frame to the slot.
+pub(crate) fn write_slot(slot: usize, frame: PhysAddr) -> bool {
+    SWAP_AREA.write_slot(slot, frame)
+}
+
+/// Reads the slot to the frame, and frees the slot if succeeded.
+pub(crate) fn read_and_free_slot(slot: usize, frame: PhysAddr) -> bool {
+    if !SWAP_AREA.read_slot(slot, frame) {
+        return false;
+    }
+    free_slot(slot);
+    true
+}
@@ -0,0 +1,152 @@
+use std::sync::Once;
+
+use axhal::paging::MappingFlags;
+use kspin::SpinNoIrq;
+use memory_addr::{va, VirtAddr, PAGE_SIZE_4K};
+
+use crate::{AddrSpace, KernelAspace, KERNEL_ASPACE};
+
+static INIT: Once = Once::new();
+
+const USER_BASE: usize = 0x1000_0000;
+const USER_SIZE: usize = 0x1000_0000;
+
+/// Initializes the global allocator with memory of the host, whose addresses
+/// are also the physical addresses on the dummy platform, and the kernel
+/// address space.
+fn init() {
+    INIT.call_once(|| {
+        const HEAP_SIZE: usize = 0x100_0000; // 16M
+        let layout = std::alloc::Layout::from_size_align(HEAP_SIZE, PAGE_SIZE_4K).unwrap();
+        let heap = unsafe { std::alloc::alloc(layout) };
+        axalloc::global_init(heap as usize, HEAP_SIZE);
+        let kernel_aspace = AddrSpace::new_empty(
+            va!(axconfig::KERNEL_ASPACE_BASE),
+            axconfig::KERNEL_ASPACE_SIZE,
+        )
+        .unwrap();
+        KERNEL_ASPACE.init_once(KernelAspace(SpinNoIrq::new(kernel_aspace)));
+    });
+}
+
+fn is_writable(aspace: &AddrSpace, vaddr: VirtAddr) -> bool {
+    let (_, flags, _) = aspace.page_table().query(vaddr).unwrap();
+    flags.contains(MappingFlags::WRITE)
+}
+
+#[test]
+fn test_cow_protect() {
+    init();
+
+    let ro = MappingFlags::READ | MappingFlags::USER;
+    let rw = ro | MappingFlags::WRITE;
+    let (page0, page1) = (va!(USER_BASE), va!(USER_BASE + PAGE_SIZE_4K));
+
+    let mut parent = AddrSpace::new_empty(va!(USER_BASE), USER_SIZE).unwrap();
+    parent.map_alloc(page0, PAGE_SIZE_4K, rw, false).unwrap();
+    parent.map_alloc(page1, PAGE_SIZE_4K, ro, false).unwrap();
+    for page in [page0, page1] {
+        assert!(parent.handle_page_fault(page, MappingFlags::READ));
+        parent.write(page, b"parent").unwrap();
+    }
+
+    // fork
+    let mut child = parent.clone_cow().unwrap();
+    assert!(!is_writable(&parent, page0));
+    assert!(!is_writable(&child, page0));
+
+    // mprotect keeps the shared pages read-only, even if they were writable
+    // before the fork.
+    for aspace in [&mut parent, &mut child] {
+        aspace.protect(page0, PAGE_SIZE_4K, ro).unwrap();
+        aspace.protect(page0, 2 * PAGE_SIZE_4K, rw).unwrap();
+        assert!(!is_writable(aspace, page0));
+        assert!(!is_writable(aspace, page1));
+    }
+
+    // The first write copies the shared page, and the last sharer reuses it.
+    for page in [page0, page1] {
+        assert!(child.handle_page_fault(page, MappingFlags::WRITE));
+        assert!(is_writable(&child, page));
+        child.write(page, b"child!").unwrap();
+        assert!(!is_writable(&parent, page));
+        assert!(parent.handle_page_fault(page, MappingFlags::WRITE));
+        assert!(is_writable(&parent, page));
+
+        let mut buf = [0; 6];
+        parent.read(page, &mut buf).unwrap();
+        assert_eq!(&buf, b"parent");
+        child.read(page, &mut buf).unwrap();
+        assert_eq!(&buf, b"child!");
+    }
+
+    // Pages not shared are made writable at once.
+    parent.protect(page0, PAGE_SIZE_4K, ro).unwrap();
+    assert!(!is_writable(&parent, page0));
+    parent.protect(page0, PAGE_SIZE_4K, rw).unwrap();
+    assert!(is_writable(&parent, page0));
+
+    parent.clear().unwrap();
+    child.clear().unwrap();
+}
+
+#[test]
+fn test_cow_kernel_write() {
+    init();
+
+    let ro = MappingFlags::READ | MappingFlags::USER;
+    let rw = ro | MappingFlags::WRITE;
+    let (page0, page1) = (va!(USER_BASE), va!(USER_BASE + PAGE_SIZE_4K));
+
+    let mut parent = AddrSpace::new_empty(va!(USER_BASE), USER_SIZE).unwrap();
+    parent.map_alloc(page0, PAGE_SIZE_4K, rw, true).unwrap();
+    parent.map_alloc(page1, PAGE_SIZE_4K, ro, true).unwrap();
+    for page in [page0, page1] {
+        parent.write(page, b"parent").unwrap();
+    }
+
+    // fork
+    let mut child = parent.clone_cow().unwrap();
+
+    // Writes by the kernel copy the shared pages, even the read-only ones.
+    for page in [page0, page1] {
+        child.write(page, b"child!").unwrap();
+        let mut buf = [0; 6];
+        parent.read(page, &mut buf).unwrap();
+        assert_eq!(&buf, b"parent");
+        child.read(page, &mut buf).unwrap();
+        assert_eq!(&buf, b"child!");
+    }
+    assert!(is_writable(&child, page0));
+    assert!(!is_writable(&child, page1));
+    assert!(!is_writable(&parent, page0));
+
+    parent.clear().unwrap();
+    child.clear().unwrap();
+}
+
+#[test]
+fn test_kernel_access_fault_in() {
+    init();
+
+    let rw = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+    let (page0, page1) = (va!(USER_BASE), va!(USER_BASE + PAGE_SIZE_4K));
+
+    let mut aspace = AddrSpace::new_empty(va!(USER_BASE), USER_SIZE).unwrap();
+    aspace
+        .map_alloc(page0, 2 * PAGE_SIZE_4K, rw, false)
+        .unwrap();
+
+    // Pages not allocated yet are faulted in by the kernel accesses.
+    let mut buf = [0xff; 6];
+    aspace.read(page0, &mut buf).unwrap();
+    assert_eq!(buf, [0; 6]);
+    aspace.write(page1, b"kernel").unwrap();
+    let bufs = aspace
+        .translated_byte_buffer(page0, 2 * PAGE_SIZE_4K)
+        .unwrap();
+    assert_eq!(bufs.len(), 2);
+    assert_eq!(&bufs[1][..6], b"kernel");
+
+    aspace.clear().unwrap();
+}
@@ -14,6 +14,9 @@
 //!
 //! - `smoltcp`: Use [smoltcp] as the underlying network stack. This is enabled
 //!   by default.
+//! - `async`: Provide async methods of [`TcpSocket`], such as
+//!   [`recv_async`](TcpSocket::recv_async), which run on the `axtask`
+//!   executor.
 //!
 //! [smoltcp]: https://github.com/smoltcp-rs/smoltcp
 
@@ -4,6 +4,8 @@ mod dns;
 mod listen_table;
 mod tcp;
 mod udp;
+#[cfg(feature = "async")]
+mod waker;
 
 use alloc::vec;
 use core::cell::RefCell;
@@ -1,6 +1,8 @@
 use core::cell::UnsafeCell;
 use core::net::SocketAddr;
 use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+#[cfg(feature = "async")]
+use core::task::Poll;
 
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axio::PollState;
@@ -223,17 +225,8 @@ impl TcpSocket {
     ///
     /// It's must be called after [`bind`](Self::bind) and [`listen`](Self::listen).
     pub fn accept(&self) -> AxResult<TcpSocket> {
-        if !self.is_listening() {
-            return ax_err!(InvalidInput, "socket accept() failed: not listen");
-        }
-
-        // SAFETY: `self.local_addr` should be initialized after `bind()`.
-        let local_port = unsafe { self.local_addr.get().read().port };
-        self.block_on(|| {
-            let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port)?;
-            debug!("TCP socket accepted a new connection {}", peer_addr);
-            Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
-        })
+        let local_port = self.listening_port()?;
+        self.block_on(|| self.try_accept(local_port))
     }
 
     /// Close the connection.
@@ -271,65 +264,14 @@ impl TcpSocket {
 
     /// Receives data from the socket, stores it in the given buffer.
     pub fn recv(&self, buf: &mut [u8]) -> AxResult<usize> {
-        if self.is_connecting() {
-            return Err(AxError::WouldBlock);
-        } else if !self.is_connected() {
-            return ax_err!(NotConnected, "socket recv() failed");
-        }
-
-        // SAFETY: `self.handle` should be initialized in a connected socket.
-        let handle = unsafe { self.handle.get().read().unwrap() };
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_active() {
-                    // not open
-                    ax_err!(ConnectionRefused, "socket recv() failed")
-                } else if !socket.may_recv() {
-                    // connection closed
-                    Ok(0)
-                } else if socket.recv_queue() > 0 {
-                    // data available
-                    // TODO: use socket.recv(|buf| {...})
-                    let len = socket
-                        .recv_slice(buf)
-                        .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
-                    Ok(len)
-                } else {
-                    // no more data
-                    Err(AxError::WouldBlock)
-                }
-            })
-        })
+        let handle = self.connected_handle("socket recv() failed")?;
+        self.block_on(|| self.try_recv(handle, buf))
     }
 
     /// Transmits data in the given buffer.
     pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
-        if self.is_connecting() {
-            return Err(AxError::WouldBlock);
-        } else if !self.is_connected() {
-            return ax_err!(NotConnected, "socket send() failed");
-        }
-
-        // SAFETY: `self.handle` should be initialized in a connected socket.
-        let handle = unsafe { self.handle.get().read().unwrap() };
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_active() || !socket.may_send() {
-                    // closed by remote
-                    ax_err!(ConnectionReset, "socket send() failed")
-                } else if socket.can_send() {
-                    // connected, and the tx buffer is not full
-                    // TODO: use socket.send(|buf| {...})
-                    let len = socket
-                        .send_slice(buf)
-                        .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
-                    Ok(len)
-                } else {
-                    // tx buffer is full
-                    Err(AxError::WouldBlock)
-                }
-            })
-        })
+        let handle = self.connected_handle("socket send() failed")?;
+        self.block_on(|| self.try_send(handle, buf))
     }
 
     /// Whether the socket is readable or writable.
@@ -346,8 +288,125 @@ impl TcpSocket {
     }
 }
 
+/// Async methods, which wait without blocking the task, regardless of the
+/// non-blocking flag.
+#[cfg(feature = "async")]
+impl TcpSocket {
+    /// Accepts a new connection asynchronously.
+    ///
+    /// It's must be called after [`bind`](Self::bind) and [`listen`](Self::listen).
+    pub async fn accept_async(&self) -> AxResult<TcpSocket> {
+        let local_port = self.listening_port()?;
+        self.wait_async(|| self.try_accept(local_port)).await
+    }
+
+    /// Receives data from the socket asynchronously, stores it in the given
+    /// buffer.
+    pub async fn recv_async(&self, buf: &mut [u8]) -> AxResult<usize> {
+        let handle = self.connected_handle("socket recv() failed")?;
+        self.wait_async(|| self.try_recv(handle, buf)).await
+    }
+
+    /// Transmits data in the given buffer asynchronously.
+    pub async fn send_async(&self, buf: &[u8]) -> AxResult<usize> {
+        let handle = self.connected_handle("socket send() failed")?;
+        self.wait_async(|| self.try_send(handle, buf)).await
+    }
+
+    /// Polls the network and calls the given function, until it completes or
+    /// fails.
+    ///
+    /// While the function returns [`Err(WouldBlock)`](AxError::WouldBlock),
+    /// the future is pending until the interfaces are polled again by the
+    /// poller task.
+    async fn wait_async<F, T>(&self, mut f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        core::future::poll_fn(|cx| {
+            SOCKET_SET.poll_interfaces();
+            match f() {
+                Err(AxError::WouldBlock) => {
+                    super::waker::register(cx.waker());
+                    Poll::Pending
+                }
+                res => Poll::Ready(res),
+            }
+        })
+        .await
+    }
+}
+
 /// Private methods
 impl TcpSocket {
+    /// Returns the listening port, or an error if the socket is not
+    /// listening.
+    fn listening_port(&self) -> AxResult<u16> {
+        if !self.is_listening() {
+            return ax_err!(InvalidInput, "socket accept() failed: not listen");
+        }
+        // SAFETY: `self.local_addr` should be initialized after `bind()`.
+        Ok(unsafe { self.local_addr.get().read().port })
+    }
+
+    /// Returns the socket handle, or an error if the socket is not connected.
+    fn connected_handle(&self, err_msg: &str) -> AxResult<SocketHandle> {
+        if self.is_connecting() {
+            return Err(AxError::WouldBlock);
+        } else if !self.is_connected() {
+            return ax_err!(NotConnected, err_msg);
+        }
+        // SAFETY: `self.handle` should be initialized in a connected socket.
+        Ok(unsafe { self.handle.get().read().unwrap() })
+    }
+
+    fn try_accept(&self, local_port: u16) -> AxResult<TcpSocket> {
+        let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port)?;
+        debug!("TCP socket accepted a new connection {}", peer_addr);
+        Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
+    }
+
+    fn try_recv(&self, handle: SocketHandle, buf: &mut [u8]) -> AxResult<usize> {
+        SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+            if !socket.is_active() {
+                // not open
+                ax_err!(ConnectionRefused, "socket recv() failed")
+            } else if !socket.may_recv() {
+                // connection closed
+                Ok(0)
+            } else if socket.recv_queue() > 0 {
+                // data available
+                // TODO: use socket.recv(|buf| {...})
+                let len = socket
+                    .recv_slice(buf)
+                    .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
+                Ok(len)
+            } else {
+                // no more data
+                Err(AxError::WouldBlock)
+            }
+        })
+    }
+
+    fn try_send(&self, handle: SocketHandle, buf: &[u8]) -> AxResult<usize> {
+        SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+            if !socket.is_active() || !socket.may_send() {
+                // closed by remote
+                ax_err!(ConnectionReset, "socket send() failed")
+            } else if socket.can_send() {
+                // connected, and the tx buffer is not full
+                // TODO: use socket.send(|buf| {...})
+                let len = socket
+                    .send_slice(buf)
+                    .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
+                Ok(len)
+            } else {
+                // tx buffer is full
+                Err(AxError::WouldBlock)
+            }
+        })
+    }
+
     #[inline]
     fn get_state(&self) -> u8 {
         self.state.load(Ordering::Acquire)
@@ -0,0 +1,59 @@
+//! Wakeups of the async socket methods on network events.
+//!
+//! A future that can not make progress registers its waker. As NIC interrupts
+//! are not used, a poller task polls the interfaces every [`POLL_INTERVAL`]
+//! while there are registered wakers, and wakes them up after each poll. It
+//! sleeps on a wait queue when there are no registered wakers.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Waker;
+use core::time::Duration;
+
+use axtask::{TaskInner, WaitQueue};
+use spin::Mutex;
+
+use super::SOCKET_SET;
+
+const POLLER_STACK_SIZE: usize = 0x10000;
+
+/// The interval between two polls of the interfaces by the poller task.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+static WAKERS: Mutex<Vec<Waker>> = Mutex::new(Vec::new());
+static POLLER_WQ: WaitQueue = WaitQueue::new();
+static POLLER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Registers a waker to be woken after the next poll of the interfaces by the
+/// poller task.
+pub(crate) fn register(waker: &Waker) {
+    {
+        let mut wakers = WAKERS.lock();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+    if !POLLER_STARTED.swap(true, Ordering::AcqRel) {
+        let mut task = TaskInner::new(poller_loop, "net-poller".into(), POLLER_STACK_SIZE);
+        task.set_unkillable();
+        axtask::spawn_task(task);
+    }
+    POLLER_WQ.notify_one(false);
+}
+
+/// Wakes up all registered wakers.
+fn wake_all() {
+    let wakers = core::mem::take(&mut *WAKERS.lock());
+    for waker in wakers {
+        waker.wake();
+    }
+}
+
+fn poller_loop() {
+    loop {
+        POLLER_WQ.wait_until(|| !WAKERS.lock().is_empty());
+        axtask::sleep(POLL_INTERVAL);
+        SOCKET_SET.poll_interfaces();
+        wake_all();
+    }
+}
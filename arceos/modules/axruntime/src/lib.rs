@@ -151,12 +151,18 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     axhal::platform_init();
 
     #[cfg(feature = "multitask")]
-    axtask::init_scheduler();
+    {
+        init_sched_policy();
+        axtask::init_scheduler();
+    }
 
-    #[cfg(any(feature = "fs", feature = "net", feature = "display"))]
+    #[cfg(any(feature = "fs", feature = "net", feature = "display", feature = "swap"))]
     {
-        #[allow(unused_variables)]
-        let all_devices = axdriver::init_drivers();
+        #[allow(unused_variables, unused_mut)]
+        let mut all_devices = axdriver::init_drivers();
+
+        #[cfg(feature = "swap")]
+        init_swap(&mut all_devices.block);
 
         #[cfg(feature = "fs")]
         axfs::init_filesystems(all_devices.block);
@@ -201,6 +207,42 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     }
 }
 
+/// Uses a block device as the swap area. The first block device is left to
+/// the filesystems if `fs` is enabled, and the next one is used.
+#[cfg(feature = "swap")]
+fn init_swap(block_devs: &mut axdriver::AxDeviceContainer<axdriver::AxBlockDevice>) {
+    #[cfg(feature = "fs")]
+    let fs_dev = block_devs.take_one();
+    match block_devs.take_one() {
+        Some(dev) => axmm::swap::init_swap(dev),
+        None => warn!("No block device for swap found!"),
+    }
+    #[cfg(feature = "fs")]
+    if let Some(dev) = fs_dev {
+        *block_devs = axdriver::AxDeviceContainer::from_one(dev);
+    }
+}
+
+/// Selects the scheduling policy by the `sched` boot argument, or leaves the
+/// default one specified by features.
+#[cfg(feature = "multitask")]
+fn init_sched_policy() {
+    let Some(arg) = axhal::cmdline::get("sched") else {
+        return;
+    };
+    match arg.parse::<axtask::SchedPolicy>() {
+        // Tasks are only preempted on timer interrupts.
+        Ok(policy) if policy.is_preemptive() && !cfg!(feature = "irq") => warn!(
+            "Scheduling policy {} needs the `irq` feature, use the default one",
+            policy.name()
+        ),
+        Ok(policy) => {
+            axtask::set_sched_policy(policy);
+        }
+        Err(_) => warn!("Unknown scheduling policy {:?}, use the default one", arg),
+    }
+}
+
 #[cfg(feature = "alloc")]
 fn init_allocator() {
     use axhal::mem::{memory_regions, phys_to_virt, MemRegionFlags};
@@ -263,13 +305,17 @@ fn init_allocator() {
 fn init_interrupt() {
     use axhal::time::TIMER_IRQ_NUM;
 
-    // Setup timer interrupt handler
+    // Setup timer interrupt handler. With multitasking, the timer interrupt
+    // is programmed by `axtask` instead.
+    #[cfg(not(feature = "multitask"))]
     const PERIODIC_INTERVAL_NANOS: u64 =
         axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
 
+    #[cfg(not(feature = "multitask"))]
     #[percpu::def_percpu]
     static NEXT_DEADLINE: u64 = 0;
 
+    #[cfg(not(feature = "multitask"))]
     fn update_timer() {
         let now_ns = axhal::time::monotonic_time_nanos();
         // Safety: we have disabled preemption in IRQ handler.
@@ -282,12 +328,14 @@ fn init_interrupt() {
     }
 
     axhal::irq::register_handler(TIMER_IRQ_NUM, || {
+        #[cfg(not(feature = "multitask"))]
         update_timer();
         #[cfg(feature = "multitask")]
         axtask::on_timer_tick();
     });
 
     // Enable IRQs before starting app
+    axhal::irq::set_online();
     axhal::arch::enable_irqs();
 }
 
@@ -51,7 +51,10 @@ pub extern "C" fn rust_main_secondary(cpu_id: usize) -> ! {
     }
 
     #[cfg(feature = "irq")]
-    axhal::arch::enable_irqs();
+    {
+        axhal::irq::set_online();
+        axhal::arch::enable_irqs();
+    }
 
     #[cfg(all(feature = "tls", not(feature = "multitask")))]
     super::init_tls();
@@ -3,26 +3,56 @@
 //! Currently supported primitives:
 //!
 //! - [`Mutex`]: A mutual exclusion primitive.
+//! - [`PiMutex`]: A mutual exclusion primitive with priority inheritance.
+//! - [`RwLock`]: A reader-writer lock.
+//! - [`Condvar`]: A condition variable.
+//! - [`Semaphore`]: A counting semaphore.
+//! - [`Barrier`]: A barrier to synchronize multiple tasks.
+//! - [`Once`] and [`OnceLock`]: One-time initialization primitives.
 //! - mod [`spin`]: spinlocks imported from the [`kspin`] crate.
 //!
 //! # Cargo Features
 //!
 //! - `multitask`: For use in the multi-threaded environments. If the feature is
-//!   not enabled, [`Mutex`] will be an alias of [`spin::SpinNoIrq`]. This
-//!   feature is enabled by default.
+//!   not enabled, [`Mutex`] and [`PiMutex`] will be aliases of
+//!   [`spin::SpinNoIrq`], and other primitives will spin instead of blocking.
+//!   This feature is enabled by default.
+//! - `irq`: Interrupts are enabled. If this feature is enabled, timed waiting
+//!   methods can be used, such as [`Condvar::wait_timeout`] and
+//!   [`Semaphore::acquire_timeout`].
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
 
 pub use kspin as spin;
 
+mod barrier;
+mod condvar;
 #[cfg(feature = "multitask")]
 mod mutex;
+mod once;
+#[cfg(feature = "multitask")]
+mod pi_mutex;
+mod rwlock;
+mod semaphore;
+mod wait_queue;
+
+pub use self::barrier::{Barrier, BarrierWaitResult};
+pub use self::condvar::{Condvar, WaitTimeoutResult};
+pub use self::once::{Once, OnceLock};
+pub use self::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use self::semaphore::{Semaphore, SemaphoreGuard};
 
 #[cfg(feature = "multitask")]
 #[doc(cfg(feature = "multitask"))]
 pub use self::mutex::{Mutex, MutexGuard};
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::pi_mutex::{PiMutex, PiMutexGuard};
 
 #[cfg(not(feature = "multitask"))]
 #[doc(cfg(not(feature = "multitask")))]
 pub use kspin::{SpinNoIrq as Mutex, SpinNoIrqGuard as MutexGuard};
+#[cfg(not(feature = "multitask"))]
+#[doc(cfg(not(feature = "multitask")))]
+pub use kspin::{SpinNoIrq as PiMutex, SpinNoIrqGuard as PiMutexGuard};
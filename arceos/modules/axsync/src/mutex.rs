@@ -167,6 +167,13 @@ impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
     }
 }
 
+impl<'a, T: ?Sized> MutexGuard<'a, T> {
+    /// Returns the [`Mutex`] that the guard is created from.
+    pub(crate) fn mutex(&self) -> &'a Mutex<T> {
+        self.lock
+    }
+}
+
 impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
     type Target = T;
     #[inline(always)]
@@ -0,0 +1,203 @@
+//! A sleeping mutex with priority inheritance.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+use axtask::{current, AxTaskRef, WaitQueue};
+use kspin::SpinNoIrq;
+
+/// A mutual exclusion primitive with priority inheritance.
+///
+/// It works like [`Mutex`](crate::Mutex), except that while higher-priority
+/// tasks are waiting for the lock, the effective priority of the holder is
+/// boosted to the highest priority of them. It prevents a low-priority holder
+/// from being starved by medium-priority tasks and thus blocking high-priority
+/// tasks indefinitely (priority inversion).
+///
+/// The boosted priority is kept until the holder releases all the mutexes with
+/// priority inheritance it holds, then its base priority is restored. The
+/// inheritance is not transitive, i.e., if the holder is blocked on another
+/// [`PiMutex`], the holder of that mutex is not boosted.
+///
+/// Priorities only take effect if the scheduler supports them (e.g., the CFS
+/// scheduler), otherwise it behaves the same as [`Mutex`](crate::Mutex).
+pub struct PiMutex<T: ?Sized> {
+    wq: WaitQueue,
+    owner: SpinNoIrq<Option<AxTaskRef>>,
+    data: UnsafeCell<T>,
+}
+
+/// A guard that provides mutable data access.
+///
+/// When the guard falls out of scope it will release the lock.
+pub struct PiMutexGuard<'a, T: ?Sized + 'a> {
+    lock: &'a PiMutex<T>,
+    data: *mut T,
+}
+
+// Same unsafe impls as `std::sync::Mutex`
+unsafe impl<T: ?Sized + Send> Sync for PiMutex<T> {}
+unsafe impl<T: ?Sized + Send> Send for PiMutex<T> {}
+
+impl<T> PiMutex<T> {
+    /// Creates a new [`PiMutex`] wrapping the supplied data.
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            owner: SpinNoIrq::new(None),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this [`PiMutex`] and unwraps the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        let PiMutex { data, .. } = self;
+        data.into_inner()
+    }
+}
+
+impl<T: ?Sized> PiMutex<T> {
+    /// Returns `true` if the lock is currently held.
+    ///
+    /// # Safety
+    ///
+    /// This function provides no synchronization guarantees and so its result should be considered 'out of date'
+    /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        self.owner.lock().is_some()
+    }
+
+    /// Locks the [`PiMutex`] and returns a guard that permits access to the inner data.
+    ///
+    /// If the lock is held by another task with a lower priority, the holder
+    /// inherits the priority of the current task until it releases the lock.
+    pub fn lock(&self) -> PiMutexGuard<T> {
+        let curr = current();
+        loop {
+            let mut owner = self.owner.lock();
+            if let Some(owner_task) = owner.as_ref() {
+                assert_ne!(
+                    owner_task.id(),
+                    curr.id(),
+                    "{} tried to acquire mutex it already owns.",
+                    curr.id_name()
+                );
+                axtask::inherit_priority(owner_task, curr.priority());
+                drop(owner);
+                // Wait until the lock looks unlocked before retrying
+                self.wq.wait_until(|| !self.is_locked());
+            } else {
+                *owner = Some(curr.as_task_ref().clone());
+                // Count the lock before others can see the owner and boost it.
+                axtask::pi_lock_acquired();
+                return PiMutexGuard {
+                    lock: self,
+                    data: unsafe { &mut *self.data.get() },
+                };
+            }
+        }
+    }
+
+    /// Try to lock this [`PiMutex`], returning a lock guard if successful.
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<PiMutexGuard<T>> {
+        let mut owner = self.owner.lock();
+        if owner.is_some() {
+            return None;
+        }
+        *owner = Some(current().as_task_ref().clone());
+        // Count the lock before others can see the owner and boost it.
+        axtask::pi_lock_acquired();
+        Some(PiMutexGuard {
+            lock: self,
+            data: unsafe { &mut *self.data.get() },
+        })
+    }
+
+    /// Force unlock the [`PiMutex`], and restore the priority of the current
+    /// task if it holds no other mutexes with priority inheritance. The waiter
+    /// with the highest priority is woken up to acquire the lock.
+    ///
+    /// # Safety
+    ///
+    /// This is *extremely* unsafe if the lock is not held by the current
+    /// thread. However, this can be useful in some instances for exposing
+    /// the lock to FFI that doesn’t know how to deal with RAII.
+    pub unsafe fn force_unlock(&self) {
+        let owner = self.owner.lock().take();
+        let curr = current();
+        assert!(
+            owner.is_some_and(|owner| owner.id() == curr.id()),
+            "{} tried to release mutex it doesn't own",
+            curr.id_name()
+        );
+        axtask::pi_lock_released();
+        self.wq.notify_highest_priority(true);
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the [`PiMutex`] mutably, and a mutable reference is guaranteed to be exclusive in
+    /// Rust, no actual locking needs to take place -- the mutable borrow statically guarantees no locks exist. As
+    /// such, this is a 'zero-cost' operation.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner mutex.
+        unsafe { &mut *self.data.get() }
+    }
+}
+
+impl<T: ?Sized + Default> Default for PiMutex<T> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for PiMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => write!(f, "PiMutex {{ data: ")
+                .and_then(|()| (*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "PiMutex {{ <locked> }}"),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for PiMutexGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        // We know statically that only we are referencing data
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for PiMutexGuard<'a, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        // We know statically that only we are referencing data
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for PiMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for PiMutexGuard<'a, T> {
+    /// The dropping of the [`PiMutexGuard`] will release the lock it was created from.
+    fn drop(&mut self) {
+        unsafe { self.lock.force_unlock() }
+    }
+}
@@ -0,0 +1,275 @@
+//! A sleeping reader-writer lock.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::wait_queue::WaitQueue;
+
+const WRITER: usize = 1 << (usize::BITS - 1);
+
+/// A reader-writer lock, similar to
+/// [`std::sync::RwLock`](https://doc.rust-lang.org/std/sync/struct.RwLock.html).
+///
+/// This type of lock allows a number of readers or at most one writer at any
+/// point in time. When the lock is not available, the current task will block
+/// and be put into the wait queue. When the lock is released, all tasks
+/// waiting on the queue will be woken up.
+///
+/// Writers are preferred: once a writer is waiting, new readers block until
+/// it has acquired and released the lock, so writers are not starved by a
+/// stream of readers. Thus a task that acquires the read lock recursively may
+/// deadlock.
+///
+/// If the `multitask` feature is not enabled, it spins instead of blocking.
+pub struct RwLock<T: ?Sized> {
+    wq: WaitQueue,
+    /// The `WRITER` bit is set if a writer holds the lock, the lower bits are
+    /// the number of readers.
+    state: AtomicUsize,
+    /// The number of writers blocked on the lock.
+    waiting_writers: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+/// A guard that provides immutable data access.
+///
+/// When the guard falls out of scope it will decrement the read count,
+/// potentially releasing the lock.
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+    data: *const T,
+}
+
+/// A guard that provides mutable data access.
+///
+/// When the guard falls out of scope it will release the lock.
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+    data: *mut T,
+}
+
+// Same unsafe impls as `std::sync::RwLock`
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockReadGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T> RwLock<T> {
+    /// Creates a new [`RwLock`] wrapping the supplied data.
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            state: AtomicUsize::new(0),
+            waiting_writers: AtomicUsize::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this [`RwLock`] and unwraps the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        let RwLock { data, .. } = self;
+        data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Returns `true` if the lock is currently held by a writer.
+    ///
+    /// This function provides no synchronization guarantees and so its result
+    /// should be considered 'out of date' the instant it is called.
+    #[inline(always)]
+    pub fn is_write_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & WRITER != 0
+    }
+
+    /// Returns the number of readers that currently hold the lock.
+    ///
+    /// This function provides no synchronization guarantees and so its result
+    /// should be considered 'out of date' the instant it is called.
+    #[inline(always)]
+    pub fn reader_count(&self) -> usize {
+        self.state.load(Ordering::Relaxed) & !WRITER
+    }
+
+    /// Locks this [`RwLock`] with shared read access, blocking the current
+    /// task until it can be acquired.
+    ///
+    /// There may be other readers currently inside the lock when this method
+    /// returns. It also blocks if there are writers waiting for the lock.
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        loop {
+            if !self.has_waiting_writers() {
+                if let Some(guard) = self.try_read() {
+                    return guard;
+                }
+            }
+            // Wait until the lock looks not write-locked and no writers are
+            // waiting before retrying
+            self.wq
+                .wait_until(|| !self.is_write_locked() && !self.has_waiting_writers());
+        }
+    }
+
+    /// Try to lock this [`RwLock`] with shared read access, returning a lock
+    /// guard if successful.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        let mut state = self.state.load(Ordering::Relaxed);
+        while state & WRITER == 0 {
+            assert!(state < WRITER - 1, "too many readers");
+            match self.state.compare_exchange_weak(
+                state,
+                state + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(RwLockReadGuard {
+                        lock: self,
+                        data: self.data.get(),
+                    })
+                }
+                Err(s) => state = s,
+            }
+        }
+        None
+    }
+
+    /// Locks this [`RwLock`] with exclusive write access, blocking the current
+    /// task until it can be acquired.
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        if let Some(guard) = self.try_write() {
+            return guard;
+        }
+        self.waiting_writers.fetch_add(1, Ordering::Relaxed);
+        let guard = loop {
+            if let Some(guard) = self.try_write() {
+                break guard;
+            }
+            // Wait until the lock looks unlocked before retrying
+            self.wq
+                .wait_until(|| self.state.load(Ordering::Relaxed) == 0);
+        };
+        self.waiting_writers.fetch_sub(1, Ordering::Relaxed);
+        guard
+    }
+
+    /// Try to lock this [`RwLock`] with exclusive write access, returning a
+    /// lock guard if successful.
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        if self
+            .state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            Some(RwLockWriteGuard {
+                lock: self,
+                data: self.data.get(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the [`RwLock`] mutably, no actual locking needs
+    /// to take place -- the mutable borrow statically guarantees no locks
+    /// exist.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner lock.
+        unsafe { &mut *self.data.get() }
+    }
+
+    fn has_waiting_writers(&self) -> bool {
+        self.waiting_writers.load(Ordering::Relaxed) != 0
+    }
+
+    fn read_unlock(&self) {
+        if self.state.fetch_sub(1, Ordering::Release) == 1 {
+            // The last reader, wake up the waiting writers.
+            self.wq.notify_all(true);
+        }
+    }
+
+    fn write_unlock(&self) {
+        self.state.fetch_and(!WRITER, Ordering::Release);
+        self.wq.notify_all(true);
+    }
+}
+
+impl<T: ?Sized + Default> Default for RwLock<T> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => write!(f, "RwLock {{ data: ")
+                .and_then(|()| (*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "RwLock {{ <locked> }}"),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        // We know statically that there is no writer
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.read_unlock();
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        // We know statically that only we are referencing data
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for RwLockWriteGuard<'a, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        // We know statically that only we are referencing data
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.write_unlock();
+    }
+}
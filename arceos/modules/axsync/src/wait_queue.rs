@@ -0,0 +1,48 @@
+//! The wait queue used by blocking primitives.
+//!
+//! If the `multitask` feature is enabled, it is the [`axtask::WaitQueue`].
+//! Otherwise, there is no other task to switch to, so waiting is done by
+//! spinning and notifying does nothing.
+
+#[cfg(feature = "multitask")]
+pub(crate) use axtask::WaitQueue;
+
+#[cfg(not(feature = "multitask"))]
+pub(crate) struct WaitQueue;
+
+#[cfg(not(feature = "multitask"))]
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self
+    }
+
+    pub fn wait_until<F>(&self, condition: F)
+    where
+        F: Fn() -> bool,
+    {
+        while !condition() {
+            core::hint::spin_loop();
+        }
+    }
+
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_until<F>(&self, dur: core::time::Duration, condition: F) -> bool
+    where
+        F: Fn() -> bool,
+    {
+        let deadline = axhal::time::wall_time() + dur;
+        while !condition() {
+            if axhal::time::wall_time() >= deadline {
+                return true;
+            }
+            core::hint::spin_loop();
+        }
+        false
+    }
+
+    pub fn notify_one(&self, _resched: bool) -> bool {
+        false
+    }
+
+    pub fn notify_all(&self, _resched: bool) {}
+}
@@ -0,0 +1,132 @@
+use axsync::PiMutex;
+use axtask as thread;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+static INIT: std::sync::Once = std::sync::Once::new();
+static SERIAL: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Initializes the scheduler once, and serializes the tests as they share the
+/// same (fake) CPU.
+///
+/// The CFS policy is used, which supports task priorities.
+fn init_serial() -> std::sync::MutexGuard<'static, ()> {
+    let guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    INIT.call_once(|| {
+        thread::set_sched_policy(thread::SchedPolicy::Cfs);
+        thread::init_scheduler();
+    });
+    guard
+}
+
+fn may_interrupt() {
+    // simulate interrupts
+    if rand::random::<u32>() % 3 == 0 {
+        thread::yield_now();
+    }
+}
+
+#[test]
+fn lots_and_lots() {
+    let _lock = init_serial();
+
+    const NUM_TASKS: u32 = 10;
+    const NUM_ITERS: u32 = 10_000;
+    static M: PiMutex<u32> = PiMutex::new(0);
+
+    fn inc(delta: u32) {
+        for _ in 0..NUM_ITERS {
+            let mut val = M.lock();
+            *val += delta;
+            may_interrupt();
+            drop(val);
+            may_interrupt();
+        }
+    }
+
+    for _ in 0..NUM_TASKS {
+        thread::spawn(|| inc(1));
+        thread::spawn(|| inc(2));
+    }
+
+    println!("spawn OK");
+    loop {
+        let val = M.lock();
+        if *val == NUM_ITERS * NUM_TASKS * 3 {
+            break;
+        }
+        may_interrupt();
+        drop(val);
+        may_interrupt();
+    }
+
+    assert_eq!(*M.lock(), NUM_ITERS * NUM_TASKS * 3);
+    assert_eq!(
+        thread::current().priority(),
+        thread::current().base_priority()
+    );
+    println!("PiMutex test OK");
+}
+
+#[test]
+fn priority_inheritance() {
+    let _lock = init_serial();
+
+    const LOW_PRIO: isize = 10;
+    const HIGH_PRIO: isize = -10;
+    static M: PiMutex<()> = PiMutex::new(());
+
+    assert!(thread::set_priority(LOW_PRIO));
+
+    let guard = M.lock();
+    let waiter = thread::spawn(|| {
+        assert!(thread::set_priority(HIGH_PRIO));
+        drop(M.lock());
+    });
+    // Let the waiter block on the mutex and boost the current task.
+    while thread::current().priority() != HIGH_PRIO {
+        thread::yield_now();
+    }
+    assert_eq!(thread::current().base_priority(), LOW_PRIO);
+
+    drop(guard);
+    assert_eq!(thread::current().priority(), LOW_PRIO);
+    assert_eq!(waiter.join(), Some(0));
+
+    assert!(thread::set_priority(0));
+    println!("PiMutex priority inheritance test OK");
+}
+
+#[test]
+fn highest_priority_waiter_first() {
+    let _lock = init_serial();
+
+    const LOW_PRIO: isize = 10;
+    const HIGH_PRIO: isize = -10;
+    static M: PiMutex<Vec<isize>> = PiMutex::new(Vec::new());
+    static WAITING: AtomicUsize = AtomicUsize::new(0);
+
+    let guard = M.lock();
+    // Let the waiters block on the mutex one by one, the low-priority one
+    // first. They block right after counting themselves without yielding.
+    let waiters: Vec<_> = [LOW_PRIO, HIGH_PRIO]
+        .into_iter()
+        .enumerate()
+        .map(|(i, prio)| {
+            let waiter = thread::spawn(move || {
+                assert!(thread::set_priority(prio));
+                WAITING.fetch_add(1, Ordering::Relaxed);
+                M.lock().push(prio);
+            });
+            while WAITING.load(Ordering::Relaxed) <= i {
+                thread::yield_now();
+            }
+            waiter
+        })
+        .collect();
+
+    drop(guard);
+    for waiter in waiters {
+        assert_eq!(waiter.join(), Some(0));
+    }
+    assert_eq!(*M.lock(), [HIGH_PRIO, LOW_PRIO]);
+}
@@ -0,0 +1,212 @@
+use axsync::{Barrier, Condvar, Mutex, Once, OnceLock, RwLock, Semaphore};
+use axtask as thread;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+static INIT: std::sync::Once = std::sync::Once::new();
+static SERIAL: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Initializes the scheduler once, and serializes the tests as they share the
+/// same (fake) CPU.
+fn init_serial() -> std::sync::MutexGuard<'static, ()> {
+    let guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    INIT.call_once(thread::init_scheduler);
+    guard
+}
+
+#[test]
+fn rendezvous() {
+    let _lock = init_serial();
+
+    const NUM_TASKS: usize = 10;
+    const NUM_ROUNDS: usize = 5;
+    static BARRIER: Barrier = Barrier::new(NUM_TASKS);
+    static ARRIVED: AtomicUsize = AtomicUsize::new(0);
+    static LEADERS: AtomicUsize = AtomicUsize::new(0);
+
+    let tasks: Vec<_> = (0..NUM_TASKS - 1)
+        .map(|_| {
+            thread::spawn(|| {
+                for round in 0..NUM_ROUNDS {
+                    ARRIVED.fetch_add(1, Ordering::Relaxed);
+                    if BARRIER.wait().is_leader() {
+                        LEADERS.fetch_add(1, Ordering::Relaxed);
+                    }
+                    assert!(ARRIVED.load(Ordering::Relaxed) >= (round + 1) * NUM_TASKS);
+                    BARRIER.wait();
+                }
+            })
+        })
+        .collect();
+
+    for round in 0..NUM_ROUNDS {
+        ARRIVED.fetch_add(1, Ordering::Relaxed);
+        if BARRIER.wait().is_leader() {
+            LEADERS.fetch_add(1, Ordering::Relaxed);
+        }
+        assert!(ARRIVED.load(Ordering::Relaxed) >= (round + 1) * NUM_TASKS);
+        BARRIER.wait();
+    }
+
+    for task in tasks {
+        assert_eq!(task.join(), Some(0));
+    }
+    assert_eq!(ARRIVED.load(Ordering::Relaxed), NUM_ROUNDS * NUM_TASKS);
+    assert_eq!(LEADERS.load(Ordering::Relaxed), NUM_ROUNDS);
+}
+
+#[test]
+fn producer_consumer() {
+    let _lock = init_serial();
+
+    const NUM_ITEMS: usize = 100;
+    const CAPACITY: usize = 4;
+    static QUEUE: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+    static NOT_EMPTY: Condvar = Condvar::new();
+    static NOT_FULL: Condvar = Condvar::new();
+
+    let producer = thread::spawn(|| {
+        for i in 0..NUM_ITEMS {
+            let mut queue = NOT_FULL.wait_while(QUEUE.lock(), |q| q.len() >= CAPACITY);
+            queue.push(i);
+            drop(queue);
+            NOT_EMPTY.notify_one();
+        }
+    });
+
+    for i in 0..NUM_ITEMS {
+        let mut queue = NOT_EMPTY.wait_while(QUEUE.lock(), |q| q.is_empty());
+        assert!(queue.len() <= CAPACITY);
+        assert_eq!(queue.remove(0), i);
+        drop(queue);
+        NOT_FULL.notify_one();
+    }
+    assert_eq!(producer.join(), Some(0));
+    assert!(QUEUE.lock().is_empty());
+}
+
+#[test]
+fn init_once() {
+    let _lock = init_serial();
+
+    const NUM_TASKS: usize = 10;
+    static ONCE: Once = Once::new();
+    static CELL: OnceLock<usize> = OnceLock::new();
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    let tasks: Vec<_> = (0..NUM_TASKS)
+        .map(|i| {
+            thread::spawn(move || {
+                ONCE.call_once(|| {
+                    thread::yield_now(); // let others wait for us
+                    CALLS.fetch_add(1, Ordering::Relaxed);
+                });
+                assert!(ONCE.is_completed());
+                let value = *CELL.get_or_init(|| {
+                    thread::yield_now();
+                    i
+                });
+                assert_eq!(CELL.get(), Some(&value));
+            })
+        })
+        .collect();
+
+    for task in tasks {
+        assert_eq!(task.join(), Some(0));
+    }
+    assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+    assert!(CELL.set(NUM_TASKS).is_err());
+
+    let mut cell = OnceLock::from(1);
+    assert_eq!(cell.take(), Some(1));
+    assert_eq!(cell.get(), None);
+    assert_eq!(cell.set(2), Ok(()));
+    assert_eq!(cell.into_inner(), Some(2));
+}
+
+#[test]
+fn readers_and_writers() {
+    let _lock = init_serial();
+
+    const NUM_TASKS: usize = 10;
+    const NUM_ITERS: usize = 1000;
+    static L: RwLock<(usize, usize)> = RwLock::new((0, 0));
+    static FINISHED_TASKS: AtomicUsize = AtomicUsize::new(0);
+
+    for _ in 0..NUM_TASKS {
+        thread::spawn(|| {
+            for _ in 0..NUM_ITERS {
+                let mut val = L.write();
+                val.0 += 1;
+                thread::yield_now();
+                val.1 += 1;
+                drop(val);
+
+                let val = L.read();
+                assert_eq!(val.0, val.1);
+                thread::yield_now();
+                assert_eq!(val.0, val.1);
+            }
+            FINISHED_TASKS.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    while FINISHED_TASKS.load(Ordering::Relaxed) < NUM_TASKS {
+        let val = L.read();
+        assert_eq!(val.0, val.1);
+        drop(val);
+        thread::yield_now();
+    }
+    assert_eq!(*L.read(), (NUM_TASKS * NUM_ITERS, NUM_TASKS * NUM_ITERS));
+    assert_eq!(L.reader_count(), 0);
+}
+
+#[test]
+fn writer_preferred() {
+    let _lock = init_serial();
+
+    static L: RwLock<usize> = RwLock::new(0);
+    static READ_VALUE: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+    let val = L.read();
+    let writer = thread::spawn(|| *L.write() += 1);
+    thread::yield_now(); // the writer waits for the read lock
+    let reader = thread::spawn(|| READ_VALUE.store(*L.read(), Ordering::Relaxed));
+    thread::yield_now(); // the reader waits for the writer
+    assert_eq!(READ_VALUE.load(Ordering::Relaxed), usize::MAX);
+    drop(val);
+
+    assert_eq!(writer.join(), Some(0));
+    assert_eq!(reader.join(), Some(0));
+    assert_eq!(READ_VALUE.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn limited_concurrency() {
+    let _lock = init_serial();
+
+    const NUM_TASKS: usize = 10;
+    const NUM_PERMITS: usize = 3;
+    static SEM: Semaphore = Semaphore::new(NUM_PERMITS);
+    static RUNNING: AtomicUsize = AtomicUsize::new(0);
+    static FINISHED_TASKS: AtomicUsize = AtomicUsize::new(0);
+
+    for _ in 0..NUM_TASKS {
+        thread::spawn(|| {
+            for _ in 0..10 {
+                let _guard = SEM.access();
+                let running = RUNNING.fetch_add(1, Ordering::Relaxed) + 1;
+                assert!(running <= NUM_PERMITS);
+                thread::yield_now();
+                RUNNING.fetch_sub(1, Ordering::Relaxed);
+            }
+            FINISHED_TASKS.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    while FINISHED_TASKS.load(Ordering::Relaxed) < NUM_TASKS {
+        thread::yield_now();
+    }
+    assert_eq!(SEM.available_permits(), NUM_PERMITS);
+    assert!(SEM.try_acquire());
+    SEM.release();
+}
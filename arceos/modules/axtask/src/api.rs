@@ -1,33 +1,29 @@
 //! Task APIs for multi-task configuration.
 
-use alloc::{string::String, sync::Arc};
+use alloc::{string::String, sync::Arc, vec::Vec};
 
-pub(crate) use crate::run_queue::{AxRunQueue, RUN_QUEUE};
+pub(crate) use crate::run_queue::{current_run_queue, select_run_queue};
 
 #[doc(cfg(feature = "multitask"))]
-pub use crate::task::{CurrentTask, TaskId, TaskInner};
+pub use crate::cpumask::AxCpuMask;
+#[doc(cfg(feature = "multitask"))]
+pub use crate::edf::{DeadlineError, DeadlineParams};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::sched::{SchedClass, SchedPolicy};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::stats::{TaskSnapshot, TaskStats};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::task::{CurrentTask, TaskId, TaskInner, TaskState};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::task_ext::{TaskExtMut, TaskExtRef};
 #[doc(cfg(feature = "multitask"))]
-pub use crate::wait_queue::WaitQueue;
+pub use crate::wait_queue::{Interrupted, WaitQueue};
 
 /// The reference type of a task.
 pub type AxTaskRef = Arc<AxTask>;
 
-cfg_if::cfg_if! {
-    if #[cfg(feature = "sched_rr")] {
-        const MAX_TIME_SLICE: usize = 5;
-        pub(crate) type AxTask = scheduler::RRTask<TaskInner, MAX_TIME_SLICE>;
-        pub(crate) type Scheduler = scheduler::RRScheduler<TaskInner, MAX_TIME_SLICE>;
-    } else if #[cfg(feature = "sched_cfs")] {
-        pub(crate) type AxTask = scheduler::CFSTask<TaskInner>;
-        pub(crate) type Scheduler = scheduler::CFScheduler<TaskInner>;
-    } else {
-        // If no scheduler features are set, use FIFO as the default.
-        pub(crate) type AxTask = scheduler::FifoTask<TaskInner>;
-        pub(crate) type Scheduler = scheduler::FifoScheduler<TaskInner>;
-    }
-}
+pub(crate) type AxTask = crate::sched::SchedTask<TaskInner>;
+pub(crate) type Scheduler = crate::sched::ClassScheduler<TaskInner>;
 
 #[cfg(feature = "preempt")]
 struct KernelGuardIfImpl;
@@ -70,29 +66,56 @@ pub fn init_scheduler() {
     crate::run_queue::init();
     #[cfg(feature = "irq")]
     crate::timers::init();
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::init();
+    crate::workqueue::init_percpu();
 
     info!("  use {} scheduler.", Scheduler::scheduler_name());
 }
 
+/// Selects the scheduling policy of [normal](SchedClass::Normal) tasks.
+///
+/// It must be called before [`init_scheduler`], as the policy is fixed once
+/// the scheduler is initialized. Returns `false` if it is too late.
+pub fn set_sched_policy(policy: SchedPolicy) -> bool {
+    crate::sched::set_policy(policy)
+}
+
+/// Returns the scheduling policy of [normal](SchedClass::Normal) tasks.
+///
+/// If it has not been selected, the default one is returned and fixed.
+pub fn sched_policy() -> SchedPolicy {
+    crate::sched::policy()
+}
+
 /// Initializes the task scheduler for secondary CPUs.
 pub fn init_scheduler_secondary() {
     crate::run_queue::init_secondary();
+    crate::workqueue::init_percpu();
 }
 
-/// Handles periodic timer ticks for the task manager.
+/// Handles timer interrupts for the task manager.
 ///
-/// For example, advance scheduler states, checks timed events, etc.
+/// It runs expired timer events, advances scheduler states on periodic ticks,
+/// and programs the next timer interrupt of the current CPU. With the
+/// `watchdog` feature, it also checks for lockups.
 #[cfg(feature = "irq")]
 #[doc(cfg(feature = "irq"))]
 pub fn on_timer_tick() {
-    crate::timers::check_events();
-    RUN_QUEUE.lock().scheduler_timer_tick();
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::on_timer_irq();
+    if crate::timers::on_timer_irq() {
+        current_run_queue().scheduler_timer_tick();
+    }
 }
 
 /// Adds the given task to the run queue, returns the task reference.
+///
+/// If the `smp` feature is enabled, the run queue is selected among all CPUs
+/// in the task's affinity mask in a round-robin manner.
 pub fn spawn_task(task: TaskInner) -> AxTaskRef {
     let task_ref = task.into_arc();
-    RUN_QUEUE.lock().add_task(task_ref.clone());
+    select_run_queue(&task_ref).add_task(task_ref.clone());
     task_ref
 }
 
@@ -119,23 +142,199 @@ where
     spawn_raw(f, "".into(), axconfig::TASK_STACK_SIZE)
 }
 
+/// Returns the live task with the given ID, or [`None`] if there is no such
+/// task or it has been recycled.
+///
+/// A task is live from its creation until it is recycled after exiting, so an
+/// exited task may still be returned.
+pub fn get_task(id: TaskId) -> Option<AxTaskRef> {
+    crate::registry::get(id)
+}
+
+/// Calls `f` on each live task, in the order of their IDs.
+///
+/// The tasks are collected before calling `f`, so `f` may spawn tasks or wait
+/// for them, but tasks created during the iteration are not visited.
+pub fn for_each_task<F>(f: F)
+where
+    F: FnMut(&AxTaskRef),
+{
+    crate::registry::tasks().iter().for_each(f)
+}
+
+/// Returns all live tasks with the given name, in the order of their IDs.
+pub fn find_tasks_by_name(name: &str) -> Vec<AxTaskRef> {
+    let mut tasks = crate::registry::tasks();
+    tasks.retain(|task| task.name() == name);
+    tasks
+}
+
+/// Takes a snapshot of all live tasks, i.e., tasks that have been created and
+/// not yet recycled, ordered by their IDs.
+pub fn task_snapshot() -> Vec<TaskSnapshot> {
+    crate::registry::tasks()
+        .iter()
+        .map(TaskSnapshot::new)
+        .collect()
+}
+
 /// Set the priority for current task.
 ///
-/// The range of the priority is dependent on the underlying scheduler. For
-/// example, in the [CFS] scheduler, the priority is the nice value, ranging from
-/// -20 to 19.
+/// The range of the priority is dependent on the scheduling policy. For
+/// example, in the [`SchedPolicy::Cfs`] policy, the priority is the nice value,
+/// ranging from -20 to 19.
 ///
 /// Returns `true` if the priority is set successfully.
-///
-/// [CFS]: https://en.wikipedia.org/wiki/Completely_Fair_Scheduler
 pub fn set_priority(prio: isize) -> bool {
-    RUN_QUEUE.lock().set_current_priority(prio)
+    current_run_queue().set_current_priority(prio)
+}
+
+/// Sets the scheduling class of the given task.
+///
+/// If the task is ready, it is moved to the ready queue of the new class
+/// immediately. Otherwise the new class takes effect the next time it becomes
+/// ready.
+pub fn set_sched_class(task: &AxTaskRef, class: SchedClass) {
+    crate::run_queue::set_sched_class(task, class)
+}
+
+/// Sets the scheduling class of the current task.
+///
+/// If the current task becomes a normal task while real-time tasks are ready,
+/// it is preempted on the next timer tick.
+pub fn set_current_sched_class(class: SchedClass) {
+    current().set_sched_class(class)
+}
+
+/// Sets the deadline parameters of the current task, making it a real-time
+/// task scheduled by the EDF scheduler. If `params` is [`None`], the current
+/// task becomes a normal task.
+///
+/// The bandwidth (`runtime / period`) of the task is reserved on the current
+/// CPU until the parameters are changed or the task is dropped. The request is
+/// rejected if the total bandwidth of real-time tasks on the CPU would exceed
+/// its capacity. The task keeps running on that CPU, unless it is moved to
+/// another one with enough bandwidth left, e.g., when its affinity changes.
+///
+/// Only available if the `sched_edf` feature is enabled, otherwise it returns
+/// [`DeadlineError::Unsupported`].
+pub fn set_current_deadline(params: Option<DeadlineParams>) -> Result<(), DeadlineError> {
+    #[cfg(feature = "sched_edf")]
+    {
+        let _rq = current_run_queue();
+        let cpu_id = axhal::cpu::this_cpu_id();
+        current().as_task_ref().set_deadline_params(params, cpu_id)
+    }
+    #[cfg(not(feature = "sched_edf"))]
+    {
+        let _ = params;
+        Err(DeadlineError::Unsupported)
+    }
+}
+
+/// Returns the number of jobs of the given task that missed their deadlines.
+///
+/// It is always 0 if the `sched_edf` feature is not enabled, or the task is
+/// not a real-time task.
+pub fn deadline_misses(task: &AxTaskRef) -> u64 {
+    #[cfg(feature = "sched_edf")]
+    {
+        task.deadline_misses()
+    }
+    #[cfg(not(feature = "sched_edf"))]
+    {
+        let _ = task;
+        0
+    }
+}
+
+/// Boosts the effective priority of the given task to `prio`, if `prio` is
+/// higher (numerically smaller) than its current effective priority.
+///
+/// It is used by locks with priority inheritance, such as `axsync::PiMutex`,
+/// to boost the lock holder while higher-priority tasks are waiting for it.
+/// The boost lasts until the holder releases all such locks, see
+/// [`pi_lock_released`].
+pub fn inherit_priority(task: &AxTaskRef, prio: isize) {
+    crate::run_queue::inherit_priority(task, prio)
+}
+
+/// Notifies that the current task has acquired a lock with priority
+/// inheritance.
+pub fn pi_lock_acquired() {
+    current_run_queue().pi_lock_acquired()
+}
+
+/// Notifies that the current task has released a lock with priority
+/// inheritance.
+///
+/// The base priority of the current task is restored once it holds no such
+/// locks.
+pub fn pi_lock_released() {
+    current_run_queue().pi_lock_released()
+}
+
+/// Sets the CPU affinity of the given task.
+///
+/// If the task is ready on a CPU that is not in `cpumask`, it is moved to an
+/// allowed CPU immediately. If the task is running on such a CPU, it is
+/// migrated the next time it is rescheduled, which happens right away if it is
+/// the current task.
+///
+/// Returns `false` if `cpumask` is empty.
+pub fn set_affinity(task: &AxTaskRef, cpumask: AxCpuMask) -> bool {
+    if cpumask.is_empty() {
+        return false;
+    }
+    task.update_cpumask(cpumask);
+    if current().ptr_eq(task) {
+        current_run_queue().migrate_current();
+    } else {
+        crate::run_queue::migrate_task(task);
+    }
+    true
+}
+
+/// Sets the CPU affinity of the current task, and migrates it to an allowed
+/// CPU if necessary.
+///
+/// Returns `false` if `cpumask` is empty.
+pub fn set_current_affinity(cpumask: AxCpuMask) -> bool {
+    set_affinity(current().as_task_ref(), cpumask)
+}
+
+/// Kills the given task: marks it to exit with `exit_code`, and wakes it up
+/// if it is sleeping or in an interruptible wait (e.g.,
+/// [`WaitQueue::wait_until_interruptible`]), which then returns
+/// [`Interrupted`].
+///
+/// The cancellation is cooperative: the task exits at its next cancellation
+/// point, i.e., when it calls [`yield_now`], [`sleep`], [`sleep_until`] or
+/// [`exit_if_killed`]. Its joiners then get `exit_code`.
+///
+/// Returns `false` if the task is an idle, init or unkillable task (see
+/// [`TaskInner::set_unkillable`]), has exited, or has already been killed.
+pub fn kill_task(task: &AxTaskRef, exit_code: i32) -> bool {
+    crate::run_queue::kill_task(task, exit_code)
+}
+
+/// Exits the current task if it has been killed by [`kill_task`], with the
+/// exit code given there.
+///
+/// Long-running tasks that never yield or sleep should call it periodically.
+pub fn exit_if_killed() {
+    if let Some(exit_code) = current().kill_exit_code() {
+        exit(exit_code);
+    }
 }
 
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
+///
+/// It exits the current task if it has been killed.
 pub fn yield_now() {
-    RUN_QUEUE.lock().yield_current();
+    exit_if_killed();
+    current_run_queue().yield_current();
 }
 
 /// Current task is going to sleep for the given duration.
@@ -147,17 +346,20 @@ pub fn sleep(dur: core::time::Duration) {
 
 /// Current task is going to sleep, it will be woken up at the given deadline.
 ///
-/// If the feature `irq` is not enabled, it uses busy-wait instead.
+/// If the feature `irq` is not enabled, it uses busy-wait instead. It exits
+/// the current task if it has been killed, before or during the sleep.
 pub fn sleep_until(deadline: axhal::time::TimeValue) {
+    exit_if_killed();
     #[cfg(feature = "irq")]
-    RUN_QUEUE.lock().sleep_until(deadline);
+    current_run_queue().sleep_until(deadline);
     #[cfg(not(feature = "irq"))]
     axhal::time::busy_wait_until(deadline);
+    exit_if_killed();
 }
 
 /// Exits the current task.
 pub fn exit(exit_code: i32) -> ! {
-    RUN_QUEUE.lock().exit_current(exit_code)
+    current_run_queue().exit_current(exit_code)
 }
 
 /// The idle task routine.
@@ -168,6 +370,19 @@ pub fn run_idle() -> ! {
         yield_now();
         debug!("idle task: waiting for IRQs...");
         #[cfg(feature = "irq")]
-        axhal::arch::wait_for_irqs();
+        {
+            // Stop the tick before checking the run queue, in case a task is
+            // woken up in between.
+            crate::timers::stop_tick();
+            {
+                // IRQs stay disabled from the check until waiting, so that a
+                // reschedule IPI in between is not handled before waiting.
+                let rq = current_run_queue();
+                if rq.is_empty() {
+                    axhal::arch::enable_irqs_and_wait();
+                }
+            }
+            crate::timers::restart_tick();
+        }
     }
 }
@@ -0,0 +1,107 @@
+use core::fmt;
+
+const _: () = assert!(
+    axconfig::SMP <= usize::BITS as usize,
+    "the number of CPUs exceeds the capacity of `AxCpuMask`"
+);
+
+/// A set of CPUs that a task is allowed to run on (CPU affinity).
+///
+/// It is a bitmap indexed by the CPU ID, only the lowest [`axconfig::SMP`]
+/// bits are valid.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct AxCpuMask(usize);
+
+impl AxCpuMask {
+    const VALID_BITS: usize = usize::MAX >> (usize::BITS as usize - axconfig::SMP);
+
+    /// Creates an empty mask, which contains no CPU.
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Creates a mask that contains all CPUs.
+    pub const fn full() -> Self {
+        Self(Self::VALID_BITS)
+    }
+
+    /// Creates a mask that contains only the given CPU.
+    ///
+    /// The mask is empty if `cpu_id` is out of range.
+    pub const fn one_shot(cpu_id: usize) -> Self {
+        if cpu_id < axconfig::SMP {
+            Self(1 << cpu_id)
+        } else {
+            Self(0)
+        }
+    }
+
+    /// Creates a mask from the raw bitmap. Bits of nonexistent CPUs are
+    /// ignored.
+    pub const fn from_raw_bits(bits: usize) -> Self {
+        Self(bits & Self::VALID_BITS)
+    }
+
+    /// Returns the raw bitmap of the mask.
+    pub const fn as_raw_bits(&self) -> usize {
+        self.0
+    }
+
+    /// Whether the mask contains no CPU.
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether the mask contains the given CPU.
+    pub const fn get(&self, cpu_id: usize) -> bool {
+        cpu_id < axconfig::SMP && self.0 & (1 << cpu_id) != 0
+    }
+
+    /// Adds (`value` is `true`) or removes (`value` is `false`) the given CPU
+    /// to or from the mask.
+    ///
+    /// CPUs that are out of range are ignored.
+    pub fn set(&mut self, cpu_id: usize, value: bool) {
+        if cpu_id < axconfig::SMP {
+            if value {
+                self.0 |= 1 << cpu_id;
+            } else {
+                self.0 &= !(1 << cpu_id);
+            }
+        }
+    }
+
+    /// Returns the number of CPUs in the mask.
+    pub const fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Returns the ID of the first CPU in the mask, or [`None`] if the mask is
+    /// empty.
+    pub const fn first_index(&self) -> Option<usize> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(self.0.trailing_zeros() as usize)
+        }
+    }
+
+    /// Returns an iterator over the IDs of CPUs in the mask, in ascending
+    /// order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> {
+        let bits = self.0;
+        (0..axconfig::SMP).filter(move |&i| bits & (1 << i) != 0)
+    }
+}
+
+impl Default for AxCpuMask {
+    fn default() -> Self {
+        Self::full()
+    }
+}
+
+impl fmt::Debug for AxCpuMask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
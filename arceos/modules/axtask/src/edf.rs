@@ -0,0 +1,305 @@
+//! Earliest Deadline First (EDF) real-time scheduling.
+//!
+//! Each real-time task is described by its (runtime, deadline, period)
+//! parameters, and runs as a [constant bandwidth server][1]: it may consume at
+//! most `runtime` of CPU time in each `period`, and its jobs are scheduled by
+//! their absolute deadlines. Ready tasks with deadlines always take precedence
+//! over tasks of other [scheduling classes](crate::SchedClass).
+//!
+//! The servers are hard: a task that has exhausted its budget is throttled,
+//! i.e., it does not run until its next period, when the budget is
+//! replenished. So other tasks can use the capacity not reserved.
+//!
+//! [1]: https://en.wikipedia.org/wiki/Constant_bandwidth_server
+
+use core::time::Duration;
+
+#[cfg(feature = "sched_edf")]
+pub(crate) use self::entity::DeadlineEntity;
+
+/// Parameters of a real-time task under the EDF scheduler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineParams {
+    /// The maximum CPU time that the task can consume in each period.
+    pub runtime: Duration,
+    /// The deadline of each job, relative to its activation. It must not be
+    /// longer than the period.
+    pub deadline: Duration,
+    /// The minimum interval between two activations (jobs) of the task.
+    pub period: Duration,
+}
+
+/// The error type of setting the [`DeadlineParams`] of a task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadlineError {
+    /// The scheduler in use does not support deadlines, i.e., the `sched_edf`
+    /// feature is not enabled.
+    Unsupported,
+    /// The parameters are invalid, it must satisfy
+    /// `0 < runtime <= deadline <= period`.
+    InvalidParams,
+    /// Admitting the task would make the total bandwidth of real-time tasks
+    /// on the CPU exceed its capacity.
+    AdmissionFailed,
+}
+
+#[cfg(feature = "sched_edf")]
+impl DeadlineParams {
+    fn is_valid(&self) -> bool {
+        !self.runtime.is_zero() && self.runtime <= self.deadline && self.deadline <= self.period
+    }
+}
+
+#[cfg(feature = "sched_edf")]
+mod entity {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use kspin::SpinNoIrq;
+
+    use super::{DeadlineError, DeadlineParams};
+
+    /// The fraction of the CPU capacity that can be reserved by real-time
+    /// tasks, in percent. The rest is left for other tasks.
+    const MAX_BANDWIDTH_PERCENT: u64 = 95;
+
+    /// The fixed-point shift of bandwidths (runtime / period).
+    const BW_SHIFT: u32 = 20;
+
+    /// The maximum bandwidth that can be reserved on each CPU.
+    const MAX_BANDWIDTH: u64 = (1 << BW_SHIFT) * MAX_BANDWIDTH_PERCENT / 100;
+
+    /// The total bandwidth of the admitted real-time tasks on each CPU.
+    static BANDWIDTH: [SpinNoIrq<u64>; axconfig::SMP] =
+        [const { SpinNoIrq::new(0) }; axconfig::SMP];
+
+    fn bandwidth(params: &DeadlineParams) -> u64 {
+        ((params.runtime.as_nanos() << BW_SHIFT) / params.period.as_nanos()) as u64
+    }
+
+    struct EdfState {
+        params: Option<DeadlineParams>,
+        /// The CPU that the bandwidth is reserved on.
+        cpu: usize,
+        /// The absolute deadline of the current job, in nanoseconds.
+        deadline: u64,
+        /// The remaining runtime of the current job, in nanoseconds.
+        budget: i64,
+        /// When the task started running since last accounting.
+        exec_start: u64,
+        /// Whether the current job has missed its deadline.
+        missed: bool,
+    }
+
+    impl EdfState {
+        /// Starts a new job at `now` with a full budget.
+        fn replenish(&mut self, params: &DeadlineParams, now: u64) {
+            self.deadline = now + params.deadline.as_nanos() as u64;
+            self.budget = params.runtime.as_nanos() as i64;
+            self.missed = false;
+        }
+
+        /// Charges the CPU time consumed since the last accounting.
+        fn account(&mut self, now: u64) {
+            self.budget -= now.saturating_sub(self.exec_start) as i64;
+            self.exec_start = now;
+        }
+
+        /// Returns `true` if the deadline of the current job is missed for the
+        /// first time.
+        fn check_miss(&mut self, now: u64) -> bool {
+            if self.params.is_some() && !self.missed && now > self.deadline {
+                self.missed = true;
+                true
+            } else {
+                false
+            }
+        }
+    }
+
+    /// The EDF scheduling state of a task.
+    ///
+    /// The absolute deadline only changes while the task is not in a ready
+    /// queue, so it can be used as the key of the queue.
+    pub(crate) struct DeadlineEntity {
+        state: SpinNoIrq<EdfState>,
+        misses: AtomicU64,
+    }
+
+    impl DeadlineEntity {
+        pub const fn new() -> Self {
+            Self {
+                state: SpinNoIrq::new(EdfState {
+                    params: None,
+                    cpu: 0,
+                    deadline: 0,
+                    budget: 0,
+                    exec_start: 0,
+                    missed: false,
+                }),
+                misses: AtomicU64::new(0),
+            }
+        }
+
+        pub fn params(&self) -> Option<DeadlineParams> {
+            self.state.lock().params
+        }
+
+        pub fn misses(&self) -> u64 {
+            self.misses.load(Ordering::Relaxed)
+        }
+
+        /// Returns the absolute deadline of the current job, or [`None`] if
+        /// the task has no deadline parameters.
+        pub fn deadline(&self) -> Option<u64> {
+            let state = self.state.lock();
+            state.params.map(|_| state.deadline)
+        }
+
+        /// Returns the CPU that the bandwidth is reserved on, or [`None`] if
+        /// the task has no deadline parameters.
+        pub fn reserved_cpu(&self) -> Option<usize> {
+            let state = self.state.lock();
+            state.params.map(|_| state.cpu)
+        }
+
+        /// Sets (or clears if `params` is [`None`]) the deadline parameters,
+        /// with admission control on the given CPU.
+        ///
+        /// It must not be called while the task is in a ready queue.
+        pub fn set_params(
+            &self,
+            params: Option<DeadlineParams>,
+            cpu: usize,
+        ) -> Result<(), DeadlineError> {
+            if params.is_some_and(|p| !p.is_valid()) {
+                return Err(DeadlineError::InvalidParams);
+            }
+            let mut state = self.state.lock();
+            let old_bw = state.params.as_ref().map_or(0, bandwidth);
+            let new_bw = params.as_ref().map_or(0, bandwidth);
+            // The old reservation is kept if the new one is rejected.
+            let released = if state.cpu == cpu { old_bw } else { 0 };
+            let mut total = BANDWIDTH[cpu].lock();
+            if *total - released + new_bw > MAX_BANDWIDTH {
+                return Err(DeadlineError::AdmissionFailed);
+            }
+            *total = *total - released + new_bw;
+            drop(total);
+            if state.cpu != cpu {
+                *BANDWIDTH[state.cpu].lock() -= old_bw;
+                state.cpu = cpu;
+            }
+
+            state.params = params;
+            if let Some(params) = params {
+                let now = axhal::time::monotonic_time_nanos();
+                state.replenish(&params, now);
+                state.exec_start = now;
+            }
+            Ok(())
+        }
+
+        /// Moves the reserved bandwidth to another CPU, so that the task can
+        /// run there. Returns `false` if there is not enough bandwidth left on
+        /// that CPU.
+        ///
+        /// It always succeeds if the task has no deadline parameters.
+        pub fn migrate(&self, cpu: usize) -> bool {
+            let mut state = self.state.lock();
+            let Some(params) = state.params else {
+                return true;
+            };
+            if state.cpu == cpu {
+                return true;
+            }
+            let bw = bandwidth(&params);
+            {
+                let mut total = BANDWIDTH[cpu].lock();
+                if *total + bw > MAX_BANDWIDTH {
+                    return false;
+                }
+                *total += bw;
+            }
+            *BANDWIDTH[state.cpu].lock() -= bw;
+            state.cpu = cpu;
+            true
+        }
+
+        /// Applies the CBS wakeup rule when the task becomes ready: keep the
+        /// current deadline and budget only if they do not exceed the reserved
+        /// bandwidth.
+        ///
+        /// A throttled task becomes ready when its budget is replenished, and
+        /// starts a new job then, as its budget is exhausted.
+        pub fn wakeup(&self, now: u64) {
+            let mut state = self.state.lock();
+            if let Some(params) = state.params {
+                let remaining = state.deadline.saturating_sub(now) as u128;
+                if state.budget <= 0
+                    || remaining == 0
+                    || state.budget as u128 * params.period.as_nanos()
+                        > remaining * params.runtime.as_nanos()
+                {
+                    state.replenish(&params, now);
+                }
+            }
+        }
+
+        /// Called when the task is picked to run.
+        pub fn start(&self, now: u64) {
+            let mut state = self.state.lock();
+            state.exec_start = now;
+            if state.check_miss(now) {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        /// Called when the task stops running.
+        pub fn stop(&self, now: u64) {
+            self.state.lock().account(now);
+        }
+
+        /// Called on timer ticks while the task is running.
+        ///
+        /// Returns `true` if the budget is exhausted, in which case the task
+        /// should be switched out and [throttled](Self::throttle).
+        pub fn tick(&self, now: u64) -> bool {
+            let mut state = self.state.lock();
+            if state.params.is_none() {
+                return false;
+            }
+            state.account(now);
+            if state.check_miss(now) {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+            }
+            state.budget <= 0
+        }
+
+        /// Called when the task is switched out. If its budget is exhausted,
+        /// returns the time when the budget is replenished, i.e., the start of
+        /// the next period, in nanoseconds.
+        ///
+        /// The task is throttled then: it must not be put into a ready queue
+        /// until that time, when [`wakeup`](Self::wakeup) is called.
+        pub fn throttle(&self, now: u64) -> Option<u64> {
+            let mut state = self.state.lock();
+            let params = state.params?;
+            state.account(now);
+            if state.budget > 0 {
+                return None;
+            }
+            let activation = state.deadline - params.deadline.as_nanos() as u64;
+            Some(activation + params.period.as_nanos() as u64)
+        }
+    }
+
+    impl Drop for DeadlineEntity {
+        fn drop(&mut self) {
+            // Release the reserved bandwidth.
+            let state = self.state.get_mut();
+            if let Some(params) = state.params {
+                *BANDWIDTH[state.cpu].lock() -= bandwidth(&params);
+            }
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Async/await support.
+//!
+//! Futures [spawned](spawn) to the executor are polled by executor worker
+//! tasks, and [`block_on`] polls a future on the current task. In both cases,
+//! wakers are backed by [`WaitQueue`] notifications: waking a spawned future
+//! puts it to the ready queue and notifies an idle worker, and waking a future
+//! in [`block_on`] notifies the blocked task.
+//!
+//! With the `irq` feature, futures can also wait for a deadline with [`sleep`]
+//! and [`sleep_until`], which are driven by [`Timer`](crate::timers::Timer)s.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::pin::{pin, Pin};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use kspin::SpinNoIrq;
+
+use crate::{TaskInner, WaitQueue};
+
+/// Runs a future to completion on the current task.
+///
+/// The task blocks while the future is pending, until it is woken.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    struct Signal {
+        woken: AtomicBool,
+        wq: WaitQueue,
+    }
+
+    impl Wake for Signal {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.woken.store(true, Ordering::Release);
+            self.wq.notify_one(false);
+        }
+    }
+
+    let mut future = pin!(future);
+    let signal = Arc::new(Signal {
+        woken: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    });
+    let waker = Waker::from(signal.clone());
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        signal
+            .wq
+            .wait_until(|| signal.woken.swap(false, Ordering::Acquire));
+    }
+}
+
+const IDLE: u8 = 0;
+const SCHEDULED: u8 = 1;
+const RUNNING: u8 = 2;
+const RUNNING_NOTIFIED: u8 = 3;
+const DONE: u8 = 4;
+
+/// A future spawned to the executor.
+struct AsyncTask {
+    state: AtomicU8,
+    /// Only accessed by the worker that moves the state from `SCHEDULED` to
+    /// `RUNNING`.
+    future: UnsafeCell<Pin<Box<dyn Future<Output = ()> + Send>>>,
+}
+
+unsafe impl Sync for AsyncTask {}
+
+impl AsyncTask {
+    fn run(self: Arc<Self>) {
+        if self
+            .state
+            .compare_exchange(SCHEDULED, RUNNING, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        let waker = Waker::from(self.clone());
+        let mut cx = Context::from_waker(&waker);
+        // Safety: the state is `RUNNING`, no one else accesses the future.
+        let future = unsafe { &mut *self.future.get() };
+        if future.as_mut().poll(&mut cx).is_ready() {
+            self.state.store(DONE, Ordering::Release);
+        } else if self
+            .state
+            .compare_exchange(RUNNING, IDLE, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            // Woken up while it was being polled.
+            self.state.store(SCHEDULED, Ordering::Release);
+            EXECUTOR.enqueue(self);
+        }
+    }
+}
+
+impl Wake for AsyncTask {
+    fn wake(self: Arc<Self>) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            let new = match state {
+                IDLE => SCHEDULED,
+                RUNNING => RUNNING_NOTIFIED,
+                _ => return,
+            };
+            match self
+                .state
+                .compare_exchange(state, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => break,
+                Err(s) => state = s,
+            }
+        }
+        if state == IDLE {
+            EXECUTOR.enqueue(self);
+        }
+    }
+}
+
+struct Executor {
+    ready: SpinNoIrq<VecDeque<Arc<AsyncTask>>>,
+    wq: WaitQueue,
+    started: AtomicBool,
+}
+
+static EXECUTOR: Executor = Executor {
+    ready: SpinNoIrq::new(VecDeque::new()),
+    wq: WaitQueue::new(),
+    started: AtomicBool::new(false),
+};
+
+impl Executor {
+    fn enqueue(&self, task: Arc<AsyncTask>) {
+        self.ready.lock().push_back(task);
+        self.wq.notify_one(false);
+    }
+
+    /// Spawns the worker tasks on the first use, one for each CPU.
+    fn start(&self) {
+        if self.started.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        for i in 0..axconfig::SMP {
+            let mut task = TaskInner::new(
+                || EXECUTOR.worker_loop(),
+                alloc::format!("async/{}", i),
+                axconfig::TASK_STACK_SIZE,
+            );
+            task.set_unkillable();
+            crate::spawn_task(task);
+        }
+    }
+
+    fn worker_loop(&self) {
+        loop {
+            self.wq.wait_until(|| !self.ready.lock().is_empty());
+            loop {
+                let task = self.ready.lock().pop_front();
+                match task {
+                    Some(task) => task.run(),
+                    None => break,
+                }
+            }
+        }
+    }
+}
+
+struct JoinState<T> {
+    output: Option<T>,
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+/// A handle to wait for a spawned future to complete, and get its output.
+///
+/// It is a future itself. Dropping the handle detaches the future, which
+/// keeps running.
+pub struct JoinHandle<T> {
+    state: Arc<SpinNoIrq<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Whether the future has completed.
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().finished
+    }
+
+    /// Blocks the current task until the future completes, and returns its
+    /// output.
+    pub fn join(self) -> T {
+        block_on(self)
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.lock();
+        if let Some(output) = state.output.take() {
+            Poll::Ready(output)
+        } else {
+            assert!(!state.finished, "`JoinHandle` polled after completion");
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Spawns a future to run on the executor worker tasks.
+///
+/// The future may be polled on any CPU. It should not block the worker task
+/// for long, which delays other futures.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let state = Arc::new(SpinNoIrq::new(JoinState {
+        output: None,
+        finished: false,
+        waker: None,
+    }));
+    let join_state = state.clone();
+    let task = Arc::new(AsyncTask {
+        state: AtomicU8::new(SCHEDULED),
+        future: UnsafeCell::new(Box::pin(async move {
+            let output = future.await;
+            let waker = {
+                let mut state = join_state.lock();
+                state.output = Some(output);
+                state.finished = true;
+                state.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        })),
+    });
+    EXECUTOR.start();
+    EXECUTOR.enqueue(task);
+    JoinHandle { state }
+}
+
+/// Yields to other futures once.
+pub async fn yield_now() {
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    YieldNow(false).await
+}
+
+#[cfg(feature = "irq")]
+pub use self::sleep::{sleep, sleep_until, Sleep};
+
+#[cfg(feature = "irq")]
+mod sleep {
+    use alloc::sync::Arc;
+    use core::future::Future;
+    use core::pin::Pin;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use core::task::{Context, Poll, Waker};
+    use core::time::Duration;
+
+    use axhal::time::{wall_time, TimeValue};
+    use kspin::SpinNoIrq;
+
+    use crate::timers::Timer;
+
+    struct Shared {
+        fired: AtomicBool,
+        waker: SpinNoIrq<Option<Waker>>,
+    }
+
+    /// A future that completes at a deadline, returned by [`sleep`] and
+    /// [`sleep_until`].
+    pub struct Sleep {
+        deadline: TimeValue,
+        shared: Arc<Shared>,
+        timer: Option<Timer>,
+    }
+
+    impl Future for Sleep {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.shared.fired.load(Ordering::Acquire) || wall_time() >= self.deadline {
+                return Poll::Ready(());
+            }
+            *self.shared.waker.lock() = Some(cx.waker().clone());
+            if self.timer.is_none() {
+                let shared = self.shared.clone();
+                let timer = Timer::new(move || {
+                    shared.fired.store(true, Ordering::Release);
+                    let waker = shared.waker.lock().take();
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                });
+                timer.arm_oneshot(self.deadline);
+                self.timer = Some(timer);
+            }
+            Poll::Pending
+        }
+    }
+
+    /// Returns a future that completes after `dur`.
+    pub fn sleep(dur: Duration) -> Sleep {
+        sleep_until(wall_time() + dur)
+    }
+
+    /// Returns a future that completes at the given deadline (in wall time).
+    pub fn sleep_until(deadline: TimeValue) -> Sleep {
+        Sleep {
+            deadline,
+            shared: Arc::new(Shared {
+                fired: AtomicBool::new(false),
+                waker: SpinNoIrq::new(None),
+            }),
+            timer: None,
+        }
+    }
+}
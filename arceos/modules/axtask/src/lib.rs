@@ -1,8 +1,9 @@
 //! [ArceOS](https://github.com/arceos-org/arceos) task management module.
 //!
 //! This module provides primitives for task management, including task
-//! creation, scheduling, sleeping, termination, etc. The scheduler algorithm
-//! is configurable by cargo features.
+//! creation, scheduling, sleeping, termination, etc. Tasks are scheduled by
+//! their [scheduling classes](SchedClass), and the [policy](SchedPolicy) of
+//! normal tasks can be selected at boot time by [`set_sched_policy`].
 //!
 //! # Cargo Features
 //!
@@ -10,20 +11,34 @@
 //!   management and scheduling is used, as well as more task-related APIs.
 //!   Otherwise, only a few APIs with naive implementation is available.
 //! - `irq`: Interrupts are enabled. If this feature is enabled, timer-based
-//!    APIs can be used, such as [`sleep`], [`sleep_until`], and
-//!    [`WaitQueue::wait_timeout`].
+//!    APIs can be used, such as [`sleep`], [`sleep_until`],
+//!    [`WaitQueue::wait_timeout`], and [`Timer`](timers::Timer) callbacks. Timer
+//!    interrupts are deadline-driven, and idle CPUs stop their periodic ticks.
 //! - `preempt`: Enable preemptive scheduling.
-//! - `sched_fifo`: Use the [FIFO cooperative scheduler][1]. It also enables the
-//!   `multitask` feature if it is enabled. This feature is enabled by default,
-//!   and it can be overriden by other scheduler features.
-//! - `sched_rr`: Use the [Round-robin preemptive scheduler][2]. It also enables
-//!   the `multitask` and `preempt` features if it is enabled.
-//! - `sched_cfs`: Use the [Completely Fair Scheduler][3]. It also enables the
-//!   the `multitask` and `preempt` features if it is enabled.
+//! - `smp`: Enable SMP (symmetric multiprocessing) support. Each CPU has its
+//!   own run queue, newly spawned tasks are distributed among CPUs, and idle
+//!   CPUs steal ready tasks from busy ones.
+//! - `paging`: Map kernel stacks in a dedicated virtual region with guard
+//!   pages, to detect stack overflows. Without this feature, overflows are
+//!   detected by checking a canary at the bottom of the stack on every context
+//!   switch.
+//! - `watchdog`: Detect CPUs that have not scheduled (soft lockups) or taken
+//!   timer interrupts (hard lockups) for a while, and report or panic. It also
+//!   enables the `multitask` and `irq` features.
+//! - `sched_fifo`: Use the [FIFO](SchedPolicy::Fifo) policy by default. It also
+//!   enables the `multitask` feature if it is enabled. This feature is enabled
+//!   by default, and it can be overriden by other scheduler features.
+//! - `sched_rr`: Use the [Round-robin](SchedPolicy::RoundRobin) policy by
+//!   default. It also enables the `multitask` and `preempt` features if it is
+//!   enabled.
+//! - `sched_cfs`: Use the [Completely Fair Scheduler](SchedPolicy::Cfs) policy
+//!   by default. It also enables the `multitask` and `preempt` features if it
+//!   is enabled.
+//! - `sched_edf`: Enable the [Earliest Deadline First][1] scheduling of
+//!   real-time tasks with deadlines (see [`set_current_deadline`]). It also
+//!   enables the `multitask` and `preempt` features if it is enabled.
 //!
-//! [1]: scheduler::FifoScheduler
-//! [2]: scheduler::RRScheduler
-//! [3]: scheduler::CFScheduler
+//! [1]: https://en.wikipedia.org/wiki/Earliest_deadline_first_scheduling
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
@@ -42,14 +57,25 @@ cfg_if::cfg_if! {
         extern crate log;
         extern crate alloc;
 
+        mod cpumask;
+        mod edf;
+        mod registry;
         mod run_queue;
+        mod sched;
+        mod stack;
+        mod stats;
         mod task;
         mod task_ext;
         mod api;
         mod wait_queue;
 
         #[cfg(feature = "irq")]
-        mod timers;
+        pub mod timers;
+        #[cfg(feature = "watchdog")]
+        mod watchdog;
+
+        pub mod future;
+        pub mod workqueue;
 
         #[doc(cfg(feature = "multitask"))]
         pub use self::api::*;
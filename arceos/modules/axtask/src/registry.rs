@@ -0,0 +1,32 @@
+//! The table of all live tasks.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use kspin::SpinNoIrq;
+
+use crate::{AxTaskRef, TaskId};
+
+/// All tasks that have been created and not yet recycled, indexed by their
+/// IDs.
+///
+/// Tasks are added when they are created, and removed when they are recycled
+/// by the `gc` task after exiting.
+static TASKS: SpinNoIrq<BTreeMap<TaskId, AxTaskRef>> = SpinNoIrq::new(BTreeMap::new());
+
+pub(crate) fn register(task: &AxTaskRef) {
+    TASKS.lock().insert(task.id(), task.clone());
+}
+
+pub(crate) fn unregister(task: &AxTaskRef) {
+    TASKS.lock().remove(&task.id());
+}
+
+pub(crate) fn get(id: TaskId) -> Option<AxTaskRef> {
+    TASKS.lock().get(&id).cloned()
+}
+
+/// Returns references to all live tasks, ordered by their IDs.
+pub(crate) fn tasks() -> Vec<AxTaskRef> {
+    TASKS.lock().values().cloned().collect()
+}
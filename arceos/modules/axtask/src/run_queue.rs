@@ -1,14 +1,14 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use core::ops::Deref;
+use core::sync::atomic::{fence, Ordering};
+use kernel_guard::NoPreemptIrqSave;
 use kspin::SpinNoIrq;
 use lazyinit::LazyInit;
 use scheduler::BaseScheduler;
 
 use crate::task::{CurrentTask, TaskState};
-use crate::{AxTaskRef, Scheduler, TaskInner, WaitQueue};
-
-// TODO: per-CPU
-pub(crate) static RUN_QUEUE: LazyInit<SpinNoIrq<AxRunQueue>> = LazyInit::new();
+use crate::{AxTaskRef, SchedClass, Scheduler, TaskInner, WaitQueue};
 
 // TODO: per-CPU
 static EXITED_TASKS: SpinNoIrq<VecDeque<AxTaskRef>> = SpinNoIrq::new(VecDeque::new());
@@ -18,55 +18,139 @@ static WAIT_FOR_EXIT: WaitQueue = WaitQueue::new();
 #[percpu::def_percpu]
 static IDLE_TASK: LazyInit<AxTaskRef> = LazyInit::new();
 
+/// The run queue of the current CPU.
+#[percpu::def_percpu]
+static RUN_QUEUE: LazyInit<AxRunQueue> = LazyInit::new();
+
+/// References to the run queues of all CPUs, indexed by the CPU ID.
+///
+/// It is used to put tasks on remote CPUs and to steal tasks from them.
+static RUN_QUEUES: [LazyInit<&'static AxRunQueue>; axconfig::SMP] =
+    [const { LazyInit::new() }; axconfig::SMP];
+
+/// The task that was switched out by the last context switch on this CPU.
+///
+/// It holds a reference to the previous task until its context is saved
+/// completely, i.e., until the next task starts running.
+#[cfg(feature = "smp")]
+#[percpu::def_percpu]
+static PREV_TASK: Option<AxTaskRef> = None;
+
 pub(crate) struct AxRunQueue {
-    scheduler: Scheduler,
+    cpu_id: usize,
+    scheduler: SpinNoIrq<Scheduler>,
+}
+
+/// A reference to the run queue of the current CPU.
+///
+/// Both preemption and local IRQs are disabled while the reference is held, so
+/// the current task can not be migrated to other CPUs.
+pub(crate) struct AxRunQueueRef {
+    inner: &'static AxRunQueue,
+    _guard: NoPreemptIrqSave,
+}
+
+impl Deref for AxRunQueueRef {
+    type Target = AxRunQueue;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
 }
 
 impl AxRunQueue {
-    pub fn new() -> SpinNoIrq<Self> {
-        let gc_task = TaskInner::new(gc_entry, "gc".into(), axconfig::TASK_STACK_SIZE).into_arc();
-        let mut scheduler = Scheduler::new();
-        scheduler.add_task(gc_task);
-        SpinNoIrq::new(Self { scheduler })
+    pub fn new(cpu_id: usize) -> Self {
+        Self {
+            cpu_id,
+            scheduler: SpinNoIrq::new(Scheduler::new()),
+        }
     }
 
-    pub fn add_task(&mut self, task: AxTaskRef) {
-        debug!("task spawn: {}", task.id_name());
+    pub fn add_task(&self, task: AxTaskRef) {
+        debug!("task spawn: {} on CPU {}", task.id_name(), self.cpu_id);
         assert!(task.is_ready());
-        self.scheduler.add_task(task);
+        task.set_cpu_id(self.cpu_id);
+        let mut scheduler = self.scheduler.lock();
+        scheduler.add_task(task);
+        kick_if_idle(self.cpu_id);
+    }
+
+    /// Whether there are no ready tasks in the run queue.
+    #[cfg(feature = "irq")]
+    pub fn is_empty(&self) -> bool {
+        self.scheduler.lock().is_empty()
     }
 
     #[cfg(feature = "irq")]
-    pub fn scheduler_timer_tick(&mut self) {
+    pub fn scheduler_timer_tick(&self) {
         let curr = crate::current();
-        if !curr.is_idle() && self.scheduler.task_tick(curr.as_task_ref()) {
+        if !curr.is_idle() && self.scheduler.lock().task_tick(curr.as_task_ref()) {
             #[cfg(feature = "preempt")]
             curr.set_preempt_pending(true);
         }
     }
 
-    pub fn yield_current(&mut self) {
+    pub fn yield_current(&self) {
         let curr = crate::current();
         trace!("task yield: {}", curr.id_name());
         assert!(curr.is_running());
         self.resched(false);
     }
 
-    pub fn set_current_priority(&mut self, prio: isize) -> bool {
-        self.scheduler
-            .set_priority(crate::current().as_task_ref(), prio)
+    pub fn set_current_priority(&self, prio: isize) -> bool {
+        let curr = crate::current();
+        let mut priority = curr.priority_info().lock();
+        let mut scheduler = self.scheduler.lock();
+        if !scheduler.set_priority(curr.as_task_ref(), prio) {
+            return false;
+        }
+        priority.base = prio;
+        if priority.pi_locks > 0 && priority.effective < prio {
+            // Keep the inherited priority until all PI locks are released.
+            scheduler.set_priority(curr.as_task_ref(), priority.effective);
+        } else {
+            priority.effective = prio;
+        }
+        true
+    }
+
+    /// Records that the current task has acquired a lock with priority
+    /// inheritance.
+    pub fn pi_lock_acquired(&self) {
+        crate::current().priority_info().lock().pi_locks += 1;
+    }
+
+    /// Records that the current task has released a lock with priority
+    /// inheritance, and restores its base priority if it holds no such locks
+    /// anymore.
+    pub fn pi_lock_released(&self) {
+        let curr = crate::current();
+        let mut priority = curr.priority_info().lock();
+        assert!(priority.pi_locks > 0);
+        priority.pi_locks -= 1;
+        if priority.pi_locks == 0 && priority.effective != priority.base {
+            debug!(
+                "task priority restore: {}, {} -> {}",
+                curr.id_name(),
+                priority.effective,
+                priority.base
+            );
+            self.scheduler
+                .lock()
+                .set_priority(curr.as_task_ref(), priority.base);
+            priority.effective = priority.base;
+        }
     }
 
     #[cfg(feature = "preempt")]
-    pub fn preempt_resched(&mut self) {
+    pub fn preempt_resched(&self) {
         let curr = crate::current();
         assert!(curr.is_running());
 
-        // When we get the mutable reference of the run queue, we must
-        // have held the `SpinNoIrq` lock with both IRQs and preemption
-        // disabled. So we need to set `current_disable_count` to 1 in
-        // `can_preempt()` to obtain the preemption permission before
-        //  locking the run queue.
+        // When we get the reference of the run queue, we must have held the
+        // `NoPreemptIrqSave` guard with both IRQs and preemption disabled. So
+        // we need to set `current_disable_count` to 1 in `can_preempt()` to
+        // obtain the preemption permission.
         let can_preempt = curr.can_preempt(1);
 
         debug!(
@@ -81,7 +165,7 @@ impl AxRunQueue {
         }
     }
 
-    pub fn exit_current(&mut self, exit_code: i32) -> ! {
+    pub fn exit_current(&self, exit_code: i32) -> ! {
         let curr = crate::current();
         debug!("task exit: {}, exit_code={}", curr.id_name(), exit_code);
         assert!(curr.is_running());
@@ -91,15 +175,15 @@ impl AxRunQueue {
             axhal::misc::terminate();
         } else {
             curr.set_state(TaskState::Exited);
-            curr.notify_exit(exit_code, self);
+            curr.notify_exit(exit_code);
             EXITED_TASKS.lock().push_back(curr.clone());
-            WAIT_FOR_EXIT.notify_one_locked(false, self);
+            WAIT_FOR_EXIT.notify_one(false);
             self.resched(false);
         }
         unreachable!("task exited!");
     }
 
-    pub fn block_current<F>(&mut self, wait_queue_push: F)
+    pub fn block_current<F>(&self, wait_queue_push: F)
     where
         F: FnOnce(AxTaskRef),
     {
@@ -112,25 +196,28 @@ impl AxRunQueue {
         #[cfg(feature = "preempt")]
         assert!(curr.can_preempt(1));
 
+        // The state must be set before the task becomes visible to wakers.
         curr.set_state(TaskState::Blocked);
+        if curr.cancel_block_if_killed() {
+            return;
+        }
         wait_queue_push(curr.clone());
         self.resched(false);
     }
 
-    pub fn unblock_task(&mut self, task: AxTaskRef, resched: bool) {
-        debug!("task unblock: {}", task.id_name());
-        if task.is_blocked() {
-            task.set_state(TaskState::Ready);
-            self.scheduler.add_task(task); // TODO: priority
-            if resched {
-                #[cfg(feature = "preempt")]
-                crate::current().set_preempt_pending(true);
-            }
+    /// Moves the current task to another CPU if it is no longer allowed to run
+    /// on this CPU.
+    pub fn migrate_current(&self) {
+        let curr = crate::current();
+        assert!(curr.is_running());
+        if !curr.is_idle() && !curr.can_run_on(self.cpu_id) {
+            debug!("task migrate: {} from CPU {}", curr.id_name(), self.cpu_id);
+            self.resched(false);
         }
     }
 
     #[cfg(feature = "irq")]
-    pub fn sleep_until(&mut self, deadline: axhal::time::TimeValue) {
+    pub fn sleep_until(&self, deadline: axhal::time::TimeValue) {
         let curr = crate::current();
         debug!("task sleep: {}, deadline={:?}", curr.id_name(), deadline);
         assert!(curr.is_running());
@@ -138,9 +225,17 @@ impl AxRunQueue {
 
         let now = axhal::time::wall_time();
         if now < deadline {
-            crate::timers::set_alarm_wakeup(deadline, curr.clone());
+            curr.set_interruptible(true);
             curr.set_state(TaskState::Blocked);
-            self.resched(false);
+            if !curr.cancel_block_if_killed() {
+                crate::timers::set_alarm_wakeup(deadline, curr.clone());
+                self.resched(false);
+            }
+            curr.set_interruptible(false);
+            if curr.in_timer_list() {
+                // woken up by `kill_task` before the deadline.
+                crate::timers::cancel_alarm(curr.as_task_ref());
+            }
         }
     }
 }
@@ -148,22 +243,85 @@ impl AxRunQueue {
 impl AxRunQueue {
     /// Common reschedule subroutine. If `preempt`, keep current task's time
     /// slice, otherwise reset it.
-    fn resched(&mut self, preempt: bool) {
+    fn resched(&self, preempt: bool) {
         let prev = crate::current();
-        if prev.is_running() {
+        if prev.is_running() && !self.throttle_current(&prev) {
             prev.set_state(TaskState::Ready);
             if !prev.is_idle() {
-                self.scheduler.put_prev_task(prev.clone(), preempt);
+                if prev.can_run_on(self.cpu_id) {
+                    self.scheduler.lock().put_prev_task(prev.clone(), preempt);
+                } else {
+                    // The affinity has been changed, migrate to an allowed CPU.
+                    select_run_queue(prev.as_task_ref()).add_task(prev.clone());
+                }
             }
         }
-        let next = self.scheduler.pick_next_task().unwrap_or_else(|| unsafe {
+        let next = self.pick_next_task().unwrap_or_else(|| unsafe {
             // Safety: IRQs must be disabled at this time.
             IDLE_TASK.current_ref_raw().get_unchecked().clone()
         });
-        self.switch_to(prev, next);
+        #[cfg(feature = "watchdog")]
+        crate::watchdog::touch_sched(&next);
+        self.switch_to(prev, next, preempt);
+    }
+
+    /// Blocks the current task with deadline until its budget is replenished,
+    /// if the budget is exhausted, so that it does not run beyond its reserved
+    /// bandwidth (hard CBS). Returns `true` if the task is throttled.
+    #[cfg(all(feature = "sched_edf", feature = "irq"))]
+    fn throttle_current(&self, curr: &CurrentTask) -> bool {
+        let now = axhal::time::monotonic_time_nanos();
+        let Some(replenish_ns) = curr.throttle_deadline(now) else {
+            return false;
+        };
+        debug!("task throttle: {}", curr.id_name());
+        curr.set_state(TaskState::Blocked);
+        let replenish = replenish_ns + axhal::time::epochoffset_nanos();
+        crate::timers::set_alarm_wakeup(
+            axhal::time::TimeValue::from_nanos(replenish),
+            curr.clone(),
+        );
+        true
+    }
+
+    #[cfg(not(all(feature = "sched_edf", feature = "irq")))]
+    fn throttle_current(&self, _curr: &CurrentTask) -> bool {
+        false
+    }
+
+    fn pick_next_task(&self) -> Option<AxTaskRef> {
+        let next = self.scheduler.lock().pick_next_task();
+        #[cfg(feature = "smp")]
+        let next = next.or_else(|| self.steal_task());
+        next
+    }
+
+    /// Steals a ready task from the run queues of other CPUs.
+    ///
+    /// It is called when there are no ready tasks on the current CPU, so idle
+    /// CPUs can share the load of busy ones. The first task in the picking
+    /// order that is allowed to run on the current CPU is taken, and the other
+    /// tasks are left in place. Tasks with deadlines are taken only if their
+    /// bandwidth can be reserved on the current CPU.
+    #[cfg(feature = "smp")]
+    fn steal_task(&self) -> Option<AxTaskRef> {
+        let task = (1..axconfig::SMP)
+            .filter_map(|i| run_queue_of((self.cpu_id + i) % axconfig::SMP))
+            .find_map(|rq| {
+                rq.scheduler.lock().pick_next_task_if(|task| {
+                    task.can_run_on(self.cpu_id) && task.reserve_on(self.cpu_id)
+                })
+            })?;
+        debug!(
+            "task steal: {} from CPU {} to CPU {}",
+            task.id_name(),
+            task.cpu_id(),
+            self.cpu_id
+        );
+        Some(task)
     }
 
-    fn switch_to(&mut self, prev_task: CurrentTask, next_task: AxTaskRef) {
+    fn switch_to(&self, prev_task: CurrentTask, next_task: AxTaskRef, preempt: bool) {
         trace!(
             "context switch: {} -> {}",
             prev_task.id_name(),
@@ -171,11 +329,30 @@ impl AxRunQueue {
         );
         #[cfg(feature = "preempt")]
         next_task.set_preempt_pending(false);
-        next_task.set_state(TaskState::Running);
         if prev_task.ptr_eq(&next_task) {
+            next_task.set_state(TaskState::Running);
             return;
         }
 
+        #[cfg(not(feature = "paging"))]
+        prev_task.check_stack_canary();
+
+        let now = axhal::time::monotonic_time_nanos();
+        prev_task.accounting().switch_out(now, preempt);
+        next_task.accounting().switch_in(now);
+
+        // The next task may be stolen from another CPU, on which it has not
+        // been switched out completely. Wait for its context to be saved.
+        #[cfg(feature = "smp")]
+        {
+            while next_task.on_cpu() {
+                core::hint::spin_loop();
+            }
+            next_task.set_on_cpu(true);
+        }
+        next_task.set_cpu_id(self.cpu_id);
+        next_task.set_state(TaskState::Running);
+
         unsafe {
             let prev_ctx_ptr = prev_task.ctx_mut_ptr();
             let next_ctx_ptr = next_task.ctx_mut_ptr();
@@ -185,12 +362,248 @@ impl AxRunQueue {
             assert!(Arc::strong_count(prev_task.as_task_ref()) > 1);
             assert!(Arc::strong_count(&next_task) >= 1);
 
+            #[cfg(feature = "smp")]
+            PREV_TASK.current_ref_mut_raw().replace(prev_task.clone());
+
             CurrentTask::set_current(prev_task, next_task);
             (*prev_ctx_ptr).switch_to(&*next_ctx_ptr);
+
+            // Now we are back, maybe on another CPU.
+            #[cfg(feature = "smp")]
+            clear_prev_task_on_cpu();
         }
     }
 }
 
+/// Marks the task switched out by the last context switch on this CPU as not
+/// running on any CPU, so that it can be scheduled on other CPUs.
+///
+/// It must be called by the next task right after the context switch, with
+/// IRQs disabled.
+#[cfg(feature = "smp")]
+pub(crate) fn clear_prev_task_on_cpu() {
+    // Safety: IRQs are disabled, no one else can access `PREV_TASK` on the
+    // current CPU.
+    if let Some(prev_task) = unsafe { PREV_TASK.current_ref_mut_raw() }.take() {
+        prev_task.set_on_cpu(false);
+    }
+}
+
+/// Returns the reference to the run queue of the current CPU.
+///
+/// Preemption and local IRQs are disabled until the reference is dropped.
+pub(crate) fn current_run_queue() -> AxRunQueueRef {
+    let guard = NoPreemptIrqSave::new();
+    AxRunQueueRef {
+        // Safety: preemption is disabled, so the current CPU will not change.
+        inner: unsafe { RUN_QUEUE.current_ref_raw() },
+        _guard: guard,
+    }
+}
+
+/// Returns the run queue of the given CPU, or [`None`] if that CPU has not
+/// initialized its scheduler yet.
+fn run_queue_of(cpu_id: usize) -> Option<&'static AxRunQueue> {
+    RUN_QUEUES.get(cpu_id)?.get().copied()
+}
+
+/// Sends a reschedule IPI to the given CPU after a task is put on its run
+/// queue, if it is another CPU that is idle with its tick stopped.
+///
+/// The scheduler of that CPU must be locked, so that either the idle CPU finds
+/// the task when it checks its run queue, or its tick is seen stopped here.
+#[cfg_attr(not(all(feature = "smp", feature = "irq")), allow(unused_variables))]
+fn kick_if_idle(cpu_id: usize) {
+    #[cfg(all(feature = "smp", feature = "irq"))]
+    if cpu_id != axhal::cpu::this_cpu_id() && crate::timers::tick_stopped(cpu_id) {
+        axhal::irq::send_ipi(axhal::irq::IpiTarget::Cpu(cpu_id), axhal::irq::RESCHED_IPI);
+    }
+}
+
+/// Selects a run queue to put the given task on.
+///
+/// Tasks are distributed among all initialized CPUs in its affinity mask in a
+/// round-robin manner. If none of these CPUs is initialized, the run queue of
+/// the current CPU is used.
+///
+/// Tasks with deadlines are put on the CPU their bandwidth is reserved on, or
+/// another allowed CPU that can take the reservation. If there is no such CPU,
+/// they are distributed as other tasks, with the reservation unchanged.
+#[cfg_attr(not(feature = "smp"), allow(unused_variables))]
+pub(crate) fn select_run_queue(task: &AxTaskRef) -> &'static AxRunQueue {
+    #[cfg(feature = "smp")]
+    {
+        use core::sync::atomic::AtomicUsize;
+        static NEXT_CPU: AtomicUsize = AtomicUsize::new(0);
+
+        let cpumask = task.cpumask();
+        let start = NEXT_CPU.fetch_add(1, Ordering::Relaxed);
+        let candidates = || {
+            (0..axconfig::SMP)
+                .map(move |i| (start + i) % axconfig::SMP)
+                .filter(|&cpu_id| cpumask.get(cpu_id))
+        };
+        if let Some(reserved) = task.reserved_cpu() {
+            if let Some(rq) = core::iter::once(reserved)
+                .filter(|&cpu_id| cpumask.get(cpu_id))
+                .chain(candidates())
+                .filter(|&cpu_id| run_queue_of(cpu_id).is_some() && task.reserve_on(cpu_id))
+                .find_map(run_queue_of)
+            {
+                return rq;
+            }
+        }
+        if let Some(rq) = candidates().find_map(run_queue_of) {
+            return rq;
+        }
+    }
+    current_run_queue().inner
+}
+
+/// Moves the given task to the run queue of an allowed CPU, if it is ready
+/// on a CPU that is not in its affinity mask.
+///
+/// Running tasks are migrated by themselves on the next reschedule, and
+/// blocked tasks are migrated when they are woken up.
+pub(crate) fn migrate_task(task: &AxTaskRef) {
+    let cpu_id = task.cpu_id();
+    if task.can_run_on(cpu_id) {
+        return;
+    }
+    let Some(rq) = run_queue_of(cpu_id) else {
+        return;
+    };
+    let task = {
+        let mut scheduler = rq.scheduler.lock();
+        // A ready task is only in the run queue of the CPU it is bound to, and
+        // it can not be rebound without holding the lock of that run queue.
+        if task.is_ready() && task.cpu_id() == cpu_id {
+            scheduler.remove_task(task)
+        } else {
+            None
+        }
+    };
+    if let Some(task) = task {
+        debug!("task migrate: {} from CPU {}", task.id_name(), cpu_id);
+        select_run_queue(&task).add_task(task);
+    }
+}
+
+/// Changes the scheduling class of the given task, and moves it to the ready
+/// queue of the new class if it is ready.
+pub(crate) fn set_sched_class(task: &AxTaskRef, class: SchedClass) {
+    let cpu_id = task.cpu_id();
+    if let Some(rq) = run_queue_of(cpu_id) {
+        let mut scheduler = rq.scheduler.lock();
+        // See `migrate_task` for why the task can be removed safely.
+        if task.is_ready() && task.cpu_id() == cpu_id {
+            if let Some(task) = scheduler.remove_task(task) {
+                task.set_sched_class(class);
+                scheduler.add_task(task);
+                return;
+            }
+        }
+    }
+    task.set_sched_class(class);
+}
+
+/// Boosts the effective priority of `task` to `prio`, if `prio` is higher
+/// than its current effective priority.
+///
+/// The run queue of the CPU that the task is on is locked, which may not be the
+/// current one.
+pub(crate) fn inherit_priority(task: &AxTaskRef, prio: isize) {
+    let mut priority = task.priority_info().lock();
+    if prio >= priority.effective {
+        return;
+    }
+    let boosted = loop {
+        let cpu_id = task.cpu_id();
+        let rq = run_queue_of(cpu_id).expect("run queue not initialized");
+        let mut scheduler = rq.scheduler.lock();
+        // The task may be migrated before the run queue is locked.
+        if task.cpu_id() == cpu_id {
+            break scheduler.set_priority(task, prio);
+        }
+    };
+    if boosted {
+        debug!(
+            "task priority inherit: {}, {} -> {}",
+            task.id_name(),
+            priority.effective,
+            prio
+        );
+        priority.effective = prio;
+    }
+}
+
+/// Wakes up the given task if it is blocked, and puts it into the run queue of
+/// the CPU it last ran on, or of another allowed CPU if its affinity has been
+/// changed. An idle CPU is woken up by a reschedule IPI.
+///
+/// If `resched` is true and the task is put on the current CPU, the current
+/// task will be preempted when the preemption is enabled, unless the woken
+/// task should not preempt it (see [`SchedTask::preempts`]).
+///
+/// [`SchedTask::preempts`]: crate::sched::SchedTask::preempts
+pub(crate) fn unblock_task(task: AxTaskRef, resched: bool) {
+    debug!("task unblock: {}", task.id_name());
+    if task.transition_state(TaskState::Blocked, TaskState::Ready) {
+        // The task may be still switching out on another CPU, wait for its
+        // context to be saved before making it visible to the schedulers.
+        #[cfg(feature = "smp")]
+        while task.on_cpu() {
+            core::hint::spin_loop();
+        }
+        task.accounting()
+            .wakeup(axhal::time::monotonic_time_nanos());
+
+        let cpu_id = task.cpu_id();
+        let rq = run_queue_of(cpu_id)
+            .filter(|_| task.can_run_on(cpu_id))
+            .unwrap_or_else(|| select_run_queue(&task));
+        let is_local = rq.cpu_id == axhal::cpu::this_cpu_id();
+        task.set_cpu_id(rq.cpu_id);
+        if resched && is_local {
+            #[cfg(feature = "preempt")]
+            {
+                let curr = crate::current();
+                if curr.is_idle() || task.preempts(curr.as_task_ref()) {
+                    curr.set_preempt_pending(true);
+                }
+            }
+        }
+        {
+            let mut scheduler = rq.scheduler.lock();
+            scheduler.add_task(task); // TODO: priority
+            kick_if_idle(rq.cpu_id);
+        }
+    }
+}
+
+/// Marks the given task to exit with `exit_code`, and wakes it up if it is in
+/// an interruptible wait.
+///
+/// Returns `false` if the task is an idle, init or unkillable task, has
+/// exited, or has already been killed.
+pub(crate) fn kill_task(task: &AxTaskRef, exit_code: i32) -> bool {
+    if task.is_idle() || task.is_init() || task.is_unkillable() || task.state() == TaskState::Exited
+    {
+        return false;
+    }
+    if !task.request_kill(exit_code) {
+        return false;
+    }
+    debug!("task kill: {}, exit_code={}", task.id_name(), exit_code);
+    // Pairs with the fence in `cancel_block_if_killed`: either the waiter sees
+    // the kill request and does not sleep, or we see it blocked here.
+    fence(Ordering::SeqCst);
+    if task.is_interruptible() {
+        unblock_task(task.clone(), true);
+    }
+    true
+}
+
 fn gc_entry() {
     loop {
         // Drop all exited tasks and recycle resources.
@@ -199,6 +612,7 @@ fn gc_entry() {
             // Do not do the slow drops in the critical section.
             let task = EXITED_TASKS.lock().pop_front();
             if let Some(task) = task {
+                crate::registry::unregister(&task);
                 if Arc::strong_count(&task) == 1 {
                     // If I'm the last holder of the task, drop it immediately.
                     drop(task);
@@ -213,7 +627,19 @@ fn gc_entry() {
     }
 }
 
+fn init_run_queue(cpu_id: usize) {
+    RUN_QUEUE.with_current(|rq| {
+        rq.init_once(AxRunQueue::new(cpu_id));
+    });
+    // Safety: the run queue of the current CPU is initialized above and never
+    // moved afterwards.
+    let rq: &'static AxRunQueue = unsafe { RUN_QUEUE.current_ref_raw() };
+    RUN_QUEUES[cpu_id].init_once(rq);
+}
+
 pub(crate) fn init() {
+    let cpu_id = axhal::cpu::this_cpu_id();
+
     // Create the `idle` task (not current task).
     const IDLE_TASK_STACK_SIZE: usize = 4096;
     let idle_task = TaskInner::new(|| crate::run_idle(), "idle".into(), IDLE_TASK_STACK_SIZE);
@@ -224,17 +650,29 @@ pub(crate) fn init() {
     // Put the subsequent execution into the `main` task.
     let main_task = TaskInner::new_init("main".into()).into_arc();
     main_task.set_state(TaskState::Running);
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::touch_sched(&main_task);
     unsafe { CurrentTask::init_current(main_task) };
 
-    RUN_QUEUE.init_once(AxRunQueue::new());
+    init_run_queue(cpu_id);
+
+    let mut gc_task = TaskInner::new(gc_entry, "gc".into(), axconfig::TASK_STACK_SIZE);
+    gc_task.set_unkillable();
+    current_run_queue().add_task(gc_task.into_arc());
 }
 
 pub(crate) fn init_secondary() {
+    let cpu_id = axhal::cpu::this_cpu_id();
+
     // Put the subsequent execution into the `idle` task.
     let idle_task = TaskInner::new_init("idle".into()).into_arc();
     idle_task.set_state(TaskState::Running);
     IDLE_TASK.with_current(|i| {
         i.init_once(idle_task.clone());
     });
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::touch_sched(&idle_task);
     unsafe { CurrentTask::init_current(idle_task) }
+
+    init_run_queue(cpu_id);
 }
@@ -0,0 +1,570 @@
+//! Scheduling classes and policies.
+//!
+//! Tasks are scheduled by their [`SchedClass`]es. Ready tasks of a higher
+//! class always run before those of lower classes:
+//!
+//! 1. Tasks with deadline parameters, scheduled by the EDF algorithm (only if
+//!    the `sched_edf` feature is enabled, see [`set_current_deadline`]).
+//! 2. [`SchedClass::RealTime`] tasks, scheduled in the FIFO order.
+//! 3. [`SchedClass::Normal`] tasks, scheduled by the [`SchedPolicy`] selected
+//!    at boot time.
+//!
+//! [`set_current_deadline`]: crate::set_current_deadline
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use core::ops::Deref;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicIsize, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use scheduler::BaseScheduler;
+
+#[cfg(feature = "sched_edf")]
+use crate::edf::{DeadlineEntity, DeadlineError, DeadlineParams};
+
+/// The time slice of normal tasks under the round-robin policy, in ticks.
+const MAX_TIME_SLICE: usize = 5;
+
+/// The weight of nice value 0 under the CFS policy.
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// Weights of nice values from -20 to 19, the same as Linux.
+const NICE_TO_WEIGHT: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, // -20 ~ -11
+    9548, 7620, 6100, 4904, 3906, 3121, 2501, 1991, 1586, 1277, // -10 ~ -1
+    1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, // 0 ~ 9
+    110, 87, 70, 56, 45, 36, 29, 23, 18, 15, // 10 ~ 19
+];
+
+/// The policy to schedule [`SchedClass::Normal`] tasks.
+///
+/// It is selected once at boot time by [`set_sched_policy`], before the
+/// scheduler is initialized. If not selected, it defaults to the one specified
+/// by the cargo features (`sched_rr`, `sched_cfs`), or [`SchedPolicy::Fifo`].
+///
+/// [`set_sched_policy`]: crate::set_sched_policy
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedPolicy {
+    /// First in, first out. A task runs until it blocks, yields or exits.
+    Fifo = 0,
+    /// Round-robin. Like FIFO, but a task is preempted after its time slice
+    /// is used up.
+    RoundRobin = 1,
+    /// The [Completely Fair Scheduler][1], the task with the least virtual
+    /// runtime runs first. The priority is the nice value, ranging from -20
+    /// to 19.
+    ///
+    /// [1]: https://en.wikipedia.org/wiki/Completely_Fair_Scheduler
+    Cfs = 2,
+}
+
+impl SchedPolicy {
+    /// Returns the human-readable name of the policy.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Fifo => "FIFO",
+            Self::RoundRobin => "Round-robin",
+            Self::Cfs => "CFS",
+        }
+    }
+
+    /// Whether running tasks are preempted on timer ticks, which needs the
+    /// `preempt` feature.
+    pub const fn is_preemptive(self) -> bool {
+        !matches!(self, Self::Fifo)
+    }
+
+    const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Fifo),
+            1 => Some(Self::RoundRobin),
+            2 => Some(Self::Cfs),
+            _ => None,
+        }
+    }
+}
+
+impl Default for SchedPolicy {
+    fn default() -> Self {
+        if cfg!(feature = "sched_rr") {
+            Self::RoundRobin
+        } else if cfg!(feature = "sched_cfs") {
+            Self::Cfs
+        } else {
+            Self::Fifo
+        }
+    }
+}
+
+impl FromStr for SchedPolicy {
+    type Err = ();
+
+    /// Parses the policy from `"fifo"`, `"rr"` or `"cfs"` (case-insensitive).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("fifo") {
+            Ok(Self::Fifo)
+        } else if s.eq_ignore_ascii_case("rr") {
+            Ok(Self::RoundRobin)
+        } else if s.eq_ignore_ascii_case("cfs") {
+            Ok(Self::Cfs)
+        } else {
+            Err(())
+        }
+    }
+}
+
+/// The scheduling class of a task.
+#[repr(u8)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SchedClass {
+    /// Real-time tasks. They are scheduled in the FIFO order, and always take
+    /// precedence over normal tasks. A real-time task runs until it blocks,
+    /// yields or exits, or a task with deadline becomes ready.
+    RealTime = 0,
+    /// Normal tasks, scheduled by the [`SchedPolicy`] selected at boot time.
+    #[default]
+    Normal = 1,
+}
+
+impl SchedClass {
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::RealTime,
+            _ => Self::Normal,
+        }
+    }
+}
+
+const POLICY_UNSET: u8 = u8::MAX;
+
+static SCHED_POLICY: AtomicU8 = AtomicU8::new(POLICY_UNSET);
+
+/// Selects the scheduling policy of normal tasks.
+///
+/// Returns `false` if the policy has already been fixed.
+pub(crate) fn set_policy(policy: SchedPolicy) -> bool {
+    SCHED_POLICY
+        .compare_exchange(
+            POLICY_UNSET,
+            policy as u8,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .is_ok()
+}
+
+/// Returns the scheduling policy of normal tasks, and fixes it to the default
+/// if it has not been selected.
+pub(crate) fn policy() -> SchedPolicy {
+    set_policy(SchedPolicy::default());
+    SchedPolicy::from_u8(SCHED_POLICY.load(Ordering::Acquire)).unwrap()
+}
+
+/// Which ready queue a task is in.
+const QUEUED_NONE: u8 = 0;
+#[cfg(feature = "sched_edf")]
+const QUEUED_DEADLINE: u8 = 1;
+const QUEUED_REALTIME: u8 = 2;
+const QUEUED_NORMAL: u8 = 3;
+
+/// A task wrapper for the [`ClassScheduler`].
+pub struct SchedTask<T> {
+    inner: T,
+    class: AtomicU8,
+    /// The ready queue the task is in, only changed with the scheduler lock.
+    queued: AtomicU8,
+    /// Ticks left of the time slice, under the round-robin policy.
+    time_slice: AtomicUsize,
+    /// The nice value, under the CFS policy.
+    nice: AtomicIsize,
+    /// The weighted virtual runtime, under the CFS policy.
+    vruntime: AtomicU64,
+    #[cfg(feature = "sched_edf")]
+    dl: DeadlineEntity,
+}
+
+impl<T> SchedTask<T> {
+    /// Creates a new [`SchedTask`] of the [`SchedClass::Normal`] class.
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            class: AtomicU8::new(SchedClass::Normal as u8),
+            queued: AtomicU8::new(QUEUED_NONE),
+            time_slice: AtomicUsize::new(MAX_TIME_SLICE),
+            nice: AtomicIsize::new(0),
+            vruntime: AtomicU64::new(0),
+            #[cfg(feature = "sched_edf")]
+            dl: DeadlineEntity::new(),
+        }
+    }
+
+    /// Returns a reference to the inner task struct.
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the scheduling class of the task.
+    pub fn sched_class(&self) -> SchedClass {
+        SchedClass::from_u8(self.class.load(Ordering::Acquire))
+    }
+
+    /// Sets the scheduling class of the task. It takes effect the next time
+    /// the task is put into a ready queue.
+    pub(crate) fn set_sched_class(&self, class: SchedClass) {
+        self.class.store(class as u8, Ordering::Release);
+    }
+
+    /// Whether the task, just woken up, should preempt the running task
+    /// `curr`.
+    ///
+    /// Under the FIFO policy, normal tasks never preempt each other, and
+    /// real-time tasks only preempt normal tasks. Tasks with deadlines always
+    /// preempt tasks without deadlines.
+    pub(crate) fn preempts(&self, curr: &Self) -> bool {
+        #[cfg(feature = "sched_edf")]
+        if self.dl.params().is_some() && curr.dl.params().is_none() {
+            return true;
+        }
+        policy().is_preemptive()
+            || (self.sched_class() == SchedClass::RealTime
+                && curr.sched_class() == SchedClass::Normal)
+    }
+
+    /// Returns the deadline parameters of the task, or [`None`] if it has no
+    /// deadline.
+    #[cfg(feature = "sched_edf")]
+    pub fn deadline_params(&self) -> Option<DeadlineParams> {
+        self.dl.params()
+    }
+
+    /// Returns the number of jobs that missed their deadlines.
+    #[cfg(feature = "sched_edf")]
+    pub fn deadline_misses(&self) -> u64 {
+        self.dl.misses()
+    }
+
+    /// Called when the task is switched out. Returns when its budget is
+    /// replenished if it has a deadline and its budget is exhausted, in
+    /// monotonic nanoseconds, in which case it must not be put into a ready
+    /// queue until then.
+    #[cfg(feature = "sched_edf")]
+    pub(crate) fn throttle_deadline(&self, now: u64) -> Option<u64> {
+        self.dl.throttle(now)
+    }
+
+    /// Sets (or clears if `params` is [`None`]) the deadline parameters of
+    /// the task, with admission control on the given CPU.
+    ///
+    /// It must not be called while the task is in a ready queue.
+    #[cfg(feature = "sched_edf")]
+    pub(crate) fn set_deadline_params(
+        &self,
+        params: Option<DeadlineParams>,
+        cpu_id: usize,
+    ) -> Result<(), DeadlineError> {
+        assert_eq!(self.queued.load(Ordering::Acquire), QUEUED_NONE);
+        self.dl.set_params(params, cpu_id)
+    }
+
+    /// Returns the CPU that the bandwidth of the task with deadline is
+    /// reserved on, where it should run.
+    pub(crate) fn reserved_cpu(&self) -> Option<usize> {
+        #[cfg(feature = "sched_edf")]
+        {
+            self.dl.reserved_cpu()
+        }
+        #[cfg(not(feature = "sched_edf"))]
+        {
+            None
+        }
+    }
+
+    /// Moves the bandwidth reservation of the task with deadline to the given
+    /// CPU, before it is moved there. Returns `false` if there is not enough
+    /// bandwidth left on that CPU.
+    ///
+    /// It always succeeds if the task has no deadline.
+    pub(crate) fn reserve_on(&self, cpu_id: usize) -> bool {
+        #[cfg(feature = "sched_edf")]
+        {
+            self.dl.migrate(cpu_id)
+        }
+        #[cfg(not(feature = "sched_edf"))]
+        {
+            let _ = cpu_id;
+            true
+        }
+    }
+
+    fn vruntime(&self) -> u64 {
+        self.vruntime.load(Ordering::Acquire)
+    }
+
+    fn weight(&self) -> u64 {
+        NICE_TO_WEIGHT[(self.nice.load(Ordering::Acquire) + 20) as usize]
+    }
+
+    /// Charges the task for a tick, and returns the new virtual runtime.
+    fn charge_tick(&self) -> u64 {
+        let delta = NICE_0_WEIGHT * NICE_0_WEIGHT / self.weight();
+        self.vruntime.fetch_add(delta, Ordering::AcqRel) + delta
+    }
+
+    fn key(self: &Arc<Self>, value: u64) -> (u64, usize) {
+        (value, Arc::as_ptr(self) as usize)
+    }
+}
+
+impl<T> Deref for SchedTask<T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// A scheduler with multiple [scheduling classes](SchedClass).
+///
+/// Normal tasks are scheduled by the [`SchedPolicy`] selected at boot time,
+/// which is fixed once the first scheduler is created.
+pub struct ClassScheduler<T> {
+    policy: SchedPolicy,
+    #[cfg(feature = "sched_edf")]
+    dl_queue: BTreeMap<(u64, usize), Arc<SchedTask<T>>>,
+    rt_queue: VecDeque<Arc<SchedTask<T>>>,
+    /// Normal tasks under the FIFO and round-robin policies.
+    ready_queue: VecDeque<Arc<SchedTask<T>>>,
+    /// Normal tasks under the CFS policy, ordered by their virtual runtime.
+    cfs_queue: BTreeMap<(u64, usize), Arc<SchedTask<T>>>,
+    min_vruntime: u64,
+}
+
+impl<T> ClassScheduler<T> {
+    /// Creates a new empty [`ClassScheduler`] with the selected policy.
+    pub fn new() -> Self {
+        Self {
+            policy: policy(),
+            #[cfg(feature = "sched_edf")]
+            dl_queue: BTreeMap::new(),
+            rt_queue: VecDeque::new(),
+            ready_queue: VecDeque::new(),
+            cfs_queue: BTreeMap::new(),
+            min_vruntime: 0,
+        }
+    }
+
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        policy().name()
+    }
+
+    /// Whether there are no ready tasks in any class.
+    pub fn is_empty(&self) -> bool {
+        !self.has_deadline_tasks()
+            && self.rt_queue.is_empty()
+            && self.ready_queue.is_empty()
+            && self.cfs_queue.is_empty()
+    }
+
+    fn has_deadline_tasks(&self) -> bool {
+        #[cfg(feature = "sched_edf")]
+        {
+            !self.dl_queue.is_empty()
+        }
+        #[cfg(not(feature = "sched_edf"))]
+        {
+            false
+        }
+    }
+
+    fn enqueue(&mut self, task: Arc<SchedTask<T>>, front: bool) {
+        #[cfg(feature = "sched_edf")]
+        if let Some(deadline) = task.dl.deadline() {
+            task.queued.store(QUEUED_DEADLINE, Ordering::Release);
+            self.dl_queue.insert(task.key(deadline), task);
+            return;
+        }
+        match task.sched_class() {
+            SchedClass::RealTime => {
+                task.queued.store(QUEUED_REALTIME, Ordering::Release);
+                if front {
+                    self.rt_queue.push_front(task);
+                } else {
+                    self.rt_queue.push_back(task);
+                }
+            }
+            SchedClass::Normal => {
+                task.queued.store(QUEUED_NORMAL, Ordering::Release);
+                if self.policy == SchedPolicy::Cfs {
+                    self.cfs_queue.insert(task.key(task.vruntime()), task);
+                } else if front {
+                    self.ready_queue.push_front(task);
+                } else {
+                    self.ready_queue.push_back(task);
+                }
+            }
+        }
+    }
+
+    /// Removes and returns the first ready task in the picking order that
+    /// satisfies `f`, e.g., allowed to run on another CPU. Other tasks keep
+    /// their positions in the ready queues.
+    pub fn pick_next_task_if(
+        &mut self,
+        f: impl Fn(&Arc<SchedTask<T>>) -> bool,
+    ) -> Option<Arc<SchedTask<T>>> {
+        #[cfg(feature = "sched_edf")]
+        let dl_tasks = self.dl_queue.values();
+        #[cfg(not(feature = "sched_edf"))]
+        let dl_tasks = core::iter::empty();
+        let task = dl_tasks
+            .chain(&self.rt_queue)
+            .chain(&self.ready_queue)
+            .chain(self.cfs_queue.values())
+            .find(|task| f(task))?
+            .clone();
+        let task = self.remove_task(&task)?;
+        #[cfg(feature = "sched_edf")]
+        if task.dl.deadline().is_some() {
+            task.dl.start(axhal::time::monotonic_time_nanos());
+        }
+        Some(task)
+    }
+
+    fn dequeued(task: Arc<SchedTask<T>>) -> Arc<SchedTask<T>> {
+        task.queued.store(QUEUED_NONE, Ordering::Release);
+        task
+    }
+}
+
+impl<T> BaseScheduler for ClassScheduler<T> {
+    type SchedItem = Arc<SchedTask<T>>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        #[cfg(feature = "sched_edf")]
+        task.dl.wakeup(axhal::time::monotonic_time_nanos());
+        // Do not let new or long-sleeping tasks monopolize the CPU.
+        task.vruntime.fetch_max(self.min_vruntime, Ordering::AcqRel);
+        self.enqueue(task, false);
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        let removed = match task.queued.load(Ordering::Acquire) {
+            #[cfg(feature = "sched_edf")]
+            QUEUED_DEADLINE => self.dl_queue.remove(&task.key(task.dl.deadline()?)),
+            QUEUED_REALTIME => self
+                .rt_queue
+                .iter()
+                .position(|t| Arc::ptr_eq(t, task))
+                .and_then(|idx| self.rt_queue.remove(idx)),
+            QUEUED_NORMAL if self.policy == SchedPolicy::Cfs => {
+                self.cfs_queue.remove(&task.key(task.vruntime()))
+            }
+            QUEUED_NORMAL => self
+                .ready_queue
+                .iter()
+                .position(|t| Arc::ptr_eq(t, task))
+                .and_then(|idx| self.ready_queue.remove(idx)),
+            _ => None,
+        };
+        removed.map(Self::dequeued)
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        #[cfg(feature = "sched_edf")]
+        if let Some((_, task)) = self.dl_queue.pop_first() {
+            task.dl.start(axhal::time::monotonic_time_nanos());
+            return Some(Self::dequeued(task));
+        }
+        if let Some(task) = self.rt_queue.pop_front() {
+            return Some(Self::dequeued(task));
+        }
+        let task = if self.policy == SchedPolicy::Cfs {
+            let ((vruntime, _), task) = self.cfs_queue.pop_first()?;
+            self.min_vruntime = self.min_vruntime.max(vruntime);
+            task
+        } else {
+            self.ready_queue.pop_front()?
+        };
+        Some(Self::dequeued(task))
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        #[cfg(feature = "sched_edf")]
+        prev.dl.stop(axhal::time::monotonic_time_nanos());
+        let front = if prev.sched_class() == SchedClass::RealTime {
+            // Preempted real-time tasks keep their positions.
+            preempt
+        } else if self.policy == SchedPolicy::RoundRobin {
+            if preempt && prev.time_slice.load(Ordering::Acquire) > 0 {
+                true
+            } else {
+                prev.time_slice.store(MAX_TIME_SLICE, Ordering::Release);
+                false
+            }
+        } else {
+            if self.policy == SchedPolicy::Cfs && !preempt {
+                // A yielding task is charged for a tick, so that other tasks
+                // with the same virtual runtime run first.
+                prev.charge_tick();
+            }
+            false
+        };
+        self.enqueue(prev, front);
+    }
+
+    fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        #[cfg(feature = "sched_edf")]
+        if let Some(deadline) = current.dl.deadline() {
+            let exhausted = current.dl.tick(axhal::time::monotonic_time_nanos());
+            return exhausted
+                || self
+                    .dl_queue
+                    .first_key_value()
+                    .is_some_and(|(key, _)| key.0 < deadline);
+        }
+        if self.has_deadline_tasks() {
+            return true;
+        }
+        if current.sched_class() == SchedClass::RealTime {
+            return false;
+        }
+        if !self.rt_queue.is_empty() {
+            return true;
+        }
+        match self.policy {
+            SchedPolicy::Fifo => false,
+            SchedPolicy::RoundRobin => {
+                let slice = current.time_slice.load(Ordering::Acquire).saturating_sub(1);
+                current.time_slice.store(slice, Ordering::Release);
+                slice == 0
+            }
+            SchedPolicy::Cfs => {
+                let vruntime = current.charge_tick();
+                self.cfs_queue
+                    .first_key_value()
+                    .is_some_and(|(key, _)| key.0 < vruntime)
+            }
+        }
+    }
+
+    fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool {
+        if self.policy == SchedPolicy::Cfs && (-20..20).contains(&prio) {
+            // The queue is ordered by the virtual runtime, which is not
+            // changed here, so it is fine to update a queued task.
+            task.nice.store(prio, Ordering::Release);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<T> Default for ClassScheduler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
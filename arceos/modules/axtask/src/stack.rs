@@ -0,0 +1,202 @@
+//! Kernel stacks of tasks, with stack overflow detection.
+//!
+//! If the `paging` feature is enabled, each stack is mapped in a dedicated
+//! kernel virtual region, with an unmapped guard page below it. A page fault
+//! on the guard page panics with the name of the overflowing task.
+//!
+//! Otherwise, a canary is put at the bottom of each stack, and it is checked
+//! every time the task is switched out.
+
+#[cfg(any(feature = "paging", test))]
+use alloc::vec::Vec;
+use core::{alloc::Layout, ptr::NonNull};
+
+use memory_addr::VirtAddr;
+#[cfg(any(feature = "paging", test))]
+use memory_addr::VirtAddrRange;
+
+/// The kernel stack of a task.
+///
+/// The memory is always allocated from the heap. With the `paging` feature,
+/// it is also mapped in the kernel stack region, and the task uses the stack
+/// through that mapping.
+pub(crate) struct TaskStack {
+    ptr: NonNull<u8>,
+    layout: Layout,
+    /// The lowest address of the stack that the task uses.
+    base: VirtAddr,
+}
+
+impl TaskStack {
+    pub fn alloc(size: usize) -> Self {
+        let layout = Layout::from_size_align(size, memory_addr::PAGE_SIZE_4K).unwrap();
+        let ptr = NonNull::new(unsafe { alloc::alloc::alloc(layout) }).unwrap();
+
+        #[cfg(feature = "paging")]
+        let base = guard::map(ptr, size);
+        #[cfg(not(feature = "paging"))]
+        let base = {
+            unsafe { ptr.cast::<u64>().write(STACK_CANARY) };
+            VirtAddr::from(ptr.as_ptr() as usize)
+        };
+        Self { ptr, layout, base }
+    }
+
+    pub const fn top(&self) -> VirtAddr {
+        VirtAddr::from_usize(self.base.as_usize() + self.layout.size())
+    }
+
+    /// Whether `vaddr` is in the guard page below the stack.
+    #[cfg(feature = "paging")]
+    pub fn guard_contains(&self, vaddr: VirtAddr) -> bool {
+        guard_contains(self.base, vaddr)
+    }
+
+    /// Whether the canary at the bottom of the stack has not been overwritten.
+    #[cfg(not(feature = "paging"))]
+    pub fn canary_intact(&self) -> bool {
+        unsafe { self.ptr.cast::<u64>().read() == STACK_CANARY }
+    }
+}
+
+impl Drop for TaskStack {
+    fn drop(&mut self) {
+        #[cfg(feature = "paging")]
+        guard::unmap(self.base, self.layout.size());
+        unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+impl core::fmt::Debug for TaskStack {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{:#x}..{:#x}", self.base, self.top())
+    }
+}
+
+#[cfg(not(feature = "paging"))]
+const STACK_CANARY: u64 = 0xdead_beef_cafe_f00d;
+
+/// The size of the unmapped guard page below each stack.
+#[cfg(any(feature = "paging", test))]
+pub(crate) const GUARD_SIZE: usize = memory_addr::PAGE_SIZE_4K;
+
+/// Whether `vaddr` is in the guard page below the stack starting at `base`.
+#[cfg(any(feature = "paging", test))]
+pub(crate) fn guard_contains(base: VirtAddr, vaddr: VirtAddr) -> bool {
+    base - GUARD_SIZE <= vaddr && vaddr < base
+}
+
+/// Virtual address allocator of the kernel stack region.
+///
+/// Each allocated range consists of a guard page and the stack above it.
+#[cfg(any(feature = "paging", test))]
+pub(crate) struct StackRegion {
+    end: VirtAddr,
+    /// The start of the part that has never been allocated.
+    next: VirtAddr,
+    /// Ranges that have been allocated and freed.
+    free: Vec<VirtAddrRange>,
+}
+
+#[cfg(any(feature = "paging", test))]
+impl StackRegion {
+    pub const fn new(start: VirtAddr, end: VirtAddr) -> Self {
+        Self {
+            end,
+            next: start,
+            free: Vec::new(),
+        }
+    }
+
+    pub fn alloc(&mut self, size: usize) -> VirtAddr {
+        if let Some(idx) = self.free.iter().position(|r| r.size() >= size) {
+            let range = self.free.swap_remove(idx);
+            if range.size() > size {
+                self.free
+                    .push(VirtAddrRange::new(range.start + size, range.end));
+            }
+            range.start
+        } else {
+            let start = self.next;
+            assert!(start + size <= self.end, "kernel stack region exhausted");
+            self.next = start + size;
+            start
+        }
+    }
+
+    /// Frees a range, which can be reused at once. The stack must have been
+    /// unmapped, with the TLB entries flushed on all CPUs.
+    pub fn dealloc(&mut self, start: VirtAddr, size: usize) {
+        self.free.push(VirtAddrRange::from_start_size(start, size));
+    }
+}
+
+#[cfg(feature = "paging")]
+mod guard {
+    use core::ptr::NonNull;
+
+    use axhal::mem::virt_to_phys;
+    use axhal::paging::MappingFlags;
+    use axhal::trap::{register_trap_handler, STACK_GUARD};
+    use kspin::SpinNoIrq;
+    use memory_addr::VirtAddr;
+
+    use super::{StackRegion, GUARD_SIZE};
+
+    const REGION_SIZE: usize = 0x4000_0000; // 1 GiB
+
+    /// The end of the kernel stack region, the last 1 GiB aligned part of the
+    /// kernel address space.
+    ///
+    /// It is covered by a single top-level page table entry on all supported
+    /// architectures, which is created by the first stack mapping and then
+    /// shared by user address spaces that copy the kernel mappings.
+    const REGION_END: usize =
+        (axconfig::KERNEL_ASPACE_BASE + axconfig::KERNEL_ASPACE_SIZE) & !(REGION_SIZE - 1);
+    const REGION_START: usize = REGION_END - REGION_SIZE;
+
+    static REGION: SpinNoIrq<StackRegion> = SpinNoIrq::new(StackRegion::new(
+        VirtAddr::from_usize(REGION_START),
+        VirtAddr::from_usize(REGION_END),
+    ));
+
+    /// Maps the stack memory at `ptr` in the kernel stack region, and returns
+    /// the mapped address.
+    pub fn map(ptr: NonNull<u8>, size: usize) -> VirtAddr {
+        let base = REGION.lock().alloc(size + GUARD_SIZE) + GUARD_SIZE;
+        let paddr = virt_to_phys(VirtAddr::from(ptr.as_ptr() as usize));
+        axmm::kernel_aspace()
+            .lock()
+            .map_linear(base, paddr, size, MappingFlags::READ | MappingFlags::WRITE)
+            .expect("failed to map the kernel stack");
+        base
+    }
+
+    pub fn unmap(base: VirtAddr, size: usize) {
+        // Unmapping from the kernel address space flushes the TLB entries on
+        // all CPUs before returning, so the range and the memory can be reused
+        // at once.
+        axmm::kernel_aspace()
+            .lock()
+            .unmap(base, size)
+            .expect("failed to unmap the kernel stack");
+        REGION.lock().dealloc(base - GUARD_SIZE, size + GUARD_SIZE);
+    }
+
+    #[register_trap_handler(STACK_GUARD)]
+    fn check_stack_guard(vaddr: VirtAddr) {
+        if !(REGION_START..REGION_END).contains(&vaddr.as_usize()) {
+            return;
+        }
+        if let Some(curr) = crate::current_may_uninit() {
+            if let Some(kstack) = curr.kstack().filter(|s| s.guard_contains(vaddr)) {
+                panic!(
+                    "kernel stack overflow in {}: fault_vaddr={:#x}, stack={:?}",
+                    curr.id_name(),
+                    vaddr,
+                    kstack
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Per-task runtime statistics.
+
+use alloc::string::String;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::task::{TaskId, TaskState};
+use crate::{AxTaskRef, SchedClass};
+
+/// Runtime statistics of a task.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TaskStats {
+    /// Total time the task has been running on CPUs.
+    pub cpu_time: Duration,
+    /// Total time the task has been ready but waiting for a CPU.
+    pub ready_time: Duration,
+    /// Total time the task has been blocked.
+    pub blocked_time: Duration,
+    /// Number of times the task gave up the CPU by itself, i.e., blocked,
+    /// yielded or exited.
+    pub voluntary_switches: u64,
+    /// Number of times the task was preempted.
+    pub involuntary_switches: u64,
+    /// Number of times the task was woken up after being blocked.
+    pub wakeups: u64,
+    /// Total time from being woken up to running on a CPU.
+    pub total_wakeup_latency: Duration,
+    /// Maximum time from being woken up to running on a CPU.
+    pub max_wakeup_latency: Duration,
+}
+
+/// A snapshot of a live task, returned by [`task_snapshot`].
+///
+/// [`task_snapshot`]: crate::task_snapshot
+#[derive(Debug, Clone)]
+pub struct TaskSnapshot {
+    /// The task ID.
+    pub id: TaskId,
+    /// The task name.
+    pub name: String,
+    /// The task state.
+    pub state: TaskState,
+    /// The CPU that the task is running on, or last ran on.
+    pub cpu_id: usize,
+    /// The effective priority.
+    pub priority: isize,
+    /// The scheduling class.
+    pub sched_class: SchedClass,
+    /// The runtime statistics.
+    pub stats: TaskStats,
+}
+
+/// Counters to accumulate [`TaskStats`] of a task.
+///
+/// The time since the last state change is charged to the old state on each
+/// change, all timestamps are in nanoseconds of the monotonic clock.
+pub(crate) struct TaskAccounting {
+    last_update: AtomicU64,
+    cpu_time: AtomicU64,
+    ready_time: AtomicU64,
+    blocked_time: AtomicU64,
+    voluntary_switches: AtomicU64,
+    involuntary_switches: AtomicU64,
+    wakeups: AtomicU64,
+    total_wakeup_latency: AtomicU64,
+    max_wakeup_latency: AtomicU64,
+    /// Whether the task becomes ready by a wakeup, not a preemption or yield.
+    woken: AtomicBool,
+}
+
+impl TaskAccounting {
+    pub fn new() -> Self {
+        Self {
+            last_update: AtomicU64::new(axhal::time::monotonic_time_nanos()),
+            cpu_time: AtomicU64::new(0),
+            ready_time: AtomicU64::new(0),
+            blocked_time: AtomicU64::new(0),
+            voluntary_switches: AtomicU64::new(0),
+            involuntary_switches: AtomicU64::new(0),
+            wakeups: AtomicU64::new(0),
+            total_wakeup_latency: AtomicU64::new(0),
+            max_wakeup_latency: AtomicU64::new(0),
+            woken: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the time since the last state change, and starts a new period.
+    fn elapsed(&self, now: u64) -> u64 {
+        now.saturating_sub(self.last_update.swap(now, Ordering::AcqRel))
+    }
+
+    /// Called when the task is switched out of a CPU.
+    pub fn switch_out(&self, now: u64, preempted: bool) {
+        self.cpu_time
+            .fetch_add(self.elapsed(now), Ordering::Relaxed);
+        if preempted {
+            self.involuntary_switches.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.voluntary_switches.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Called when the task is switched in to a CPU.
+    pub fn switch_in(&self, now: u64) {
+        let waited = self.elapsed(now);
+        self.ready_time.fetch_add(waited, Ordering::Relaxed);
+        if self.woken.swap(false, Ordering::AcqRel) {
+            self.total_wakeup_latency
+                .fetch_add(waited, Ordering::Relaxed);
+            self.max_wakeup_latency.fetch_max(waited, Ordering::Relaxed);
+        }
+    }
+
+    /// Called when the task is woken up, after it has been switched out.
+    pub fn wakeup(&self, now: u64) {
+        self.blocked_time
+            .fetch_add(self.elapsed(now), Ordering::Relaxed);
+        self.wakeups.fetch_add(1, Ordering::Relaxed);
+        self.woken.store(true, Ordering::Release);
+    }
+
+    /// Returns the statistics, including the time spent in the current state.
+    pub fn stats(&self, state: TaskState) -> TaskStats {
+        let now = axhal::time::monotonic_time_nanos();
+        let pending = now.saturating_sub(self.last_update.load(Ordering::Acquire));
+        let load = |counter: &AtomicU64, pending_state| {
+            let value = counter.load(Ordering::Relaxed);
+            if state == pending_state {
+                value + pending
+            } else {
+                value
+            }
+        };
+        TaskStats {
+            cpu_time: Duration::from_nanos(load(&self.cpu_time, TaskState::Running)),
+            ready_time: Duration::from_nanos(load(&self.ready_time, TaskState::Ready)),
+            blocked_time: Duration::from_nanos(load(&self.blocked_time, TaskState::Blocked)),
+            voluntary_switches: self.voluntary_switches.load(Ordering::Relaxed),
+            involuntary_switches: self.involuntary_switches.load(Ordering::Relaxed),
+            wakeups: self.wakeups.load(Ordering::Relaxed),
+            total_wakeup_latency: Duration::from_nanos(
+                self.total_wakeup_latency.load(Ordering::Relaxed),
+            ),
+            max_wakeup_latency: Duration::from_nanos(
+                self.max_wakeup_latency.load(Ordering::Relaxed),
+            ),
+        }
+    }
+}
+
+impl TaskSnapshot {
+    pub(crate) fn new(task: &AxTaskRef) -> Self {
+        let state = task.state();
+        Self {
+            id: task.id(),
+            name: task.name().into(),
+            state,
+            cpu_id: task.cpu_id(),
+            priority: task.priority(),
+            sched_class: task.sched_class(),
+            stats: task.accounting().stats(state),
+        }
+    }
+}
@@ -1,43 +1,77 @@
 use alloc::{boxed::Box, string::String, sync::Arc};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, Ordering};
-use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
-
-#[cfg(feature = "preempt")]
-use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{
+    fence, AtomicBool, AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering,
+};
+use core::{cell::UnsafeCell, fmt};
 
 #[cfg(feature = "tls")]
 use axhal::tls::TlsArea;
 
 use axhal::arch::TaskContext;
+use kspin::SpinNoIrq;
 use memory_addr::{align_up_4k, VirtAddr};
 
+use crate::stack::TaskStack;
+use crate::stats::{TaskAccounting, TaskStats};
 use crate::task_ext::AxTaskExt;
-use crate::{AxRunQueue, AxTask, AxTaskRef, WaitQueue};
+use crate::{AxCpuMask, AxTask, AxTaskRef, WaitQueue};
 
 /// A unique identifier for a thread.
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct TaskId(u64);
 
 /// The possible states of a task.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub(crate) enum TaskState {
+pub enum TaskState {
+    /// Running on a CPU.
     Running = 1,
+    /// Ready to run, waiting in a run queue.
     Ready = 2,
+    /// Blocked, waiting for an event or sleeping.
     Blocked = 3,
+    /// Exited, waiting to be recycled.
     Exited = 4,
 }
 
+const KILL_PENDING: u64 = 1 << 32;
+
+/// Priorities of a task.
+///
+/// A smaller value means a higher priority. The effective priority may be
+/// temporarily boosted above the base priority by priority inheritance.
+pub(crate) struct TaskPriority {
+    /// The priority set by [`set_priority`](crate::set_priority).
+    pub base: isize,
+    /// The priority that the scheduler actually uses.
+    pub effective: isize,
+    /// The number of held locks that support priority inheritance.
+    pub pi_locks: usize,
+}
+
 /// The inner task structure.
 pub struct TaskInner {
     id: TaskId,
     name: String,
     is_idle: bool,
     is_init: bool,
+    /// Whether the task is kernel-internal, and refused by
+    /// [`kill_task`](crate::kill_task).
+    unkillable: bool,
 
     entry: Option<*mut dyn FnOnce()>,
     state: AtomicU8,
+    priority: SpinNoIrq<TaskPriority>,
+
+    /// The CPU that the task is running on, or last ran on.
+    cpu_id: AtomicUsize,
+    /// The CPUs that the task is allowed to run on (raw bits of [`AxCpuMask`]).
+    cpumask: AtomicUsize,
+    /// Whether the task is running on a CPU, including the period of being
+    /// switched out.
+    #[cfg(feature = "smp")]
+    on_cpu: AtomicBool,
 
     in_wait_queue: AtomicBool,
     #[cfg(feature = "irq")]
@@ -48,9 +82,18 @@ pub struct TaskInner {
     #[cfg(feature = "preempt")]
     preempt_disable_count: AtomicUsize,
 
+    /// The pending kill request, with the exit code in the low 32 bits and
+    /// [`KILL_PENDING`] set, or 0 if the task has not been killed.
+    kill_request: AtomicU64,
+    /// Whether the task is in an interruptible wait, which is woken up by
+    /// [`kill_task`](crate::kill_task).
+    interruptible: AtomicBool,
+
     exit_code: AtomicI32,
     wait_for_exit: WaitQueue,
 
+    accounting: TaskAccounting,
+
     kstack: Option<TaskStack>,
     ctx: UnsafeCell<TaskContext>,
     task_ext: AxTaskExt,
@@ -69,6 +112,12 @@ impl TaskId {
     pub const fn as_u64(&self) -> u64 {
         self.0
     }
+
+    /// Convert a `u64` to the task ID, e.g., to look up a task by
+    /// [`get_task`](crate::get_task).
+    pub const fn from_u64(id: u64) -> Self {
+        Self(id)
+    }
 }
 
 impl From<u8> for TaskState {
@@ -126,6 +175,56 @@ impl TaskInner {
         alloc::format!("Task({}, {:?})", self.id.as_u64(), self.name)
     }
 
+    /// Gets the effective priority of the task.
+    ///
+    /// It may be higher (numerically smaller) than the base priority if the
+    /// task holds a lock that higher-priority tasks are waiting for.
+    pub fn priority(&self) -> isize {
+        self.priority.lock().effective
+    }
+
+    /// Gets the base priority of the task, as set by
+    /// [`set_priority`](crate::set_priority).
+    pub fn base_priority(&self) -> isize {
+        self.priority.lock().base
+    }
+
+    /// Gets the set of CPUs that the task is allowed to run on.
+    pub fn cpumask(&self) -> AxCpuMask {
+        AxCpuMask::from_raw_bits(self.cpumask.load(Ordering::Acquire))
+    }
+
+    /// Sets the set of CPUs that the task is allowed to run on, before the
+    /// task is spawned.
+    ///
+    /// Use [`set_affinity`](crate::set_affinity) to change the affinity of a
+    /// spawned task.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mask is empty.
+    pub fn set_cpumask(&mut self, cpumask: AxCpuMask) {
+        assert!(!cpumask.is_empty(), "empty CPU affinity mask");
+        *self.cpumask.get_mut() = cpumask.as_raw_bits();
+    }
+
+    /// Marks the task as kernel-internal before it is spawned, so that it can
+    /// not be killed by [`kill_task`](crate::kill_task).
+    pub fn set_unkillable(&mut self) {
+        self.unkillable = true;
+    }
+
+    /// Gets the runtime statistics of the task.
+    pub fn stats(&self) -> TaskStats {
+        self.accounting.stats(self.state())
+    }
+
+    /// Whether the task has been killed by [`kill_task`](crate::kill_task),
+    /// and is going to exit.
+    pub fn is_killed(&self) -> bool {
+        self.kill_request.load(Ordering::SeqCst) != 0
+    }
+
     /// Wait for the task to exit, and return the exit code.
     ///
     /// It will return immediately if the task has already exited (but not dropped).
@@ -169,8 +268,18 @@ impl TaskInner {
             name,
             is_idle: false,
             is_init: false,
+            unkillable: false,
             entry: None,
             state: AtomicU8::new(TaskState::Ready as u8),
+            priority: SpinNoIrq::new(TaskPriority {
+                base: 0,
+                effective: 0,
+                pi_locks: 0,
+            }),
+            cpu_id: AtomicUsize::new(0),
+            cpumask: AtomicUsize::new(AxCpuMask::full().as_raw_bits()),
+            #[cfg(feature = "smp")]
+            on_cpu: AtomicBool::new(false),
             in_wait_queue: AtomicBool::new(false),
             #[cfg(feature = "irq")]
             in_timer_list: AtomicBool::new(false),
@@ -178,8 +287,11 @@ impl TaskInner {
             need_resched: AtomicBool::new(false),
             #[cfg(feature = "preempt")]
             preempt_disable_count: AtomicUsize::new(0),
+            kill_request: AtomicU64::new(0),
+            interruptible: AtomicBool::new(false),
             exit_code: AtomicI32::new(0),
             wait_for_exit: WaitQueue::new(),
+            accounting: TaskAccounting::new(),
             kstack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
             task_ext: AxTaskExt::empty(),
@@ -206,7 +318,9 @@ impl TaskInner {
     }
 
     pub(crate) fn into_arc(self) -> AxTaskRef {
-        Arc::new(AxTask::new(self))
+        let task = Arc::new(AxTask::new(self));
+        crate::registry::register(&task);
+        task
     }
 
     #[inline]
@@ -219,6 +333,16 @@ impl TaskInner {
         self.state.store(state as u8, Ordering::Release)
     }
 
+    /// Transforms the task state from `from` to `to` atomically.
+    ///
+    /// Returns `false` if the current state is not `from`.
+    #[inline]
+    pub(crate) fn transition_state(&self, from: TaskState, to: TaskState) -> bool {
+        self.state
+            .compare_exchange(from as u8, to as u8, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
     #[inline]
     pub(crate) fn is_running(&self) -> bool {
         matches!(self.state(), TaskState::Running)
@@ -244,6 +368,73 @@ impl TaskInner {
         self.is_idle
     }
 
+    #[inline]
+    pub(crate) const fn is_unkillable(&self) -> bool {
+        self.unkillable
+    }
+
+    #[inline]
+    pub(crate) fn cpu_id(&self) -> usize {
+        self.cpu_id.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_cpu_id(&self, cpu_id: usize) {
+        self.cpu_id.store(cpu_id, Ordering::Release)
+    }
+
+    #[inline]
+    pub(crate) fn accounting(&self) -> &TaskAccounting {
+        &self.accounting
+    }
+
+    #[inline]
+    #[cfg(feature = "paging")]
+    pub(crate) fn kstack(&self) -> Option<&TaskStack> {
+        self.kstack.as_ref()
+    }
+
+    /// Panics if the canary at the bottom of the kernel stack has been
+    /// overwritten, i.e., the stack has overflowed.
+    #[cfg(not(feature = "paging"))]
+    pub(crate) fn check_stack_canary(&self) {
+        if let Some(kstack) = self.kstack.as_ref().filter(|s| !s.canary_intact()) {
+            panic!(
+                "kernel stack overflow in {}: stack={:?}",
+                self.id_name(),
+                kstack
+            );
+        }
+    }
+
+    #[inline]
+    pub(crate) fn priority_info(&self) -> &SpinNoIrq<TaskPriority> {
+        &self.priority
+    }
+
+    #[inline]
+    pub(crate) fn update_cpumask(&self, cpumask: AxCpuMask) {
+        self.cpumask.store(cpumask.as_raw_bits(), Ordering::Release)
+    }
+
+    /// Whether the task is allowed to run on the given CPU.
+    #[inline]
+    pub(crate) fn can_run_on(&self, cpu_id: usize) -> bool {
+        self.cpumask().get(cpu_id)
+    }
+
+    #[inline]
+    #[cfg(feature = "smp")]
+    pub(crate) fn on_cpu(&self) -> bool {
+        self.on_cpu.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    #[cfg(feature = "smp")]
+    pub(crate) fn set_on_cpu(&self, on_cpu: bool) {
+        self.on_cpu.store(on_cpu, Ordering::Release)
+    }
+
     #[inline]
     pub(crate) fn in_wait_queue(&self) -> bool {
         self.in_wait_queue.load(Ordering::Acquire)
@@ -266,6 +457,56 @@ impl TaskInner {
         self.in_timer_list.store(in_timer_list, Ordering::Release);
     }
 
+    /// Records a kill request with the given exit code.
+    ///
+    /// Returns `false` if the task has already been killed.
+    #[inline]
+    pub(crate) fn request_kill(&self, exit_code: i32) -> bool {
+        self.kill_request
+            .compare_exchange(
+                0,
+                KILL_PENDING | exit_code as u32 as u64,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_ok()
+    }
+
+    /// Returns the exit code of the pending kill request, if any.
+    #[inline]
+    pub(crate) fn kill_exit_code(&self) -> Option<i32> {
+        match self.kill_request.load(Ordering::SeqCst) {
+            0 => None,
+            req => Some(req as u32 as i32),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_interruptible(&self) -> bool {
+        self.interruptible.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub(crate) fn set_interruptible(&self, interruptible: bool) {
+        self.interruptible.store(interruptible, Ordering::SeqCst);
+    }
+
+    /// Keeps the current task running if it has been killed, after it is
+    /// marked blocked in an interruptible wait, so that the wakeup from
+    /// [`kill_task`](crate::kill_task) will not be missed.
+    ///
+    /// Returns `true` if the block is canceled. It must be called before the
+    /// task is put into a wait queue, so that no one can notify it then.
+    pub(crate) fn cancel_block_if_killed(&self) -> bool {
+        if self.is_interruptible() {
+            fence(Ordering::SeqCst);
+            if self.is_killed() {
+                return self.transition_state(TaskState::Blocked, TaskState::Running);
+            }
+        }
+        false
+    }
+
     #[inline]
     #[cfg(feature = "preempt")]
     pub(crate) fn set_preempt_pending(&self, pending: bool) {
@@ -297,16 +538,16 @@ impl TaskInner {
     fn current_check_preempt_pending() {
         let curr = crate::current();
         if curr.need_resched.load(Ordering::Acquire) && curr.can_preempt(0) {
-            let mut rq = crate::RUN_QUEUE.lock();
+            let rq = crate::current_run_queue();
             if curr.need_resched.load(Ordering::Acquire) {
                 rq.preempt_resched();
             }
         }
     }
 
-    pub(crate) fn notify_exit(&self, exit_code: i32, rq: &mut AxRunQueue) {
+    pub(crate) fn notify_exit(&self, exit_code: i32) {
         self.exit_code.store(exit_code, Ordering::Release);
-        self.wait_for_exit.notify_all_locked(false, rq);
+        self.wait_for_exit.notify_all(false);
     }
 
     #[inline]
@@ -346,31 +587,6 @@ impl Drop for TaskInner {
     }
 }
 
-struct TaskStack {
-    ptr: NonNull<u8>,
-    layout: Layout,
-}
-
-impl TaskStack {
-    pub fn alloc(size: usize) -> Self {
-        let layout = Layout::from_size_align(size, 16).unwrap();
-        Self {
-            ptr: NonNull::new(unsafe { alloc::alloc::alloc(layout) }).unwrap(),
-            layout,
-        }
-    }
-
-    pub const fn top(&self) -> VirtAddr {
-        unsafe { core::mem::transmute(self.ptr.as_ptr().add(self.layout.size())) }
-    }
-}
-
-impl Drop for TaskStack {
-    fn drop(&mut self) {
-        unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
-    }
-}
-
 use core::mem::ManuallyDrop;
 
 /// A wrapper of [`AxTaskRef`] as the current task.
@@ -407,6 +623,9 @@ impl CurrentTask {
 
     pub(crate) unsafe fn init_current(init_task: AxTaskRef) {
         assert!(init_task.is_init());
+        init_task.set_cpu_id(axhal::cpu::this_cpu_id());
+        #[cfg(feature = "smp")]
+        init_task.set_on_cpu(true);
         #[cfg(feature = "tls")]
         axhal::arch::write_thread_pointer(init_task.tls.tls_ptr() as usize);
         let ptr = Arc::into_raw(init_task);
@@ -429,8 +648,10 @@ impl Deref for CurrentTask {
 }
 
 extern "C" fn task_entry() -> ! {
-    // release the lock that was implicitly held across the reschedule
-    unsafe { crate::RUN_QUEUE.force_unlock() };
+    // finish the context switch from the previous task
+    #[cfg(feature = "smp")]
+    crate::run_queue::clear_prev_task_on_cpu();
+    // IRQs were implicitly disabled across the reschedule
     #[cfg(feature = "irq")]
     axhal::arch::enable_irqs();
     let task = crate::current();
@@ -1,7 +1,7 @@
 use core::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Mutex, Once};
+use std::sync::{Arc, Mutex, Once};
 
-use crate::{api as axtask, current, WaitQueue};
+use crate::{api as axtask, current, AxCpuMask, SchedClass, TaskInner, TaskState, WaitQueue};
 
 static INIT: Once = Once::new();
 static SERIAL: Mutex<()> = Mutex::new(());
@@ -128,3 +128,609 @@ fn test_task_join() {
         assert_eq!(tasks[i].join(), Some(i as _));
     }
 }
+
+#[test]
+fn test_task_affinity() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let cpu_id = axhal::cpu::this_cpu_id();
+    assert!(!axtask::set_current_affinity(AxCpuMask::new()));
+    assert!(axtask::set_current_affinity(AxCpuMask::one_shot(cpu_id)));
+    assert_eq!(current().cpumask(), AxCpuMask::one_shot(cpu_id));
+
+    let mut inner = TaskInner::new(
+        move || {
+            assert_eq!(current().cpumask(), AxCpuMask::one_shot(cpu_id));
+            axtask::yield_now();
+            assert_eq!(axhal::cpu::this_cpu_id(), cpu_id);
+        },
+        "pinned".into(),
+        0x1000,
+    );
+    inner.set_cpumask(AxCpuMask::one_shot(cpu_id));
+    let task = axtask::spawn_task(inner);
+    assert_eq!(task.join(), Some(0));
+
+    assert!(axtask::set_current_affinity(AxCpuMask::full()));
+    assert_eq!(current().cpumask(), AxCpuMask::full());
+}
+
+#[test]
+fn test_deadline_params() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let ms = core::time::Duration::from_millis;
+    let invalid = axtask::DeadlineParams {
+        runtime: ms(20),
+        deadline: ms(10),
+        period: ms(100),
+    };
+    let valid = axtask::DeadlineParams {
+        runtime: ms(10),
+        deadline: ms(50),
+        period: ms(100),
+    };
+    if cfg!(feature = "sched_edf") {
+        assert_eq!(
+            axtask::set_current_deadline(Some(invalid)),
+            Err(axtask::DeadlineError::InvalidParams)
+        );
+        assert_eq!(axtask::set_current_deadline(Some(valid)), Ok(()));
+        axtask::yield_now();
+        assert_eq!(axtask::set_current_deadline(None), Ok(()));
+    } else {
+        assert_eq!(
+            axtask::set_current_deadline(Some(valid)),
+            Err(axtask::DeadlineError::Unsupported)
+        );
+    }
+    assert_eq!(axtask::deadline_misses(current().as_task_ref()), 0);
+}
+
+#[test]
+fn test_sched_class() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static ORDER: Mutex<Vec<SchedClass>> = Mutex::new(Vec::new());
+    assert!(!axtask::set_sched_policy(axtask::sched_policy()));
+
+    let normal = axtask::spawn(|| ORDER.lock().unwrap().push(SchedClass::Normal));
+    let realtime = axtask::spawn(|| {
+        assert_eq!(current().sched_class(), SchedClass::RealTime);
+        ORDER.lock().unwrap().push(SchedClass::RealTime);
+    });
+    assert_eq!(realtime.sched_class(), SchedClass::Normal);
+    // The ready task is moved to the real-time queue, and runs first.
+    axtask::set_sched_class(&realtime, SchedClass::RealTime);
+    assert_eq!(realtime.join(), Some(0));
+    assert_eq!(normal.join(), Some(0));
+    assert_eq!(
+        *ORDER.lock().unwrap(),
+        [SchedClass::RealTime, SchedClass::Normal]
+    );
+}
+
+#[test]
+fn test_task_stats() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let task = axtask::spawn_raw(
+        || {
+            for _ in 0..3 {
+                axtask::yield_now();
+            }
+        },
+        "stats".into(),
+        0x1000,
+    );
+    let snapshot = axtask::task_snapshot();
+    assert!(snapshot
+        .windows(2)
+        .all(|w| w[0].id.as_u64() < w[1].id.as_u64()));
+    let info = snapshot.iter().find(|t| t.id == task.id()).unwrap();
+    assert_eq!(info.name, "stats");
+    assert_eq!(info.state, TaskState::Ready);
+    assert_eq!(info.stats.cpu_time, core::time::Duration::ZERO);
+
+    assert_eq!(task.join(), Some(0));
+    let stats = task.stats();
+    assert!(stats.voluntary_switches >= 1);
+    assert_eq!(stats.involuntary_switches, 0);
+    assert!(current().stats().wakeups >= 1);
+}
+
+#[test]
+fn test_task_registry() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let curr = current();
+    assert!(axtask::get_task(curr.id()).is_some_and(|t| Arc::ptr_eq(&t, curr.as_task_ref())));
+    let task = axtask::spawn_raw(|| {}, "registry".into(), 0x1000);
+    let found = axtask::find_tasks_by_name("registry");
+    assert_eq!(found.len(), 1);
+    assert!(Arc::ptr_eq(&found[0], &task));
+
+    let mut ids = Vec::new();
+    axtask::for_each_task(|t| ids.push(t.id()));
+    assert!(ids.contains(&curr.id()) && ids.contains(&task.id()));
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+
+    // The task is removed from the registry once the `gc` task recycles it,
+    // even if we still hold a reference.
+    let id = task.id();
+    assert_eq!(task.join(), Some(0));
+    for _ in 0..100 {
+        if axtask::get_task(id).is_none() {
+            break;
+        }
+        axtask::yield_now();
+    }
+    assert!(axtask::get_task(id).is_none());
+    assert!(axtask::find_tasks_by_name("registry").is_empty());
+    assert!(axtask::get_task(axtask::TaskId::from_u64(u64::MAX)).is_none());
+}
+
+#[test]
+fn test_task_kill() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static WQ: WaitQueue = WaitQueue::new();
+    static INTERRUPTED: AtomicUsize = AtomicUsize::new(0);
+
+    // A task blocked in an interruptible wait is woken up, and exits at the
+    // next cancellation point.
+    let waiter = axtask::spawn_raw(
+        || {
+            assert_eq!(
+                WQ.wait_until_interruptible(|| false),
+                Err(axtask::Interrupted)
+            );
+            INTERRUPTED.fetch_add(1, Ordering::Relaxed);
+            axtask::exit_if_killed();
+            unreachable!();
+        },
+        "kill_waiter".into(),
+        0x1000,
+    );
+    axtask::yield_now();
+    assert_eq!(waiter.state(), TaskState::Blocked);
+    assert!(axtask::kill_task(&waiter, 42));
+    assert!(waiter.is_killed());
+    assert!(!axtask::kill_task(&waiter, 43));
+    assert_eq!(waiter.join(), Some(42));
+    assert_eq!(INTERRUPTED.load(Ordering::Relaxed), 1);
+    assert!(!axtask::kill_task(&waiter, 42));
+
+    // A task blocked in other waits is not woken up, and exits at the next
+    // cancellation point after it is notified.
+    static WOKEN: AtomicUsize = AtomicUsize::new(0);
+    let plain = axtask::spawn_raw(
+        || {
+            WQ.wait();
+            WOKEN.fetch_add(1, Ordering::Relaxed);
+            axtask::yield_now();
+            unreachable!();
+        },
+        "kill_plain".into(),
+        0x1000,
+    );
+    axtask::yield_now();
+    assert_eq!(plain.state(), TaskState::Blocked);
+    assert!(axtask::kill_task(&plain, 1));
+    axtask::yield_now();
+    assert_eq!(plain.state(), TaskState::Blocked);
+    assert_eq!(WOKEN.load(Ordering::Relaxed), 0);
+    assert!(WQ.notify_one(false));
+    assert_eq!(plain.join(), Some(1));
+    assert_eq!(WOKEN.load(Ordering::Relaxed), 1);
+
+    // A busy task exits when it yields.
+    let worker = axtask::spawn_raw(
+        || loop {
+            axtask::yield_now();
+        },
+        "kill_worker".into(),
+        0x1000,
+    );
+    axtask::yield_now();
+    assert!(axtask::kill_task(&worker, -1));
+    assert_eq!(worker.join(), Some(-1));
+
+    // The state captured by a killed task is never dropped, so its result can
+    // only be read through a shared cell after joining (as `axstd` does).
+    let my_packet = Arc::new(Mutex::new(None));
+    let their_packet = my_packet.clone();
+    let worker = axtask::spawn_raw(
+        move || {
+            axtask::yield_now();
+            axtask::yield_now();
+            *their_packet.lock().unwrap() = Some(0);
+        },
+        "kill_packet".into(),
+        0x1000,
+    );
+    axtask::yield_now();
+    assert!(axtask::kill_task(&worker, -1));
+    assert_eq!(worker.join(), Some(-1));
+    assert_eq!(Arc::strong_count(&my_packet), 2);
+    assert_eq!(my_packet.lock().unwrap().take(), None);
+
+    assert!(!axtask::kill_task(current().as_task_ref(), 0)); // init task
+
+    // Kernel-internal tasks can not be killed.
+    let gc = axtask::task_snapshot()
+        .into_iter()
+        .find(|t| t.name == "gc")
+        .unwrap();
+    assert!(!axtask::kill_task(&axtask::get_task(gc.id).unwrap(), 0));
+}
+
+#[test]
+fn test_workqueue() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    use crate::workqueue;
+
+    static RUN: AtomicUsize = AtomicUsize::new(0);
+
+    let first = workqueue::queue_work(|| {
+        RUN.fetch_add(1, Ordering::Relaxed);
+    });
+    let second = workqueue::queue_work(|| {
+        RUN.fetch_add(10, Ordering::Relaxed);
+    });
+    // The worker has not run yet.
+    assert!(first.is_pending());
+    assert!(second.cancel());
+    assert!(!second.cancel());
+    assert!(!second.is_pending());
+
+    first.flush();
+    assert_eq!(RUN.load(Ordering::Relaxed), 1);
+    assert!(!first.cancel());
+
+    // Works run in order, and may block.
+    let order = Arc::new(Mutex::new(Vec::new()));
+    for i in 0..5 {
+        let order = order.clone();
+        workqueue::queue_work(move || {
+            axtask::yield_now();
+            order.lock().unwrap().push(i);
+        });
+    }
+    workqueue::flush_all();
+    assert_eq!(*order.lock().unwrap(), [0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn test_async_executor() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    use crate::future;
+
+    assert_eq!(future::block_on(async { 42 }), 42);
+
+    // Spawned futures run on the worker tasks and interleave at yields.
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let handles = (0..3)
+        .map(|i| {
+            let order = order.clone();
+            future::spawn(async move {
+                for j in 0..2 {
+                    order.lock().unwrap().push((j, i));
+                    future::yield_now().await;
+                }
+                i * 10
+            })
+        })
+        .collect::<Vec<_>>();
+    let sum = future::block_on(async {
+        let mut sum = 0;
+        for handle in handles {
+            sum += handle.await;
+        }
+        sum
+    });
+    assert_eq!(sum, 30);
+    // A yielding future is put to the back of the ready queue.
+    assert_eq!(
+        *order.lock().unwrap(),
+        [(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)]
+    );
+
+    let handle = future::spawn(async { "done" });
+    assert_eq!(handle.join(), "done");
+}
+
+#[test]
+#[cfg(feature = "irq")]
+fn test_ipi() {
+    let _lock = SERIAL.lock();
+
+    use axhal::irq::{self, IpiTarget};
+
+    const TEST_IPI: usize = irq::MAX_IPI_COUNT - 1;
+    static HANDLED: AtomicUsize = AtomicUsize::new(0);
+    static CALLED: AtomicUsize = AtomicUsize::new(0);
+
+    assert!(!irq::register_ipi_handler(irq::CALL_FUNCTION_IPI, || {}));
+    assert!(!irq::register_ipi_handler(irq::MAX_IPI_COUNT, || {}));
+    assert!(irq::register_ipi_handler(TEST_IPI, || {
+        HANDLED.fetch_add(1, Ordering::Relaxed);
+    }));
+    assert!(!irq::register_ipi_handler(TEST_IPI, || {}));
+
+    // IPIs to the current CPU are handled at once on the dummy platform, and
+    // there are no other CPUs.
+    let this_cpu = axhal::cpu::this_cpu_id();
+    irq::send_ipi(IpiTarget::Cpu(this_cpu), TEST_IPI);
+    assert_eq!(HANDLED.load(Ordering::Relaxed), 1);
+    irq::send_ipi(IpiTarget::Mask(1 << this_cpu), TEST_IPI);
+    assert_eq!(HANDLED.load(Ordering::Relaxed), 2);
+    if axconfig::SMP == 1 {
+        irq::send_ipi(IpiTarget::Others, TEST_IPI);
+        assert_eq!(HANDLED.load(Ordering::Relaxed), 2);
+    }
+
+    // IPIs sent as NMIs run the same handlers, but not the built-in one.
+    irq::send_nmi_ipi(IpiTarget::Cpu(this_cpu), TEST_IPI);
+    assert_eq!(HANDLED.load(Ordering::Relaxed), 3);
+
+    irq::smp_call_function(IpiTarget::Cpu(this_cpu), &|| {
+        CALLED.fetch_add(1, Ordering::Relaxed);
+    });
+    assert_eq!(CALLED.load(Ordering::Relaxed), 1);
+    if axconfig::SMP == 1 {
+        // Only the current CPU runs the function.
+        irq::smp_call_function(IpiTarget::Mask(usize::MAX), &|| {
+            CALLED.fetch_add(1, Ordering::Relaxed);
+        });
+        irq::smp_call_function(IpiTarget::Others, &|| unreachable!());
+        // No calls are left pending after returning.
+        irq::handle_call_function();
+        assert_eq!(CALLED.load(Ordering::Relaxed), 2);
+    }
+}
+
+#[test]
+#[cfg(feature = "irq")]
+fn test_timer_programming() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    use crate::timers::{programmed_deadline, Timer};
+    use axhal::time::TimeValue;
+
+    // The time is always zero on the dummy platform, so the deadline is earlier
+    // than any programmed one, or the hardware timer is not programmed yet.
+    let timer = Timer::new(|| {});
+    timer.arm_oneshot(TimeValue::from_nanos(1));
+    assert_eq!(programmed_deadline(), 1);
+    assert!(timer.cancel());
+
+    // Idle CPUs are woken up by the reschedule IPI, whose handler is
+    // registered by the scheduler.
+    #[cfg(feature = "smp")]
+    assert!(!axhal::irq::register_ipi_handler(
+        axhal::irq::RESCHED_IPI,
+        || {}
+    ));
+}
+
+#[test]
+fn test_pick_next_task_if() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    use crate::sched::{ClassScheduler, SchedTask};
+    use scheduler::BaseScheduler;
+
+    let mut scheduler = ClassScheduler::new();
+    for i in 0..4 {
+        scheduler.add_task(Arc::new(SchedTask::new(i)));
+    }
+    // The first matching task is taken, and the others are left in place.
+    let task = scheduler.pick_next_task_if(|task| *task.inner() % 2 == 1);
+    assert_eq!(task.map(|task| *task.inner()), Some(1));
+    assert!(scheduler
+        .pick_next_task_if(|task| *task.inner() > 3)
+        .is_none());
+
+    let mut rest: Vec<usize> = core::iter::from_fn(|| scheduler.pick_next_task())
+        .map(|task| *task.inner())
+        .collect();
+    if axtask::sched_policy() == crate::SchedPolicy::Cfs {
+        // Tasks with the same virtual runtime are not ordered.
+        rest.sort();
+    }
+    assert_eq!(rest, [0, 2, 3]);
+}
+
+#[cfg(feature = "sched_edf")]
+mod edf {
+    use core::time::Duration;
+    use std::sync::Arc;
+
+    use scheduler::BaseScheduler;
+
+    use super::SERIAL;
+    use crate::edf::DeadlineEntity;
+    use crate::sched::{ClassScheduler, SchedTask};
+    use crate::{DeadlineError, DeadlineParams, SchedClass};
+
+    const MS: u64 = 1_000_000;
+
+    fn params(runtime: u64, deadline: u64, period: u64) -> DeadlineParams {
+        DeadlineParams {
+            runtime: Duration::from_millis(runtime),
+            deadline: Duration::from_millis(deadline),
+            period: Duration::from_millis(period),
+        }
+    }
+
+    #[test]
+    fn test_edf_order() {
+        let _lock = SERIAL.lock();
+
+        let mut scheduler = ClassScheduler::new();
+        let realtime = Arc::new(SchedTask::new("realtime"));
+        realtime.set_sched_class(SchedClass::RealTime);
+        scheduler.add_task(realtime);
+        for (name, deadline) in [("dl30", 30), ("dl10", 10), ("dl20", 20)] {
+            let task = Arc::new(SchedTask::new(name));
+            task.set_deadline_params(Some(params(1, deadline, 100)), 0)
+                .unwrap();
+            scheduler.add_task(task);
+        }
+
+        // Tasks with earlier deadlines run first, before other classes.
+        let order: Vec<_> = core::iter::from_fn(|| scheduler.pick_next_task())
+            .map(|task| *task.inner())
+            .collect();
+        assert_eq!(order, ["dl10", "dl20", "dl30", "realtime"]);
+    }
+
+    #[test]
+    fn test_edf_admission() {
+        let _lock = SERIAL.lock();
+
+        let first = DeadlineEntity::new();
+        let second = DeadlineEntity::new();
+        assert_eq!(first.set_params(Some(params(60, 100, 100)), 0), Ok(()));
+        assert_eq!(
+            second.set_params(Some(params(60, 100, 100)), 0),
+            Err(DeadlineError::AdmissionFailed)
+        );
+        assert_eq!(second.reserved_cpu(), None);
+        // A smaller reservation of the same task is always admitted.
+        assert_eq!(first.set_params(Some(params(30, 100, 100)), 0), Ok(()));
+        assert_eq!(second.set_params(Some(params(60, 100, 100)), 0), Ok(()));
+        assert_eq!(second.reserved_cpu(), Some(0));
+        drop(first);
+        drop(second);
+
+        // The bandwidth is released after the tasks are dropped.
+        let third = DeadlineEntity::new();
+        assert_eq!(third.set_params(Some(params(90, 100, 100)), 0), Ok(()));
+        assert_eq!(third.set_params(None, 0), Ok(()));
+        assert_eq!(third.reserved_cpu(), None);
+    }
+
+    #[test]
+    fn test_edf_budget() {
+        let _lock = SERIAL.lock();
+
+        // The clock of the dummy platform stays at 0, so the times are given
+        // explicitly.
+        let dl = DeadlineEntity::new();
+        dl.set_params(Some(params(2, 10, 10)), 0).unwrap();
+        assert_eq!(dl.deadline(), Some(10 * MS));
+        dl.start(0);
+        assert!(!dl.tick(MS));
+        assert_eq!(dl.throttle(MS), None);
+        // The budget is exhausted, the task is throttled until the next
+        // period, and a new job is started then.
+        assert!(dl.tick(2 * MS));
+        assert_eq!(dl.throttle(3 * MS), Some(10 * MS));
+        dl.wakeup(10 * MS);
+        assert_eq!(dl.deadline(), Some(20 * MS));
+        dl.start(10 * MS);
+        dl.stop(11 * MS);
+
+        // The budget left can not be used after a long sleep, a new job is
+        // started instead.
+        dl.wakeup(19 * MS);
+        assert_eq!(dl.deadline(), Some(29 * MS));
+        assert_eq!(dl.misses(), 0);
+    }
+
+    #[test]
+    fn test_edf_deadline_miss() {
+        let _lock = SERIAL.lock();
+
+        let dl = DeadlineEntity::new();
+        dl.set_params(Some(params(2, 4, 10)), 0).unwrap();
+        // Picked after the deadline.
+        dl.start(5 * MS);
+        assert_eq!(dl.misses(), 1);
+        // A job is counted only once.
+        assert!(!dl.tick(6 * MS));
+        assert_eq!(dl.misses(), 1);
+        assert!(dl.tick(7 * MS));
+        assert_eq!(dl.throttle(7 * MS), Some(10 * MS));
+        dl.wakeup(10 * MS);
+        assert_eq!(dl.deadline(), Some(14 * MS));
+        // The deadline of the new job is missed again.
+        dl.start(10 * MS);
+        assert!(dl.tick(15 * MS));
+        assert_eq!(dl.misses(), 2);
+    }
+
+    #[test]
+    fn test_edf_throttle() {
+        let _lock = SERIAL.lock();
+
+        let mut scheduler = ClassScheduler::new();
+        let spinning = Arc::new(SchedTask::new("spinning"));
+        spinning
+            .set_deadline_params(Some(params(2, 10, 10)), 0)
+            .unwrap();
+        let normal = Arc::new(SchedTask::new("normal"));
+        scheduler.add_task(spinning.clone());
+        scheduler.add_task(normal);
+
+        let task = scheduler.pick_next_task().unwrap();
+        assert_eq!(*task.inner(), "spinning");
+        // It is switched out with the budget exhausted (the clock of the dummy
+        // platform stays at 0, so the time is given explicitly). It is
+        // throttled rather than put back, so the normal task runs.
+        assert_eq!(spinning.throttle_deadline(2 * MS), Some(10 * MS));
+        let task = scheduler.pick_next_task().unwrap();
+        assert_eq!(*task.inner(), "normal");
+        assert!(scheduler.pick_next_task().is_none());
+
+        // It preempts the normal task again after its budget is replenished.
+        scheduler.add_task(spinning);
+        assert!(scheduler.task_tick(&task));
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), "spinning");
+    }
+}
+
+#[test]
+fn test_stack_region() {
+    use crate::stack::{guard_contains, StackRegion, GUARD_SIZE};
+    use memory_addr::{va, PAGE_SIZE_4K};
+
+    const STACK_SIZE: usize = 4 * PAGE_SIZE_4K;
+    const RANGE_SIZE: usize = STACK_SIZE + GUARD_SIZE;
+    let mut region = StackRegion::new(va!(0x1000_0000), va!(0x1000_0000 + 4 * RANGE_SIZE));
+
+    // Each range starts with the guard page, faults there hit the guard.
+    let first = region.alloc(RANGE_SIZE);
+    let second = region.alloc(RANGE_SIZE);
+    assert_eq!(second, first + RANGE_SIZE);
+    let base = second + GUARD_SIZE;
+    assert!(guard_contains(base, second));
+    assert!(guard_contains(base, base - 1));
+    assert!(!guard_contains(base, base));
+    // The top of the stack below is not in the guard page.
+    assert!(!guard_contains(base, second - 1));
+
+    // Freed ranges are reused, and split for smaller stacks.
+    region.dealloc(first, RANGE_SIZE);
+    assert_eq!(region.alloc(RANGE_SIZE), first);
+    region.dealloc(second, RANGE_SIZE);
+    let small = PAGE_SIZE_4K + GUARD_SIZE;
+    assert_eq!(region.alloc(small), second);
+    assert_eq!(region.alloc(RANGE_SIZE - small), second + small);
+    // Larger stacks get new ranges.
+    assert_eq!(region.alloc(2 * RANGE_SIZE), second + RANGE_SIZE);
+}
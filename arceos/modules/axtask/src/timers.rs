@@ -1,37 +1,310 @@
+//! Timer events and the programming of the timer interrupt.
+//!
+//! Each CPU programs its hardware timer in one-shot mode, to the earlier of
+//! its next periodic scheduler tick and the earliest pending timer event. The
+//! periodic tick is stopped when the CPU becomes idle, so that an idle CPU only
+//! wakes up for timer events and other interrupts.
+//!
+//! General-purpose timer callbacks are provided by [`Timer`]. They run in the
+//! [timer softirq](axhal::irq::TIMER_SOFTIRQ), i.e., at the end of the timer
+//! interrupt with IRQs enabled, but not in any task context.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
 use alloc::sync::Arc;
-use axhal::time::wall_time;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use axhal::time::{monotonic_time_nanos, wall_time, NANOS_PER_SEC};
+use kernel_guard::IrqSave;
 use kspin::SpinNoIrq;
 use lazyinit::LazyInit;
 use timer_list::{TimeValue, TimerEvent, TimerList};
 
-use crate::{AxTaskRef, RUN_QUEUE};
+use crate::run_queue::unblock_task;
+use crate::workqueue::WorkInner;
+use crate::AxTaskRef;
+
+const PERIODIC_INTERVAL_NANOS: u64 = NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+
+/// The longest time that an idle CPU sleeps without timer events.
+///
+/// Other CPUs wake up an idle CPU by a reschedule IPI when they put tasks on
+/// its run queue, so this only bounds the time that a lost wakeup may cost.
+const MAX_IDLE_SLEEP_NANOS: u64 = NANOS_PER_SEC;
 
 // TODO: per-CPU
-static TIMER_LIST: LazyInit<SpinNoIrq<TimerList<TaskWakeupEvent>>> = LazyInit::new();
+static TIMER_LIST: LazyInit<SpinNoIrq<TimerList<AxTimerEvent>>> = LazyInit::new();
+
+/// The deadline of the next periodic tick of this CPU, in monotonic
+/// nanoseconds.
+#[percpu::def_percpu]
+static NEXT_TICK_DEADLINE: u64 = 0;
 
-struct TaskWakeupEvent(AxTaskRef);
+/// The deadline that the hardware timer of this CPU is programmed to, in
+/// monotonic nanoseconds, or [`u64::MAX`] if it has not been programmed.
+#[percpu::def_percpu]
+static PROGRAMMED_DEADLINE: u64 = u64::MAX;
+
+/// Whether the periodic tick of each CPU is stopped, i.e., the CPU is idle.
+static TICK_STOPPED: [AtomicBool; axconfig::SMP] =
+    [const { AtomicBool::new(false) }; axconfig::SMP];
+
+/// Expired [`Timer`]s, with the generation they were armed at, waiting for the
+/// timer softirq to run their callbacks.
+static EXPIRED_TIMERS: SpinNoIrq<VecDeque<(Arc<TimerInner>, u64)>> =
+    SpinNoIrq::new(VecDeque::new());
+
+enum AxTimerEvent {
+    /// Wakes up a task blocked with a timeout.
+    TaskWakeup(AxTaskRef),
+    /// Expiration of a [`Timer`] armed at the given generation.
+    Timer(Arc<TimerInner>, u64),
+    /// Queues a delayed work to its worker.
+    DelayedWork(Arc<WorkInner>),
+}
 
-impl TimerEvent for TaskWakeupEvent {
+impl TimerEvent for AxTimerEvent {
     fn callback(self, _now: TimeValue) {
-        let mut rq = RUN_QUEUE.lock();
-        self.0.set_in_timer_list(false);
-        rq.unblock_task(self.0, true);
+        match self {
+            Self::TaskWakeup(task) => {
+                task.set_in_timer_list(false);
+                unblock_task(task, true);
+            }
+            Self::Timer(timer, generation) => {
+                EXPIRED_TIMERS.lock().push_back((timer, generation));
+                axhal::irq::raise_softirq(axhal::irq::TIMER_SOFTIRQ);
+            }
+            Self::DelayedWork(work) => crate::workqueue::queue_expired(work),
+        }
     }
 }
 
-pub fn set_alarm_wakeup(deadline: TimeValue, task: AxTaskRef) {
-    let mut timers = TIMER_LIST.lock();
+/// Converts a wall time deadline of timer events to monotonic nanoseconds.
+fn deadline_nanos(deadline: TimeValue) -> u64 {
+    (deadline.as_nanos() as u64).saturating_sub(axhal::time::epochoffset_nanos())
+}
+
+/// Adds an event to the timer list, and advances the hardware timer of this
+/// CPU if the event is earlier than the programmed deadline.
+fn add_event(deadline: TimeValue, event: AxTimerEvent) {
+    let _guard = IrqSave::new();
+    TIMER_LIST.lock().set(deadline, event);
+    let deadline_ns = deadline_nanos(deadline);
+    if deadline_ns < unsafe { PROGRAMMED_DEADLINE.read_current_raw() } {
+        unsafe { PROGRAMMED_DEADLINE.write_current_raw(deadline_ns) };
+        axhal::time::set_oneshot_timer(deadline_ns);
+    }
+}
+
+/// Programs the hardware timer of this CPU to the next tick or timer event.
+///
+/// IRQs must be disabled.
+fn program_timer(now_ns: u64) {
+    let mut deadline_ns = if TICK_STOPPED[axhal::cpu::this_cpu_id()].load(Ordering::Acquire) {
+        now_ns + MAX_IDLE_SLEEP_NANOS
+    } else {
+        unsafe { NEXT_TICK_DEADLINE.read_current_raw() }
+    };
+    if let Some(next) = TIMER_LIST.lock().next_deadline() {
+        deadline_ns = deadline_ns.min(deadline_nanos(next));
+    }
+    unsafe { PROGRAMMED_DEADLINE.write_current_raw(deadline_ns) };
+    axhal::time::set_oneshot_timer(deadline_ns);
+}
+
+pub(crate) fn set_alarm_wakeup(deadline: TimeValue, task: AxTaskRef) {
     task.set_in_timer_list(true);
-    timers.set(deadline, TaskWakeupEvent(task));
+    add_event(deadline, AxTimerEvent::TaskWakeup(task));
 }
 
-pub fn cancel_alarm(task: &AxTaskRef) {
+pub(crate) fn cancel_alarm(task: &AxTaskRef) {
     let mut timers = TIMER_LIST.lock();
     task.set_in_timer_list(false);
-    timers.cancel(|t| Arc::ptr_eq(&t.0, task));
+    timers.cancel(|e| matches!(e, AxTimerEvent::TaskWakeup(t) if Arc::ptr_eq(t, task)));
+}
+
+pub(crate) fn set_delayed_work(deadline: TimeValue, work: Arc<WorkInner>) {
+    add_event(deadline, AxTimerEvent::DelayedWork(work));
+}
+
+pub(crate) fn cancel_delayed_work(work: &Arc<WorkInner>) {
+    TIMER_LIST
+        .lock()
+        .cancel(|e| matches!(e, AxTimerEvent::DelayedWork(w) if Arc::ptr_eq(w, work)));
+}
+
+struct TimerState {
+    /// Incremented every time the timer is armed or cancelled, so that
+    /// expirations of the previous arming are ignored.
+    generation: u64,
+    armed: bool,
+    deadline: TimeValue,
+    period: Option<Duration>,
+}
+
+struct TimerInner {
+    callback: Box<dyn Fn() + Send + Sync>,
+    state: SpinNoIrq<TimerState>,
+}
+
+impl TimerInner {
+    /// Runs the callback of an expiration, and re-arms a periodic timer.
+    fn expire(self: &Arc<Self>, generation: u64) {
+        {
+            let mut state = self.state.lock();
+            if !state.armed || state.generation != generation {
+                return;
+            }
+            if let Some(period) = state.period {
+                // Skips the periods that have been missed.
+                let now = wall_time();
+                let mut deadline = state.deadline + period;
+                while deadline <= now {
+                    deadline += period;
+                }
+                state.deadline = deadline;
+                add_event(deadline, AxTimerEvent::Timer(self.clone(), generation));
+            } else {
+                state.armed = false;
+            }
+        }
+        (self.callback)();
+    }
+}
+
+/// A timer that calls a function once at a deadline, or periodically.
+///
+/// The callback runs in softirq context on the CPU where the timer expires:
+/// IRQs are enabled but preemption is disabled, so it must not block, and
+/// should be short. Long work should be handed over to a task.
+///
+/// The timer is cancelled when it is dropped.
+pub struct Timer {
+    inner: Arc<TimerInner>,
+}
+
+impl Timer {
+    /// Creates a new disarmed timer with the given callback.
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        Self {
+            inner: Arc::new(TimerInner {
+                callback: Box::new(callback),
+                state: SpinNoIrq::new(TimerState {
+                    generation: 0,
+                    armed: false,
+                    deadline: TimeValue::ZERO,
+                    period: None,
+                }),
+            }),
+        }
+    }
+
+    /// Arms the timer to expire once at the given deadline (in wall time).
+    ///
+    /// If the timer is already armed, it is re-armed with the new deadline.
+    pub fn arm_oneshot(&self, deadline: TimeValue) {
+        self.arm(deadline, None);
+    }
+
+    /// Arms the timer to expire every `period`, starting one period from
+    /// now.
+    ///
+    /// If the timer is already armed, it is re-armed. Expirations that are
+    /// missed (e.g., because IRQs were disabled for long) are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn arm_periodic(&self, period: Duration) {
+        assert!(!period.is_zero(), "zero timer period");
+        self.arm(wall_time() + period, Some(period));
+    }
+
+    fn arm(&self, deadline: TimeValue, period: Option<Duration>) {
+        let mut state = self.inner.state.lock();
+        if state.armed {
+            self.remove_events();
+        }
+        state.generation += 1;
+        state.armed = true;
+        state.deadline = deadline;
+        state.period = period;
+        add_event(
+            deadline,
+            AxTimerEvent::Timer(self.inner.clone(), state.generation),
+        );
+    }
+
+    /// Cancels the timer.
+    ///
+    /// Returns `false` if the timer was not armed. The callback may still be
+    /// running on another CPU when it returns.
+    pub fn cancel(&self) -> bool {
+        let mut state = self.inner.state.lock();
+        if !state.armed {
+            return false;
+        }
+        self.remove_events();
+        state.generation += 1;
+        state.armed = false;
+        true
+    }
+
+    /// Whether the timer is armed, i.e., a periodic timer that has not been
+    /// cancelled, or a one-shot timer that has not expired.
+    pub fn is_armed(&self) -> bool {
+        self.inner.state.lock().armed
+    }
+
+    /// Removes the pending events of this timer from the timer list.
+    ///
+    /// An expired event may still be waiting for the softirq, which ignores
+    /// it after the generation is changed.
+    fn remove_events(&self) {
+        TIMER_LIST
+            .lock()
+            .cancel(|e| matches!(e, AxTimerEvent::Timer(t, _) if Arc::ptr_eq(t, &self.inner)));
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.cancel();
+    }
 }
 
-pub fn check_events() {
+impl core::fmt::Debug for Timer {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let state = self.inner.state.lock();
+        f.debug_struct("Timer")
+            .field("armed", &state.armed)
+            .field("deadline", &state.deadline)
+            .field("period", &state.period)
+            .finish()
+    }
+}
+
+/// The handler of the timer softirq, which runs the callbacks of expired
+/// timers.
+fn run_expired_timers() {
+    loop {
+        let expired = EXPIRED_TIMERS.lock().pop_front();
+        match expired {
+            Some((timer, generation)) => timer.expire(generation),
+            None => break,
+        }
+    }
+}
+
+/// Handles a timer interrupt: runs expired timer events and programs the next
+/// interrupt.
+///
+/// Returns whether a periodic tick has elapsed.
+pub(crate) fn on_timer_irq() -> bool {
     loop {
         let now = wall_time();
         let event = TIMER_LIST.lock().expire_one(now);
@@ -41,8 +314,51 @@ pub fn check_events() {
             break;
         }
     }
+
+    let now_ns = monotonic_time_nanos();
+    let next_tick = unsafe { NEXT_TICK_DEADLINE.read_current_raw() };
+    let ticked = now_ns >= next_tick;
+    if ticked {
+        unsafe { NEXT_TICK_DEADLINE.write_current_raw(now_ns + PERIODIC_INTERVAL_NANOS) };
+    }
+    program_timer(now_ns);
+    ticked && !TICK_STOPPED[axhal::cpu::this_cpu_id()].load(Ordering::Acquire)
+}
+
+/// Stops the periodic tick of this CPU, when it is going to be idle.
+pub(crate) fn stop_tick() {
+    let _guard = IrqSave::new();
+    TICK_STOPPED[axhal::cpu::this_cpu_id()].store(true, Ordering::Release);
+    program_timer(monotonic_time_nanos());
+}
+
+/// Restarts the periodic tick of this CPU, when it leaves idle.
+pub(crate) fn restart_tick() {
+    let _guard = IrqSave::new();
+    TICK_STOPPED[axhal::cpu::this_cpu_id()].store(false, Ordering::Release);
+    let now_ns = monotonic_time_nanos();
+    unsafe { NEXT_TICK_DEADLINE.write_current_raw(now_ns + PERIODIC_INTERVAL_NANOS) };
+    program_timer(now_ns);
 }
 
-pub fn init() {
+/// Whether the periodic tick of the given CPU is stopped, i.e., the CPU is
+/// idle and needs a reschedule IPI to notice tasks put on its run queue.
+#[cfg(feature = "smp")]
+pub(crate) fn tick_stopped(cpu_id: usize) -> bool {
+    TICK_STOPPED[cpu_id].load(Ordering::Acquire)
+}
+
+pub(crate) fn init() {
     TIMER_LIST.init_once(SpinNoIrq::new(TimerList::new()));
+    axhal::irq::register_softirq_handler(axhal::irq::TIMER_SOFTIRQ, run_expired_timers);
+    // The IPI wakes up the idle task waiting for IRQs, which then checks
+    // its run queue, so the handler itself has nothing to do.
+    #[cfg(feature = "smp")]
+    axhal::irq::register_ipi_handler(axhal::irq::RESCHED_IPI, || {});
+}
+
+/// The deadline that the hardware timer of this CPU is programmed to.
+#[cfg(test)]
+pub(crate) fn programmed_deadline() -> u64 {
+    unsafe { PROGRAMMED_DEADLINE.read_current_raw() }
 }
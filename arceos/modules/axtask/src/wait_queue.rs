@@ -1,11 +1,21 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
-use kspin::SpinRaw;
+use kspin::SpinNoIrq;
 
-use crate::{AxRunQueue, AxTaskRef, CurrentTask, RUN_QUEUE};
+use crate::run_queue::{current_run_queue, unblock_task};
+use crate::{AxTaskRef, CurrentTask};
+
+/// The error returned by interruptible waits, if the waiting task has been
+/// killed by [`kill_task`](crate::kill_task).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupted;
 
 /// A queue to store sleeping tasks.
 ///
+/// Tasks in the `_interruptible` waits are woken up if they are killed by
+/// [`kill_task`](crate::kill_task), and the waits return [`Interrupted`].
+/// Other waits are not affected.
+///
 /// # Examples
 ///
 /// ```
@@ -27,21 +37,21 @@ use crate::{AxRunQueue, AxTaskRef, CurrentTask, RUN_QUEUE};
 /// assert_eq!(VALUE.load(Ordering::Relaxed), 1);
 /// ```
 pub struct WaitQueue {
-    queue: SpinRaw<VecDeque<AxTaskRef>>, // we already disabled IRQs when lock the `RUN_QUEUE`
+    queue: SpinNoIrq<VecDeque<AxTaskRef>>,
 }
 
 impl WaitQueue {
     /// Creates an empty wait queue.
     pub const fn new() -> Self {
         Self {
-            queue: SpinRaw::new(VecDeque::new()),
+            queue: SpinNoIrq::new(VecDeque::new()),
         }
     }
 
     /// Creates an empty wait queue with space for at least `capacity` elements.
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            queue: SpinRaw::new(VecDeque::with_capacity(capacity)),
+            queue: SpinNoIrq::new(VecDeque::with_capacity(capacity)),
         }
     }
 
@@ -50,8 +60,6 @@ impl WaitQueue {
         // the event from another queue.
         if curr.in_wait_queue() {
             // wake up by timer (timeout).
-            // `RUN_QUEUE` is not locked here, so disable IRQs.
-            let _guard = kernel_guard::IrqSave::new();
             self.queue.lock().retain(|t| !curr.ptr_eq(t));
             curr.set_in_wait_queue(false);
         }
@@ -65,7 +73,7 @@ impl WaitQueue {
     /// Blocks the current task and put it into the wait queue, until other task
     /// notifies it.
     pub fn wait(&self) {
-        RUN_QUEUE.lock().block_current(|task| {
+        current_run_queue().block_current(|task| {
             task.set_in_wait_queue(true);
             self.queue.lock().push_back(task)
         });
@@ -82,18 +90,57 @@ impl WaitQueue {
         F: Fn() -> bool,
     {
         loop {
-            let mut rq = RUN_QUEUE.lock();
+            let rq = current_run_queue();
+            // Hold the queue lock between checking the condition and blocking,
+            // so that notifications in between will not be lost.
+            let mut wq = self.queue.lock();
             if condition() {
                 break;
             }
-            rq.block_current(|task| {
+            rq.block_current(move |task| {
                 task.set_in_wait_queue(true);
-                self.queue.lock().push_back(task);
+                wq.push_back(task);
             });
         }
         self.cancel_events(crate::current());
     }
 
+    /// Blocks the current task and put it into the wait queue, until the given
+    /// `condition` becomes true, or the current task is killed.
+    ///
+    /// Returns [`Err(Interrupted)`](Interrupted) if the task is killed before
+    /// the condition becomes true. The caller should then release its
+    /// resources and return, so that the task exits at the next cancellation
+    /// point (see [`exit_if_killed`](crate::exit_if_killed)).
+    pub fn wait_until_interruptible<F>(&self, condition: F) -> Result<(), Interrupted>
+    where
+        F: Fn() -> bool,
+    {
+        let curr = crate::current();
+        curr.set_interruptible(true);
+        let mut res = Ok(());
+        loop {
+            let rq = current_run_queue();
+            // Hold the queue lock between checking the condition and blocking,
+            // so that notifications in between will not be lost.
+            let mut wq = self.queue.lock();
+            if condition() {
+                break;
+            }
+            if curr.is_killed() {
+                res = Err(Interrupted);
+                break;
+            }
+            rq.block_current(move |task| {
+                task.set_in_wait_queue(true);
+                wq.push_back(task);
+            });
+        }
+        curr.set_interruptible(false);
+        self.cancel_events(curr);
+        res
+    }
+
     /// Blocks the current task and put it into the wait queue, until other tasks
     /// notify it, or the given duration has elapsed.
     #[cfg(feature = "irq")]
@@ -105,11 +152,11 @@ impl WaitQueue {
             curr.id_name(),
             deadline
         );
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
 
-        RUN_QUEUE.lock().block_current(|task| {
+        current_run_queue().block_current(|task| {
             task.set_in_wait_queue(true);
-            self.queue.lock().push_back(task)
+            self.queue.lock().push_back(task.clone());
+            crate::timers::set_alarm_wakeup(deadline, task);
         });
         let timeout = curr.in_wait_queue(); // still in the wait queue, must have timed out
         self.cancel_events(curr);
@@ -133,32 +180,106 @@ impl WaitQueue {
             curr.id_name(),
             deadline
         );
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
 
         let mut timeout = true;
         while axhal::time::wall_time() < deadline {
-            let mut rq = RUN_QUEUE.lock();
+            let rq = current_run_queue();
+            let mut wq = self.queue.lock();
             if condition() {
                 timeout = false;
                 break;
             }
-            rq.block_current(|task| {
+            rq.block_current(move |task| {
                 task.set_in_wait_queue(true);
-                self.queue.lock().push_back(task);
+                wq.push_back(task.clone());
+                if !task.in_timer_list() {
+                    crate::timers::set_alarm_wakeup(deadline, task);
+                }
             });
         }
         self.cancel_events(curr);
         timeout
     }
 
+    /// Blocks the current task and put it into the wait queue, until the given
+    /// `condition` becomes true, the given duration has elapsed, or the
+    /// current task is killed.
+    ///
+    /// Returns whether the wait timed out, or [`Err(Interrupted)`](Interrupted)
+    /// if the task is killed. See [`WaitQueue::wait_until_interruptible`].
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_until_interruptible<F>(
+        &self,
+        dur: core::time::Duration,
+        condition: F,
+    ) -> Result<bool, Interrupted>
+    where
+        F: Fn() -> bool,
+    {
+        let curr = crate::current();
+        let deadline = axhal::time::wall_time() + dur;
+        debug!(
+            "task wait_timeout: {}, deadline={:?}",
+            curr.id_name(),
+            deadline
+        );
+
+        curr.set_interruptible(true);
+        let mut res = Ok(true);
+        while axhal::time::wall_time() < deadline {
+            let rq = current_run_queue();
+            let mut wq = self.queue.lock();
+            if condition() {
+                res = Ok(false);
+                break;
+            }
+            if curr.is_killed() {
+                res = Err(Interrupted);
+                break;
+            }
+            rq.block_current(move |task| {
+                task.set_in_wait_queue(true);
+                wq.push_back(task.clone());
+                if !task.in_timer_list() {
+                    crate::timers::set_alarm_wakeup(deadline, task);
+                }
+            });
+        }
+        curr.set_interruptible(false);
+        self.cancel_events(curr);
+        res
+    }
+
     /// Wakes up one task in the wait queue, usually the first one.
     ///
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_one(&self, resched: bool) -> bool {
-        let mut rq = RUN_QUEUE.lock();
-        if !self.queue.lock().is_empty() {
-            self.notify_one_locked(resched, &mut rq)
+        let mut wq = self.queue.lock();
+        if let Some(task) = wq.pop_front() {
+            task.set_in_wait_queue(false);
+            drop(wq); // do not hold the lock while waking up the task.
+            unblock_task(task, resched);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Wakes up the task with the highest priority (i.e., the lowest
+    /// [`priority`](crate::TaskInner::priority) value) in the wait queue, or
+    /// the first one of them if there are several.
+    ///
+    /// If `resched` is true, the current task will be preempted when the
+    /// preemption is enabled.
+    pub fn notify_highest_priority(&self, resched: bool) -> bool {
+        let mut wq = self.queue.lock();
+        let index = (0..wq.len()).min_by_key(|&i| (wq[i].priority(), i));
+        if let Some(task) = index.and_then(|i| wq.remove(i)) {
+            task.set_in_wait_queue(false);
+            drop(wq);
+            unblock_task(task, resched);
+            true
         } else {
             false
         }
@@ -170,14 +291,14 @@ impl WaitQueue {
     /// preemption is enabled.
     pub fn notify_all(&self, resched: bool) {
         loop {
-            let mut rq = RUN_QUEUE.lock();
-            if let Some(task) = self.queue.lock().pop_front() {
+            let mut wq = self.queue.lock();
+            if let Some(task) = wq.pop_front() {
                 task.set_in_wait_queue(false);
-                rq.unblock_task(task, resched);
+                drop(wq);
+                unblock_task(task, resched);
             } else {
                 break;
             }
-            drop(rq); // we must unlock `RUN_QUEUE` after unlocking `self.queue`.
         }
     }
 
@@ -186,31 +307,15 @@ impl WaitQueue {
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_task(&mut self, resched: bool, task: &AxTaskRef) -> bool {
-        let mut rq = RUN_QUEUE.lock();
         let mut wq = self.queue.lock();
         if let Some(index) = wq.iter().position(|t| Arc::ptr_eq(t, task)) {
+            let task = wq.remove(index).unwrap();
             task.set_in_wait_queue(false);
-            rq.unblock_task(wq.remove(index).unwrap(), resched);
-            true
-        } else {
-            false
-        }
-    }
-
-    pub(crate) fn notify_one_locked(&self, resched: bool, rq: &mut AxRunQueue) -> bool {
-        if let Some(task) = self.queue.lock().pop_front() {
-            task.set_in_wait_queue(false);
-            rq.unblock_task(task, resched);
+            drop(wq);
+            unblock_task(task, resched);
             true
         } else {
             false
         }
     }
-
-    pub(crate) fn notify_all_locked(&self, resched: bool, rq: &mut AxRunQueue) {
-        while let Some(task) = self.queue.lock().pop_front() {
-            task.set_in_wait_queue(false);
-            rq.unblock_task(task, resched);
-        }
-    }
 }
@@ -0,0 +1,164 @@
+//! Lockup detection.
+//!
+//! Every CPU records when it last ran the scheduler and when it last took a
+//! timer interrupt. On each timer interrupt, a CPU checks for:
+//!
+//! - A soft lockup of itself: it has not scheduled for the threshold, e.g.,
+//!   a task spins with preemption disabled. The current task, the interrupted
+//!   registers and a backtrace are reported.
+//! - Hard lockups of other CPUs: they have not taken a timer interrupt for the
+//!   threshold, e.g., an IRQ handler never returns. The task running on the
+//!   stuck CPU is reported, and the CPU is sent an NMI to report its registers
+//!   and backtrace by itself. Without NMIs (on architectures other than x86),
+//!   it reports them only when it enables IRQs again.
+//!
+//! The threshold is `watchdog-thresh-ms` in the platform configuration, and
+//! the watchdog panics on lockups if `watchdog-panic` is set. Idle CPUs
+//! schedule and take timer interrupts at least once a second, so they are not
+//! taken as stuck.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use axhal::irq::{IpiTarget, BACKTRACE_IPI};
+use axhal::time::{monotonic_time_nanos, NANOS_PER_MILLIS};
+
+use crate::{AxTaskRef, TaskId};
+
+const THRESH_NANOS: u64 = axconfig::WATCHDOG_THRESH_MS as u64 * NANOS_PER_MILLIS;
+
+/// How long to wait for a stuck CPU to report its state before panicking.
+const DUMP_TIMEOUT_NANOS: u64 = 100 * NANOS_PER_MILLIS;
+
+struct CpuWatchdog {
+    /// When the CPU last ran the scheduler, in monotonic nanoseconds. 0 if
+    /// the CPU has not started.
+    last_sched: AtomicU64,
+    /// When the CPU last took a timer interrupt, in monotonic nanoseconds. 0
+    /// if the CPU has not started.
+    last_timer_irq: AtomicU64,
+    /// The ID of the task running on the CPU.
+    curr_task: AtomicU64,
+    /// Whether the current soft lockup has been reported.
+    soft_reported: AtomicBool,
+    /// Whether the current hard lockup has been reported.
+    hard_reported: AtomicBool,
+    /// Whether the CPU has reported its state on [`BACKTRACE_IPI`].
+    dumped: AtomicBool,
+}
+
+static WATCHDOGS: [CpuWatchdog; axconfig::SMP] = [const {
+    CpuWatchdog {
+        last_sched: AtomicU64::new(0),
+        last_timer_irq: AtomicU64::new(0),
+        curr_task: AtomicU64::new(0),
+        soft_reported: AtomicBool::new(false),
+        hard_reported: AtomicBool::new(false),
+        dumped: AtomicBool::new(false),
+    }
+}; axconfig::SMP];
+
+fn this_cpu() -> &'static CpuWatchdog {
+    &WATCHDOGS[axhal::cpu::this_cpu_id()]
+}
+
+/// Records that the scheduler has run on this CPU and picked `next`.
+pub(crate) fn touch_sched(next: &AxTaskRef) {
+    let wd = this_cpu();
+    wd.curr_task.store(next.id().as_u64(), Ordering::Relaxed);
+    wd.last_sched
+        .store(monotonic_time_nanos(), Ordering::Relaxed);
+    wd.soft_reported.store(false, Ordering::Relaxed);
+}
+
+/// Records a timer interrupt on this CPU, and checks for lockups.
+pub(crate) fn on_timer_irq() {
+    let now = monotonic_time_nanos();
+    let this_cpu_id = axhal::cpu::this_cpu_id();
+    let wd = &WATCHDOGS[this_cpu_id];
+    wd.last_timer_irq.store(now, Ordering::Relaxed);
+    wd.hard_reported.store(false, Ordering::Relaxed);
+
+    let last_sched = wd.last_sched.load(Ordering::Relaxed);
+    if last_sched != 0
+        && now.saturating_sub(last_sched) > THRESH_NANOS
+        && !wd.soft_reported.swap(true, Ordering::Relaxed)
+    {
+        report_soft_lockup(now - last_sched);
+    }
+
+    for (cpu_id, other) in WATCHDOGS.iter().enumerate() {
+        let last_irq = other.last_timer_irq.load(Ordering::Relaxed);
+        if cpu_id != this_cpu_id
+            && last_irq != 0
+            && now.saturating_sub(last_irq) > THRESH_NANOS
+            && !other.hard_reported.swap(true, Ordering::Relaxed)
+        {
+            report_hard_lockup(cpu_id, other, now - last_irq);
+        }
+    }
+}
+
+fn task_id_name(id: u64) -> alloc::string::String {
+    match crate::get_task(TaskId::from_u64(id)) {
+        Some(task) => task.id_name(),
+        None => alloc::format!("Task({})", id),
+    }
+}
+
+fn report_soft_lockup(stuck_nanos: u64) {
+    let cpu_id = axhal::cpu::this_cpu_id();
+    let curr = crate::current();
+    error!(
+        "watchdog: soft lockup on CPU {}, not scheduled for {} ms, current task: {}",
+        cpu_id,
+        stuck_nanos / NANOS_PER_MILLIS,
+        curr.id_name()
+    );
+    if let Some(tf) = axhal::trap::irq_trap_frame() {
+        error!("registers:\n{:#x?}", tf);
+        // Safety: the interrupted context is the current task, whose stack is
+        // alive.
+        let bt = unsafe { axhal::backtrace::Backtrace::from_trap_frame(&tf) };
+        error!("backtrace:\n{}", bt);
+    }
+    if axconfig::WATCHDOG_PANIC != 0 {
+        panic!("watchdog: soft lockup on CPU {}", cpu_id);
+    }
+}
+
+fn report_hard_lockup(cpu_id: usize, wd: &CpuWatchdog, stuck_nanos: u64) {
+    error!(
+        "watchdog: hard lockup on CPU {}, no timer interrupt for {} ms, current task: {}",
+        cpu_id,
+        stuck_nanos / NANOS_PER_MILLIS,
+        task_id_name(wd.curr_task.load(Ordering::Relaxed))
+    );
+    wd.dumped.store(false, Ordering::Relaxed);
+    axhal::irq::send_nmi_ipi(IpiTarget::Cpu(cpu_id), BACKTRACE_IPI);
+    if axconfig::WATCHDOG_PANIC != 0 {
+        // Lets the stuck CPU report its state before the system goes down.
+        let deadline = monotonic_time_nanos() + DUMP_TIMEOUT_NANOS;
+        while !wd.dumped.load(Ordering::Acquire) && monotonic_time_nanos() < deadline {
+            core::hint::spin_loop();
+        }
+        panic!("watchdog: hard lockup on CPU {}", cpu_id);
+    }
+}
+
+/// The handler of [`BACKTRACE_IPI`], which reports the registers and backtrace
+/// of the context interrupted on this CPU.
+fn dump_this_cpu() {
+    let cpu_id = axhal::cpu::this_cpu_id();
+    if let Some(tf) = axhal::trap::irq_trap_frame() {
+        error!("watchdog: registers of CPU {}:\n{:#x?}", cpu_id, tf);
+        // Safety: the interrupted context is still on this CPU, whose stack is
+        // alive.
+        let bt = unsafe { axhal::backtrace::Backtrace::from_trap_frame(&tf) };
+        error!("watchdog: backtrace of CPU {}:\n{}", cpu_id, bt);
+    }
+    WATCHDOGS[cpu_id].dumped.store(true, Ordering::Release);
+}
+
+pub(crate) fn init() {
+    axhal::irq::register_ipi_handler(BACKTRACE_IPI, dump_this_cpu);
+}
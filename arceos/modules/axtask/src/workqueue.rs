@@ -0,0 +1,251 @@
+//! Deferred work run by per-CPU kernel worker tasks.
+//!
+//! IRQ handlers (and any other code that must not block) can hand over work
+//! to the worker task of a CPU with [`queue_work`], and the work runs later in
+//! task context, where it may block. Works on the same CPU run one by one, in
+//! the order they are queued. With the `irq` feature, works can also be
+//! delayed with [`queue_delayed_work`].
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use kspin::SpinNoIrq;
+
+use crate::{AxCpuMask, TaskInner, WaitQueue};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+enum WorkState {
+    /// Waiting for the timer to queue it.
+    #[cfg(feature = "irq")]
+    Delayed(Job),
+    /// Queued to the worker, not started yet.
+    Pending(Job),
+    Running,
+    Done,
+    Cancelled,
+}
+
+pub(crate) struct WorkInner {
+    /// The CPU whose worker runs the work.
+    cpu_id: usize,
+    state: SpinNoIrq<WorkState>,
+    /// Tasks waiting for the work to finish or be cancelled.
+    finished: WaitQueue,
+}
+
+impl WorkInner {
+    fn is_finished(&self) -> bool {
+        matches!(*self.state.lock(), WorkState::Done | WorkState::Cancelled)
+    }
+
+    fn run(&self) {
+        let job = {
+            let mut state = self.state.lock();
+            match core::mem::replace(&mut *state, WorkState::Running) {
+                WorkState::Pending(job) => job,
+                other => {
+                    // Cancelled after being queued.
+                    *state = other;
+                    return;
+                }
+            }
+        };
+        job();
+        *self.state.lock() = WorkState::Done;
+        self.finished.notify_all(false);
+    }
+}
+
+/// The work queue of a CPU.
+struct Worker {
+    works: SpinNoIrq<VecDeque<Arc<WorkInner>>>,
+    wait: WaitQueue,
+    /// Whether the worker task has been spawned, i.e., the CPU is online.
+    started: AtomicBool,
+}
+
+static WORKERS: [Worker; axconfig::SMP] = [const {
+    Worker {
+        works: SpinNoIrq::new(VecDeque::new()),
+        wait: WaitQueue::new(),
+        started: AtomicBool::new(false),
+    }
+}; axconfig::SMP];
+
+/// Puts a pending work to the queue of its worker, and wakes the worker up.
+fn enqueue(work: Arc<WorkInner>) {
+    let worker = &WORKERS[work.cpu_id];
+    worker.works.lock().push_back(work);
+    worker.wait.notify_one(false);
+}
+
+/// Queues an expired delayed work, called by the timer.
+#[cfg(feature = "irq")]
+pub(crate) fn queue_expired(work: Arc<WorkInner>) {
+    {
+        let mut state = work.state.lock();
+        match core::mem::replace(&mut *state, WorkState::Cancelled) {
+            WorkState::Delayed(job) => *state = WorkState::Pending(job),
+            other => {
+                *state = other;
+                return;
+            }
+        }
+    }
+    enqueue(work);
+}
+
+fn worker_entry(cpu_id: usize) {
+    let worker = &WORKERS[cpu_id];
+    loop {
+        worker.wait.wait_until(|| !worker.works.lock().is_empty());
+        loop {
+            let work = worker.works.lock().pop_front();
+            match work {
+                Some(work) => work.run(),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Spawns the worker task of the current CPU.
+pub(crate) fn init_percpu() {
+    let cpu_id = axhal::cpu::this_cpu_id();
+    let mut task = TaskInner::new(
+        move || worker_entry(cpu_id),
+        alloc::format!("kworker/{}", cpu_id),
+        axconfig::TASK_STACK_SIZE,
+    );
+    task.set_cpumask(AxCpuMask::one_shot(cpu_id));
+    task.set_unkillable();
+    crate::spawn_task(task);
+    WORKERS[cpu_id].started.store(true, Ordering::Release);
+}
+
+/// A handle of a queued work, to cancel it or wait for it to finish.
+///
+/// Dropping the handle does not cancel the work.
+pub struct WorkHandle(Arc<WorkInner>);
+
+impl WorkHandle {
+    /// Cancels the work if it has not started.
+    ///
+    /// Returns `false` if the work has started, finished or been cancelled.
+    pub fn cancel(&self) -> bool {
+        let mut state = self.0.state.lock();
+        match &*state {
+            WorkState::Pending(_) => {}
+            #[cfg(feature = "irq")]
+            WorkState::Delayed(_) => {
+                crate::timers::cancel_delayed_work(&self.0);
+            }
+            _ => return false,
+        }
+        let job = core::mem::replace(&mut *state, WorkState::Cancelled);
+        drop(state);
+        drop(job);
+        self.0.finished.notify_all(false);
+        true
+    }
+
+    /// Waits for the work to finish or be cancelled.
+    ///
+    /// A delayed work is queued immediately. It must be called in task
+    /// context, and not by a work of the same CPU, which would wait for
+    /// itself.
+    pub fn flush(&self) {
+        #[cfg(feature = "irq")]
+        {
+            let mut state = self.0.state.lock();
+            if let WorkState::Delayed(_) = &*state {
+                crate::timers::cancel_delayed_work(&self.0);
+                if let WorkState::Delayed(job) =
+                    core::mem::replace(&mut *state, WorkState::Cancelled)
+                {
+                    *state = WorkState::Pending(job);
+                }
+                drop(state);
+                enqueue(self.0.clone());
+            }
+        }
+        self.0.finished.wait_until(|| self.0.is_finished());
+    }
+
+    /// Whether the work has not started yet.
+    pub fn is_pending(&self) -> bool {
+        match &*self.0.state.lock() {
+            WorkState::Pending(_) => true,
+            #[cfg(feature = "irq")]
+            WorkState::Delayed(_) => true,
+            _ => false,
+        }
+    }
+}
+
+fn new_work<F>(cpu_id: usize, state: fn(Job) -> WorkState, f: F) -> Arc<WorkInner>
+where
+    F: FnOnce() + Send + 'static,
+{
+    assert!(cpu_id < axconfig::SMP, "invalid CPU ID {}", cpu_id);
+    Arc::new(WorkInner {
+        cpu_id,
+        state: SpinNoIrq::new(state(Box::new(f))),
+        finished: WaitQueue::new(),
+    })
+}
+
+/// Queues a work to the worker of the current CPU.
+///
+/// It can be called in IRQ context.
+pub fn queue_work<F>(f: F) -> WorkHandle
+where
+    F: FnOnce() + Send + 'static,
+{
+    queue_work_on(axhal::cpu::this_cpu_id(), f)
+}
+
+/// Queues a work to the worker of the given CPU.
+///
+/// It can be called in IRQ context.
+pub fn queue_work_on<F>(cpu_id: usize, f: F) -> WorkHandle
+where
+    F: FnOnce() + Send + 'static,
+{
+    let work = new_work(cpu_id, WorkState::Pending, f);
+    enqueue(work.clone());
+    WorkHandle(work)
+}
+
+/// Queues a work to the worker of the current CPU after `delay`.
+///
+/// It can be called in IRQ context.
+#[cfg(feature = "irq")]
+pub fn queue_delayed_work<F>(delay: Duration, f: F) -> WorkHandle
+where
+    F: FnOnce() + Send + 'static,
+{
+    let work = new_work(axhal::cpu::this_cpu_id(), WorkState::Delayed, f);
+    crate::timers::set_delayed_work(axhal::time::wall_time() + delay, work.clone());
+    WorkHandle(work)
+}
+
+/// Waits for all works queued to all online CPUs so far to finish.
+///
+/// Delayed works that have not been queued are not waited for. It must be
+/// called in task context, and not by a work.
+pub fn flush_all() {
+    for cpu_id in 0..axconfig::SMP {
+        if !WORKERS[cpu_id].started.load(Ordering::Acquire) {
+            continue;
+        }
+        // Works of a CPU run in order, so the previous ones have finished
+        // when an empty work queued now finishes.
+        queue_work_on(cpu_id, || {}).flush();
+    }
+}
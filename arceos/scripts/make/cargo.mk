@@ -17,7 +17,14 @@ build_args := \
   $(build_args-$(MODE)) \
   $(verbose)
 
-RUSTFLAGS := -C link-arg=-T$(LD_SCRIPT) -C link-arg=-no-pie -C link-arg=-znostart-stop-gc
+RUSTFLAGS := -C link-arg=-T$(LD_SCRIPT) -C link-arg=-znostart-stop-gc
+ifneq ($(filter kaslr,$(FEATURES)),)
+  # Position-independent kernel, relocated by itself on boot
+  RUSTFLAGS += -C relocation-model=pie -C link-arg=-pie -C link-arg=--no-dynamic-linker \
+    -C link-arg=-znotext -C link-arg=--apply-dynamic-relocs
+else
+  RUSTFLAGS += -C link-arg=-no-pie
+endif
 RUSTDOCFLAGS := -Z unstable-options --enable-index-page -D rustdoc::broken_intra_doc_links
 
 ifeq ($(MAKECMDGOALS), doc_check_missing)
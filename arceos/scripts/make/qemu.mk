@@ -26,6 +26,10 @@ qemu_args-aarch64 := \
 
 qemu_args-y := -m 128M -smp $(SMP) $(qemu_args-$(ARCH))
 
+ifneq ($(SCHED),)
+  qemu_args-y += -append "sched=$(SCHED)"
+endif
+
 qemu_args-$(PFLASH) += \
   -drive if=pflash,file=$(CURDIR)/$(PFLASH_IMG),format=raw,unit=1
 
@@ -44,7 +44,7 @@ fn main() {
     // Load corresponding images for VM.
     info!("VM created success, loading images...");
     let image_fname = "/sbin/m_1_1_riscv64-qemu-virt.bin";
-    load_vm_image(image_fname.to_string(), KERNEL_BASE.into(), &aspace).expect("Failed to load VM images");
+    load_vm_image(image_fname.to_string(), KERNEL_BASE.into(), &mut aspace).expect("Failed to load VM images");
 
     // Register pflash device into vm.
     let mut vmdevs = VmDevGroup::new();
@@ -83,7 +83,7 @@ fn main() {
     }
 }
 
-fn load_vm_image(image_path: String, image_load_gpa: VirtAddr, aspace: &AddrSpace) -> AxResult {
+fn load_vm_image(image_path: String, image_load_gpa: VirtAddr, aspace: &mut AddrSpace) -> AxResult {
     use std::io::{BufReader, Read};
     let (image_file, image_size) = open_image_file(image_path.as_str())?;
 
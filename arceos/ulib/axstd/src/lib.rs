@@ -22,11 +22,14 @@
 //!     - `alloc-buddy`: Use the buddy system allocator.
 //!     - `paging`: Enable page table manipulation.
 //!     - `tls`: Enable thread-local storage.
+//!     - `swap`: Swap anonymous pages out to a block device when memory is low.
+//!     - `kaslr`: Move the kernel to a random address on boot (riscv64 and x86_64).
 //! - Task management
 //!     - `multitask`: Enable multi-threading support.
-//!     - `sched_fifo`: Use the FIFO cooperative scheduler.
-//!     - `sched_rr`: Use the Round-robin preemptive scheduler.
-//!     - `sched_cfs`: Use the Completely Fair Scheduler (CFS) preemptive scheduler.
+//!     - `sched_fifo`: Use the FIFO cooperative scheduler by default.
+//!     - `sched_rr`: Use the Round-robin preemptive scheduler by default.
+//!     - `sched_cfs`: Use the Completely Fair Scheduler (CFS) preemptive scheduler by default.
+//!     - `sched_edf`: Enable the Earliest Deadline First (EDF) real-time scheduling.
 //! - Upperlayer stacks
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
@@ -0,0 +1,184 @@
+//! A condition variable.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use super::wait_queue::WaitQueue;
+use super::{LockResult, MutexGuard};
+
+/// A type indicating whether a timed wait on a condition variable returned
+/// due to a time out or not.
+///
+/// It is returned by the [`Condvar::wait_timeout`] method.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct WaitTimeoutResult(bool);
+
+impl WaitTimeoutResult {
+    /// Returns `true` if the wait was known to have timed out.
+    pub fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+/// A condition variable, similar to
+/// [`std::sync::Condvar`](https://doc.rust-lang.org/std/sync/struct.Condvar.html).
+///
+/// Condition variables represent the ability to block a task such that it
+/// consumes no CPU time while waiting for an event to occur. It is used with
+/// a [`Mutex`](super::Mutex) to protect the shared state.
+///
+/// If the `multitask` feature is not enabled, there is no other task to
+/// notify the current one, so the waiting methods return immediately (which
+/// are treated as spurious wakeups).
+pub struct Condvar {
+    wq: WaitQueue,
+    /// Incremented on every notification, so that a waiter can tell whether
+    /// it has been notified after it released the mutex.
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    /// Creates a new condition variable which is ready to be waited on and
+    /// notified.
+    pub const fn new() -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            seq: AtomicU32::new(0),
+        }
+    }
+
+    /// Blocks the current task until this condition variable receives a
+    /// notification.
+    ///
+    /// This function will atomically unlock the mutex specified (represented
+    /// by `guard`) and block the current task. When this function returns,
+    /// the lock will have been re-acquired.
+    ///
+    /// Note that this function is susceptible to spurious wakeups, so it is
+    /// usually called in a loop, or use [`Condvar::wait_while`] instead.
+    ///
+    /// It never returns an error, as the mutex is never poisoned.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> LockResult<MutexGuard<'a, T>> {
+        #[cfg(feature = "multitask")]
+        {
+            let seq = self.seq.load(Ordering::Acquire);
+            let mutex = guard.mutex();
+            drop(guard);
+            self.wq
+                .wait_until(|| self.seq.load(Ordering::Acquire) != seq);
+            Ok(mutex.lock())
+        }
+        #[cfg(not(feature = "multitask"))]
+        {
+            core::hint::spin_loop();
+            Ok(guard)
+        }
+    }
+
+    /// Blocks the current task until the provided `condition` becomes false.
+    ///
+    /// `condition` is checked immediately; if not met (returns `true`), this
+    /// will [`wait`](Self::wait) for the next notification then check again.
+    /// This repeats until `condition` returns `false`, in which case this
+    /// function returns.
+    pub fn wait_while<'a, T: ?Sized, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: F,
+    ) -> LockResult<MutexGuard<'a, T>>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut *guard) {
+            guard = self.wait(guard)?;
+        }
+        Ok(guard)
+    }
+
+    /// Waits on this condition variable for a notification, timing out after
+    /// the specified duration.
+    ///
+    /// The semantics of this function are equivalent to [`wait`](Self::wait)
+    /// except that the task will be blocked for roughly no longer than `dur`.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout<'a, T: ?Sized>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        dur: Duration,
+    ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)> {
+        #[cfg(feature = "multitask")]
+        {
+            let seq = self.seq.load(Ordering::Acquire);
+            let mutex = guard.mutex();
+            drop(guard);
+            let timed_out = self
+                .wq
+                .wait_timeout_until(dur, || self.seq.load(Ordering::Acquire) != seq);
+            Ok((mutex.lock(), WaitTimeoutResult(timed_out)))
+        }
+        #[cfg(not(feature = "multitask"))]
+        {
+            let _ = dur;
+            core::hint::spin_loop();
+            Ok((guard, WaitTimeoutResult(false)))
+        }
+    }
+
+    /// Waits on this condition variable for a notification, timing out after
+    /// the specified duration, until the provided `condition` becomes false.
+    ///
+    /// The returned [`WaitTimeoutResult`] value indicates if the timeout is
+    /// known to have elapsed without the condition being met.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_while<'a, T: ?Sized, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        dur: Duration,
+        mut condition: F,
+    ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let deadline = arceos_api::time::ax_wall_time() + dur;
+        loop {
+            if !condition(&mut *guard) {
+                return Ok((guard, WaitTimeoutResult(false)));
+            }
+            let now = arceos_api::time::ax_wall_time();
+            if now >= deadline {
+                return Ok((guard, WaitTimeoutResult(true)));
+            }
+            guard = self.wait_timeout(guard, deadline - now)?.0;
+        }
+    }
+
+    /// Wakes up one blocked task on this condvar.
+    ///
+    /// If there is a blocked task on this condition variable, then it will be
+    /// woken up from its call to [`wait`](Self::wait) or
+    /// [`wait_timeout`](Self::wait_timeout). Calls to `notify_one` are not
+    /// buffered in any way.
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+
+    /// Wakes up all blocked tasks on this condvar.
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.wq.notify_all(true);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for Condvar {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("Condvar").finish_non_exhaustive()
+    }
+}
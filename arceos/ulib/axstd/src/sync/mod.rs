@@ -7,8 +7,22 @@ pub use core::sync::atomic;
 #[doc(no_inline)]
 pub use alloc::sync::{Arc, Weak};
 
+mod barrier;
+mod condvar;
 #[cfg(feature = "multitask")]
 mod mutex;
+mod once;
+mod poison;
+mod rwlock;
+mod semaphore;
+mod wait_queue;
+
+pub use self::barrier::{Barrier, BarrierWaitResult};
+pub use self::condvar::{Condvar, WaitTimeoutResult};
+pub use self::once::{Once, OnceLock};
+pub use self::poison::{LockResult, PoisonError, TryLockError, TryLockResult};
+pub use self::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use self::semaphore::{Semaphore, SemaphoreGuard};
 
 #[cfg(feature = "multitask")]
 #[doc(cfg(feature = "multitask"))]
@@ -0,0 +1,206 @@
+//! One-time initialization primitives.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use super::wait_queue::WaitQueue;
+
+const INCOMPLETE: u8 = 0;
+const RUNNING: u8 = 1;
+const COMPLETE: u8 = 2;
+
+/// A synchronization primitive which can be used to run a one-time global
+/// initialization, similar to
+/// [`std::sync::Once`](https://doc.rust-lang.org/std/sync/struct.Once.html).
+///
+/// Tasks that call [`call_once`](Self::call_once) while the initialization is
+/// running on another task are blocked until it completes. If the `multitask`
+/// feature is not enabled, they spin instead.
+pub struct Once {
+    wq: WaitQueue,
+    state: AtomicU8,
+}
+
+impl Once {
+    /// Creates a new `Once` value.
+    pub const fn new() -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            state: AtomicU8::new(INCOMPLETE),
+        }
+    }
+
+    /// Performs an initialization routine once and only once. The given
+    /// closure will be executed if this is the first time `call_once` has
+    /// been called, and otherwise the routine will *not* be invoked.
+    ///
+    /// This method will block the current task if another initialization
+    /// routine is currently running.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+        match self
+            .state
+            .compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                f();
+                self.state.store(COMPLETE, Ordering::Release);
+                self.wq.notify_all(true);
+            }
+            Err(_) => self.wq.wait_until(|| self.is_completed()),
+        }
+    }
+
+    /// Returns `true` if some [`call_once()`](Self::call_once) call has
+    /// completed successfully.
+    #[inline]
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Once {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Once").finish_non_exhaustive()
+    }
+}
+
+/// A synchronization primitive which can be written to only once, similar to
+/// [`std::sync::OnceLock`](https://doc.rust-lang.org/std/sync/struct.OnceLock.html).
+pub struct OnceLock<T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// Same unsafe impls as `std::sync::OnceLock`
+unsafe impl<T: Sync + Send> Sync for OnceLock<T> {}
+unsafe impl<T: Send> Send for OnceLock<T> {}
+
+impl<T> OnceLock<T> {
+    /// Creates a new empty cell.
+    pub const fn new() -> Self {
+        Self {
+            once: Once::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Gets the reference to the underlying value.
+    ///
+    /// Returns [`None`] if the cell is empty, or being initialized.
+    pub fn get(&self) -> Option<&T> {
+        if self.once.is_completed() {
+            // SAFETY: the value is initialized and never changed afterwards.
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Gets the mutable reference to the underlying value.
+    ///
+    /// Returns [`None`] if the cell is empty.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.once.is_completed() {
+            Some(unsafe { self.value.get_mut().assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    /// Sets the contents of this cell to `value`.
+    ///
+    /// May block if another task is currently attempting to initialize the
+    /// cell. Returns `Err(value)` if the cell was already initialized.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    /// Gets the contents of the cell, initializing it with `f` if the cell
+    /// was empty.
+    ///
+    /// Many tasks may call `get_or_init` concurrently with different
+    /// initializing functions, but it is guaranteed that only one function
+    /// will be executed.
+    pub fn get_or_init<F>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> T,
+    {
+        self.once.call_once(|| {
+            // SAFETY: we are the only one to access the value here.
+            unsafe { (*self.value.get()).write(f()) };
+        });
+        // SAFETY: the value is initialized above.
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+
+    /// Consumes the cell, returning the wrapped value.
+    ///
+    /// Returns [`None`] if the cell was empty.
+    pub fn into_inner(mut self) -> Option<T> {
+        self.take()
+    }
+
+    /// Takes the value out of this cell, moving it back to an uninitialized
+    /// state.
+    ///
+    /// Has no effect and returns [`None`] if the cell hasn't been initialized.
+    pub fn take(&mut self) -> Option<T> {
+        if self.once.is_completed() {
+            self.once = Once::new();
+            // SAFETY: the value was initialized, and the state is reset above
+            // so it will not be read or dropped again.
+            Some(unsafe { self.value.get_mut().assume_init_read() })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Default for OnceLock<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.get() {
+            Some(v) => f.debug_tuple("OnceLock").field(v).finish(),
+            None => f.write_str("OnceLock(<uninit>)"),
+        }
+    }
+}
+
+impl<T> From<T> for OnceLock<T> {
+    fn from(value: T) -> Self {
+        let cell = Self::new();
+        let _ = cell.set(value);
+        cell
+    }
+}
+
+impl<T> Drop for OnceLock<T> {
+    fn drop(&mut self) {
+        if self.once.is_completed() {
+            // SAFETY: the value is initialized and will never be accessed
+            // again.
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
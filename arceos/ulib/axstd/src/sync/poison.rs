@@ -0,0 +1,93 @@
+//! Lock results compatible with `std`.
+//!
+//! Unlike `std`, a task that panics does not unwind, so the locks are never
+//! poisoned. The types are only provided for `std`-compatible signatures, and
+//! the results of blocking lock methods are always [`Ok`].
+
+use core::fmt;
+
+/// A type of error which can be returned whenever a lock is acquired, similar
+/// to [`std::sync::PoisonError`](https://doc.rust-lang.org/std/sync/struct.PoisonError.html).
+///
+/// It is never returned in ArceOS, as locks are never poisoned.
+pub struct PoisonError<T> {
+    guard: T,
+}
+
+impl<T> PoisonError<T> {
+    /// Creates a [`PoisonError`] wrapping the guard.
+    pub fn new(guard: T) -> Self {
+        Self { guard }
+    }
+
+    /// Consumes this error, returning the underlying guard.
+    pub fn into_inner(self) -> T {
+        self.guard
+    }
+
+    /// Reaches into this error, returning a reference to the underlying guard.
+    pub fn get_ref(&self) -> &T {
+        &self.guard
+    }
+
+    /// Reaches into this error, returning a mutable reference to the
+    /// underlying guard.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> fmt::Debug for PoisonError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PoisonError").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for PoisonError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        "poisoned lock: another task failed inside".fmt(f)
+    }
+}
+
+/// An enumeration of possible errors of the `try_*` lock methods, similar to
+/// [`std::sync::TryLockError`](https://doc.rust-lang.org/std/sync/enum.TryLockError.html).
+pub enum TryLockError<T> {
+    /// The lock could not be acquired because it is poisoned, which never
+    /// happens in ArceOS.
+    Poisoned(PoisonError<T>),
+    /// The lock could not be acquired at this time because the operation
+    /// would otherwise block.
+    WouldBlock,
+}
+
+impl<T> From<PoisonError<T>> for TryLockError<T> {
+    fn from(err: PoisonError<T>) -> Self {
+        Self::Poisoned(err)
+    }
+}
+
+impl<T> fmt::Debug for TryLockError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Poisoned(..) => "Poisoned(..)".fmt(f),
+            Self::WouldBlock => "WouldBlock".fmt(f),
+        }
+    }
+}
+
+impl<T> fmt::Display for TryLockError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Poisoned(..) => "poisoned lock: another task failed inside",
+            Self::WouldBlock => "try_lock failed because the operation would block",
+        }
+        .fmt(f)
+    }
+}
+
+/// The result of a blocking lock method, which is always [`Ok`] in ArceOS.
+pub type LockResult<Guard> = Result<Guard, PoisonError<Guard>>;
+
+/// The result of a non-blocking lock method, which is [`Err`] only if the
+/// lock is held by others.
+pub type TryLockResult<Guard> = Result<Guard, TryLockError<Guard>>;
@@ -0,0 +1,104 @@
+//! A counting semaphore.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use super::wait_queue::WaitQueue;
+
+/// A counting semaphore.
+///
+/// It maintains a number of permits. [`acquire`](Self::acquire) blocks the
+/// current task until a permit is available and then takes it, and
+/// [`release`](Self::release) gives a permit back, waking up a blocked task
+/// if there is one.
+///
+/// If the `multitask` feature is not enabled, it spins instead of blocking.
+pub struct Semaphore {
+    wq: WaitQueue,
+    permits: AtomicUsize,
+}
+
+/// A guard that holds a permit of a [`Semaphore`].
+///
+/// When the guard falls out of scope it will release the permit.
+pub struct SemaphoreGuard<'a> {
+    sem: &'a Semaphore,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the initial number of permits.
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            permits: AtomicUsize::new(permits),
+        }
+    }
+
+    /// Returns the number of currently available permits.
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+
+    /// Acquires a permit, blocking the current task until one is available.
+    pub fn acquire(&self) {
+        while !self.try_acquire() {
+            self.wq.wait_until(|| self.available_permits() > 0);
+        }
+    }
+
+    /// Acquires a permit, blocking the current task until one is available
+    /// or the given duration has elapsed.
+    ///
+    /// Returns `true` if a permit is acquired, or `false` if timed out.
+    #[cfg(feature = "irq")]
+    pub fn acquire_timeout(&self, dur: Duration) -> bool {
+        let deadline = arceos_api::time::ax_wall_time() + dur;
+        while !self.try_acquire() {
+            let now = arceos_api::time::ax_wall_time();
+            if now >= deadline {
+                return false;
+            }
+            self.wq
+                .wait_timeout_until(deadline - now, || self.available_permits() > 0);
+        }
+        true
+    }
+
+    /// Tries to acquire a permit without blocking.
+    ///
+    /// Returns `true` if a permit is acquired.
+    pub fn try_acquire(&self) -> bool {
+        self.permits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |permits| {
+                permits.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Releases a permit, waking up a task waiting for it if there is one.
+    pub fn release(&self) {
+        self.permits.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+
+    /// Acquires a permit and returns a guard that releases it when dropped.
+    pub fn access(&self) -> SemaphoreGuard<'_> {
+        self.acquire();
+        SemaphoreGuard { sem: self }
+    }
+}
+
+impl core::fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.available_permits())
+            .finish()
+    }
+}
+
+impl Drop for SemaphoreGuard<'_> {
+    fn drop(&mut self) {
+        self.sem.release();
+    }
+}
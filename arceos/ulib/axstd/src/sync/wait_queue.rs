@@ -0,0 +1,70 @@
+//! The wait queue used by blocking primitives.
+//!
+//! If the `multitask` feature is enabled, it is built on the wait queue
+//! provided by [`arceos_api::task`]. Otherwise, there is no other thread to
+//! switch to, so waiting is done by spinning and notifying does nothing.
+
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+#[cfg(feature = "multitask")]
+use arceos_api::task::{self as api, AxWaitQueueHandle};
+
+pub(crate) struct WaitQueue {
+    #[cfg(feature = "multitask")]
+    inner: AxWaitQueueHandle,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            #[cfg(feature = "multitask")]
+            inner: AxWaitQueueHandle::new(),
+        }
+    }
+
+    pub fn wait_until<F>(&self, condition: F)
+    where
+        F: Fn() -> bool,
+    {
+        #[cfg(feature = "multitask")]
+        api::ax_wait_queue_wait(&self.inner, condition, None);
+        #[cfg(not(feature = "multitask"))]
+        while !condition() {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Returns `true` if timed out.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_until<F>(&self, dur: Duration, condition: F) -> bool
+    where
+        F: Fn() -> bool,
+    {
+        #[cfg(feature = "multitask")]
+        {
+            api::ax_wait_queue_wait(&self.inner, condition, Some(dur))
+        }
+        #[cfg(not(feature = "multitask"))]
+        {
+            let deadline = arceos_api::time::ax_wall_time() + dur;
+            while !condition() {
+                if arceos_api::time::ax_wall_time() >= deadline {
+                    return true;
+                }
+                core::hint::spin_loop();
+            }
+            false
+        }
+    }
+
+    pub fn notify_one(&self, _resched: bool) {
+        #[cfg(feature = "multitask")]
+        api::ax_wait_queue_wake(&self.inner, 1);
+    }
+
+    pub fn notify_all(&self, _resched: bool) {
+        #[cfg(feature = "multitask")]
+        api::ax_wait_queue_wake(&self.inner, u32::MAX);
+    }
+}
@@ -3,11 +3,17 @@
 extern crate alloc;
 
 use crate::io;
-use alloc::{string::String, sync::Arc};
-use core::{cell::UnsafeCell, num::NonZeroU64};
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::num::NonZeroU64;
 
 use arceos_api::task::{self as api, AxTaskHandle};
 use axerrno::ax_err_type;
+use kspin::SpinNoIrq;
+
+/// A set of CPUs that a thread is allowed to run on.
+pub use arceos_api::task::AxCpuMask as CpuMask;
+/// The state and runtime statistics of a thread, returned by [`snapshot`].
+pub use arceos_api::task::AxTaskSnapshot as ThreadSnapshot;
 
 /// A unique identifier for a running thread.
 #[derive(Eq, PartialEq, Clone, Copy, Debug)]
@@ -49,6 +55,8 @@ pub struct Builder {
     name: Option<String>,
     // The size of the stack for the spawned thread in bytes
     stack_size: Option<usize>,
+    // The CPUs that the spawned thread is allowed to run on
+    affinity: Option<CpuMask>,
 }
 
 impl Builder {
@@ -58,6 +66,7 @@ impl Builder {
         Builder {
             name: None,
             stack_size: None,
+            affinity: None,
         }
     }
 
@@ -73,6 +82,14 @@ impl Builder {
         self
     }
 
+    /// Sets the CPUs that the new thread is allowed to run on.
+    ///
+    /// By default, the thread can run on all CPUs.
+    pub fn affinity(mut self, cpumask: CpuMask) -> Builder {
+        self.affinity = Some(cpumask);
+        self
+    }
+
     /// Spawns a new thread by taking ownership of the `Builder`, and returns an
     /// [`io::Result`] to its [`JoinHandle`].
     ///
@@ -99,23 +116,22 @@ impl Builder {
         let stack_size = self
             .stack_size
             .unwrap_or(arceos_api::config::TASK_STACK_SIZE);
+        if self.affinity.is_some_and(|cpumask| cpumask.is_empty()) {
+            return Err(ax_err_type!(InvalidInput, "empty CPU affinity mask"));
+        }
 
         let my_packet = Arc::new(Packet {
-            result: UnsafeCell::new(None),
+            result: SpinNoIrq::new(None),
         });
         let their_packet = my_packet.clone();
 
         let main = move || {
             let ret = f();
-            // SAFETY: `their_packet` as been built just above and moved by the
-            // closure (it is an Arc<...>) and `my_packet` will be stored in the
-            // same `JoinHandle` as this closure meaning the mutation will be
-            // safe (not modify it and affect a value far away).
-            unsafe { *their_packet.result.get() = Some(ret) };
+            *their_packet.result.lock() = Some(ret);
             drop(their_packet);
         };
 
-        let task = api::ax_spawn(main, name, stack_size);
+        let task = api::ax_spawn(main, name, stack_size, self.affinity);
         Ok(JoinHandle {
             thread: Thread::from_id(task.id()),
             native: task,
@@ -130,6 +146,25 @@ pub fn current() -> Thread {
     Thread::from_id(id)
 }
 
+/// Sets the CPUs that the current thread is allowed to run on.
+///
+/// The thread is migrated to an allowed CPU immediately if it is running on a
+/// CPU that is not in `cpumask`.
+pub fn set_affinity(cpumask: CpuMask) -> io::Result<()> {
+    api::ax_set_current_affinity(cpumask)
+}
+
+/// Gets the CPUs that the current thread is allowed to run on.
+pub fn get_affinity() -> CpuMask {
+    api::ax_get_current_affinity()
+}
+
+/// Returns the states and runtime statistics of all live threads, including
+/// the ones created by the kernel, ordered by their IDs.
+pub fn snapshot() -> Vec<ThreadSnapshot> {
+    api::ax_task_snapshot()
+}
+
 /// Spawns a new thread, returning a [`JoinHandle`] for it.
 ///
 /// The join handle provides a [`join`] method that can be used to join the
@@ -147,12 +182,13 @@ where
     Builder::new().spawn(f).expect("failed to spawn thread")
 }
 
+// The result is shared with the thread: `their_packet` is never dropped if
+// the thread is killed, so it is taken under the lock rather than by unique
+// ownership.
 struct Packet<T> {
-    result: UnsafeCell<Option<T>>,
+    result: SpinNoIrq<Option<T>>,
 }
 
-unsafe impl<T> Sync for Packet<T> {}
-
 /// An owned permission to join on a thread (block on its termination).
 ///
 /// A `JoinHandle` *detaches* the associated thread when it is dropped, which
@@ -173,16 +209,36 @@ impl<T> JoinHandle<T> {
         &self.thread
     }
 
+    /// Sets the CPUs that the associated thread is allowed to run on.
+    ///
+    /// The thread is migrated to an allowed CPU if necessary.
+    pub fn set_affinity(&self, cpumask: CpuMask) -> io::Result<()> {
+        api::ax_set_task_affinity(&self.native, cpumask)
+    }
+
+    /// Gets the CPUs that the associated thread is allowed to run on.
+    pub fn affinity(&self) -> CpuMask {
+        api::ax_get_task_affinity(&self.native)
+    }
+
+    /// Kills the associated thread.
+    ///
+    /// The thread exits the next time it yields or sleeps, and is woken up if
+    /// it is sleeping. [`join`](JoinHandle::join) then returns an error as
+    /// the thread does not finish normally.
+    pub fn kill(&self) -> io::Result<()> {
+        api::ax_kill_task(&self.native, -1)
+    }
+
     /// Waits for the associated thread to finish.
     ///
     /// This function will return immediately if the associated thread has
     /// already finished.
-    pub fn join(mut self) -> io::Result<T> {
+    pub fn join(self) -> io::Result<T> {
         api::ax_wait_for_exit(self.native).ok_or_else(|| ax_err_type!(BadState))?;
-        Arc::get_mut(&mut self.packet)
-            .unwrap()
+        self.packet
             .result
-            .get_mut()
+            .lock()
             .take()
             .ok_or_else(|| ax_err_type!(BadState))
     }
@@ -95,3 +95,47 @@ impl Sub<Instant> for Instant {
         self.duration_since(other)
     }
 }
+
+/// A timer that calls a function once at an instant, or periodically.
+///
+/// The function runs in interrupt (softirq) context, so it must not block,
+/// and should be short. Timers work only if the feature `irq` is enabled,
+/// otherwise arming them returns an error.
+///
+/// The timer is cancelled when it is dropped.
+#[cfg(feature = "multitask")]
+pub struct Timer(arceos_api::task::AxTimerHandle);
+
+#[cfg(feature = "multitask")]
+impl Timer {
+    /// Creates a new disarmed timer that calls `f` when it expires.
+    pub fn new<F>(f: F) -> Timer
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        Timer(arceos_api::task::ax_timer_new(f))
+    }
+
+    /// Arms the timer to expire once at `instant`, replacing the previous
+    /// arming if any.
+    pub fn arm_at(&self, instant: Instant) -> crate::io::Result<()> {
+        arceos_api::task::ax_timer_arm(&self.0, instant.0, None)
+    }
+
+    /// Arms the timer to expire once after `dur`, replacing the previous
+    /// arming if any.
+    pub fn arm_after(&self, dur: Duration) -> crate::io::Result<()> {
+        self.arm_at(Instant::now() + dur)
+    }
+
+    /// Arms the timer to expire every `period`, starting one period from now,
+    /// replacing the previous arming if any.
+    pub fn arm_periodic(&self, period: Duration) -> crate::io::Result<()> {
+        arceos_api::task::ax_timer_arm(&self.0, AxTimeValue::ZERO, Some(period))
+    }
+
+    /// Cancels the timer, returns `false` if it was not armed.
+    pub fn cancel(&self) -> bool {
+        arceos_api::task::ax_timer_cancel(&self.0)
+    }
+}